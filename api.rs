@@ -0,0 +1,84 @@
+// The JSON forms of games, moves and scores that the WebSocket and HTTP
+// servers share, so a frontend sees the same shapes from either, and the
+// limits that every server, gRPC's too, holds a search to. Only the limits
+// are there in a build with just the `grpc` feature.
+
+use std::time::Duration;
+
+#[cfg(feature = "server")]
+use serde_json::{json, Value};
+
+#[cfg(feature = "server")]
+use crate::search::mate_in;
+use crate::search::SearchLimits;
+#[cfg(feature = "server")]
+use crate::{san, Color, Game, Move, ParseMoveError};
+
+pub const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+pub const MAX_MOVE_TIME: Duration = Duration::from_secs(30);
+pub const MAX_LINES: u64 = 16;
+
+// The search a request asks for with the limits it gives, if any, and
+// the number of lines it wants. It searches for `DEFAULT_MOVE_TIME` with no
+// limit at all and never for longer than `MAX_MOVE_TIME`, so that one
+// request can't take a server over, and it searches 1 to `MAX_LINES`
+// lines.
+pub fn search_limits(depth: Option<u64>, nodes: Option<u64>, move_time_ms: Option<u64>, multi_pv: Option<u64>) -> (SearchLimits, usize) {
+    let mut limits = SearchLimits {
+        depth: depth.map(|depth| u32::try_from(depth).unwrap_or(u32::MAX)),
+        nodes,
+        move_time: move_time_ms.map(Duration::from_millis),
+        ..SearchLimits::default()
+    };
+    if limits == SearchLimits::default() {
+        limits.move_time = Some(DEFAULT_MOVE_TIME);
+    }
+    limits.move_time = Some(limits.move_time.map_or(MAX_MOVE_TIME, |time| time.min(MAX_MOVE_TIME)));
+    (limits, multi_pv.unwrap_or(1).clamp(1, MAX_LINES) as usize)
+}
+
+// A game's position and history, with what can be played next:
+//
+//     {"game": 1, "fen": ..., "turn": "white", "moves": ["e2e4", ...],
+//      "legal_moves": ["e7e5", ...], "check": false,
+//      "outcome": null or {"result": "1-0", "reason": "Checkmate"}}
+#[cfg(feature = "server")]
+pub fn game(id: u64, game: &Game) -> Value {
+    let moves: Vec<String> = game.moves.iter().map(|mv| mv.to_string()).collect();
+    let legal: Vec<String> = game.legal_moves().iter().map(|mv| mv.to_string()).collect();
+    let outcome = game.outcome().map(|outcome| json!({"result": outcome.result.to_string(), "reason": format!("{:?}", outcome.reason)}));
+    json!({
+        "game": id,
+        "fen": game.to_fen(),
+        "turn": if game.turn == Color::White { "white" } else { "black" },
+        "moves": moves,
+        "legal_moves": legal,
+        "check": game.is_in_check(game.turn),
+        "outcome": outcome,
+    })
+}
+
+// {"uci": "g1f3", "san": "Nf3", "from": "g1", "to": "f3"}
+#[cfg(feature = "server")]
+pub fn legal_move(game: &Game, mv: Move) -> Value {
+    json!({"uci": mv.to_string(), "san": san::format(game, mv), "from": mv.from.to_string(), "to": mv.to.to_string()})
+}
+
+// {"cp": 31}, or {"mate": 3} for a mate in three, -3 for being mated.
+#[cfg(feature = "server")]
+pub fn score(score: i32) -> Value {
+    match mate_in(score) {
+        Some(moves) => json!({"mate": moves}),
+        None => json!({"cp": score}),
+    }
+}
+
+// A move in UCI coordinates or, failing that, SAN.
+#[cfg(feature = "server")]
+pub fn parse_move(game: &Game, text: &str) -> Result<Move, String> {
+    match game.parse_uci(text) {
+        Ok(mv) => Ok(mv),
+        Err(ParseMoveError::Invalid(_)) => san::parse(game, text).map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
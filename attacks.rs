@@ -0,0 +1,46 @@
+// Attack sets for every piece type, shared by move generation and
+// evaluation. Knight, king and pawn attacks don't depend on other pieces and
+// are tabulated at compile time; sliders come from the magic tables.
+
+use crate::bitboard::Bitboard;
+use crate::{Color, Square, KING_OFFSETS, KNIGHT_OFFSETS};
+
+pub use crate::magic::{bishop_attacks, queen_attacks, rook_attacks};
+
+const KNIGHT: [Bitboard; 64] = leaper_table(&KNIGHT_OFFSETS);
+const KING: [Bitboard; 64] = leaper_table(&KING_OFFSETS);
+// Indexed by the pawn's color; each pawn attacks diagonally forward.
+const PAWN: [[Bitboard; 64]; 2] = [leaper_table(&[(-1, 1), (1, 1)]), leaper_table(&[(-1, -1), (1, -1)])];
+
+pub fn knight_attacks(square: Square) -> Bitboard {
+    KNIGHT[square.0 as usize]
+}
+
+pub fn king_attacks(square: Square) -> Bitboard {
+    KING[square.0 as usize]
+}
+
+// The squares a `color` pawn on `square` attacks.
+pub fn pawn_attacks(color: Color, square: Square) -> Bitboard {
+    PAWN[color.index()][square.0 as usize]
+}
+
+const fn leaper_table(offsets: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [Bitboard::EMPTY; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut targets = 0u64;
+        let mut i = 0;
+        while i < offsets.len() {
+            let file = (square % 8) as i8 + offsets[i].0;
+            let rank = (square / 8) as i8 + offsets[i].1;
+            if 0 <= file && file < 8 && 0 <= rank && rank < 8 {
+                targets |= 1 << (rank * 8 + file);
+            }
+            i += 1;
+        }
+        table[square] = Bitboard(targets);
+        square += 1;
+    }
+    table
+}
@@ -0,0 +1,315 @@
+// A compact binary encoding of positions and games, for storing many
+// games in far less space than PGN.
+//
+// A position is the occupied squares as a 64-bit mask, then a 4-bit code
+// for the piece on each occupied square from a1 up, two to a byte; a byte
+// of flags (Black to move, the four castling rights, en passant); the en
+// passant square if there is one; and the two move counters as varints
+// (LEB128). The start position takes 27 bytes.
+//
+// A game is a byte saying whether it starts from the usual position (0) or
+// the position that follows (1), the number of moves as a varint, and then
+// each move as its index among the legal moves, ordered by their packed
+// form, in just enough bits to tell them apart: usually 5 or 6, and none
+// at all for a forced move. The bits run together and are padded to a
+// whole byte at the end.
+//
+// Both are self-delimiting, so records can simply be written one after
+// another; the decoders take them off the front of a slice.
+
+use std::fmt;
+
+use crate::packed::PackedMove;
+use crate::{CastlingRights, ChessPiece, Color, Game, Move, Piece, PositionBuilder, PositionError, Square};
+
+const BLACK_TO_MOVE: u8 = 1;
+const WHITE_KINGSIDE: u8 = 2;
+const WHITE_QUEENSIDE: u8 = 4;
+const BLACK_KINGSIDE: u8 = 8;
+const BLACK_QUEENSIDE: u8 = 16;
+const EN_PASSANT: u8 = 32;
+
+const STANDARD_START: u8 = 0;
+const SET_UP: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // The bytes ran out in the middle of a record.
+    Truncated,
+    // A byte or code no encoder writes.
+    Invalid(&'static str),
+    Position(PositionError),
+    // The move at this index, from 0, isn't one of the legal moves.
+    Move(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "record cut short"),
+            DecodeError::Invalid(what) => write!(f, "invalid {}", what),
+            DecodeError::Position(err) => write!(f, "{}", err),
+            DecodeError::Move(index) => write!(f, "move {} is not legal", index + 1),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<PositionError> for DecodeError {
+    fn from(err: PositionError) -> DecodeError {
+        DecodeError::Position(err)
+    }
+}
+
+// The position in `game`, without its history.
+pub fn encode_position(game: &Game) -> Vec<u8> {
+    let mut bytes = vec![];
+    write_position(&mut bytes, game);
+    bytes
+}
+
+// A position off the front of `bytes`, which are moved past it.
+pub fn decode_position(bytes: &mut &[u8]) -> Result<Game, DecodeError> {
+    let occupied = u64::from_le_bytes(take(bytes, 8)?.try_into().expect("eight bytes"));
+    let codes = take(bytes, (occupied.count_ones() as usize).div_ceil(2))?;
+    let mut builder = PositionBuilder::new();
+    let squares = (0..64).filter(|&i| occupied & 1 << i != 0).map(Square);
+    for (i, square) in squares.enumerate() {
+        let code = codes[i / 2] >> (i % 2 * 4) & 0xf;
+        let color = if code < 6 { Color::White } else { Color::Black };
+        let piece = *Piece::ALL.get(code as usize % 6).filter(|_| code < 12).ok_or(DecodeError::Invalid("piece code"))?;
+        builder = builder.piece(square, ChessPiece { piece, color });
+    }
+
+    let flags = take(bytes, 1)?[0];
+    if flags >= EN_PASSANT << 1 {
+        return Err(DecodeError::Invalid("flags"));
+    }
+    builder = builder.turn(if flags & BLACK_TO_MOVE != 0 { Color::Black } else { Color::White }).castling(CastlingRights {
+        white_kingside: flags & WHITE_KINGSIDE != 0,
+        white_queenside: flags & WHITE_QUEENSIDE != 0,
+        black_kingside: flags & BLACK_KINGSIDE != 0,
+        black_queenside: flags & BLACK_QUEENSIDE != 0,
+    });
+    if flags & EN_PASSANT != 0 {
+        let square = take(bytes, 1)?[0];
+        if square >= 64 {
+            return Err(DecodeError::Invalid("en passant square"));
+        }
+        builder = builder.en_passant(Square(square));
+    }
+    let halfmove_clock = read_varint(bytes)?;
+    let fullmove_number = read_varint(bytes)?;
+    Ok(builder.halfmove_clock(halfmove_clock).fullmove_number(fullmove_number).build()?)
+}
+
+// The position `game` started from and every move played since.
+pub fn encode_game(game: &Game) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut replay = Game::from_fen(&game.start).expect("a game's start is a valid FEN");
+    if game.start == Game::new().start {
+        bytes.push(STANDARD_START);
+    } else {
+        bytes.push(SET_UP);
+        write_position(&mut bytes, &replay);
+    }
+    write_varint(&mut bytes, game.moves.len() as u32);
+
+    let mut bits = BitWriter::default();
+    for &mv in &game.moves {
+        let legal = ordered_moves(&replay);
+        let index = legal.iter().position(|&other| other == mv).expect("the game's moves were legal when played");
+        bits.write(index as u32, width(legal.len()));
+        replay.play_move(mv);
+    }
+    bytes.extend(bits.finish());
+    bytes
+}
+
+// A game off the front of `bytes`, which are moved past it, with its
+// moves replayed.
+pub fn decode_game(bytes: &mut &[u8]) -> Result<Game, DecodeError> {
+    let mut game = match take(bytes, 1)?[0] {
+        STANDARD_START => Game::new(),
+        SET_UP => decode_position(bytes)?,
+        _ => return Err(DecodeError::Invalid("start marker")),
+    };
+    let count = read_varint(bytes)? as usize;
+    let rest: &[u8] = bytes;
+    let mut bits = BitReader { bytes: rest, used: 0 };
+    for i in 0..count {
+        let legal = ordered_moves(&game);
+        let index = bits.read(width(legal.len()))?;
+        let mv = *legal.get(index as usize).ok_or(DecodeError::Move(i))?;
+        game.play_move(mv);
+    }
+    *bytes = &rest[bits.used.div_ceil(8)..];
+    Ok(game)
+}
+
+fn write_position(bytes: &mut Vec<u8>, game: &Game) {
+    let occupied = game.bitboards.occupied();
+    bytes.extend(occupied.0.to_le_bytes());
+    let codes: Vec<u8> = occupied
+        .squares()
+        .map(|square| {
+            let piece = game.piece_at(square).expect("an occupied square has a piece");
+            piece.piece.index() as u8 + if piece.color == Color::Black { 6 } else { 0 }
+        })
+        .collect();
+    bytes.extend(codes.chunks(2).map(|pair| pair[0] | pair.get(1).map_or(0, |code| code << 4)));
+
+    let castling = game.castling;
+    let flags = [
+        (game.turn == Color::Black, BLACK_TO_MOVE),
+        (castling.white_kingside, WHITE_KINGSIDE),
+        (castling.white_queenside, WHITE_QUEENSIDE),
+        (castling.black_kingside, BLACK_KINGSIDE),
+        (castling.black_queenside, BLACK_QUEENSIDE),
+        (game.en_passant.is_some(), EN_PASSANT),
+    ];
+    bytes.push(flags.iter().filter(|(set, _)| *set).fold(0, |flags, (_, flag)| flags | flag));
+    if let Some(square) = game.en_passant {
+        bytes.push(square.0);
+    }
+    write_varint(bytes, game.halfmove_clock);
+    write_varint(bytes, game.fullmove_number);
+}
+
+// The legal moves in an order that doesn't depend on how they were
+// generated, so that old records still decode after the move generator
+// changes.
+fn ordered_moves(game: &Game) -> Vec<Move> {
+    let mut moves: Vec<Move> = game.legal_moves().iter().copied().collect();
+    moves.sort_by_key(|&mv| PackedMove::from(mv).bits());
+    moves
+}
+
+// Bits enough for an index into `count` moves.
+fn width(count: usize) -> u32 {
+    usize::BITS - count.saturating_sub(1).leading_zeros()
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < count {
+        return Err(DecodeError::Truncated);
+    }
+    let (taken, rest) = bytes.split_at(count);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u32, DecodeError> {
+    let mut value = 0u32;
+    for shift in [0, 7, 14, 21, 28] {
+        let byte = take(bytes, 1)?[0];
+        // The fifth byte has room for only the top four bits.
+        if shift == 28 && byte > 0x0f {
+            break;
+        }
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Invalid("varint"))
+}
+
+// Writes values of a few bits each, lowest bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    used: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, width: u32) {
+        for bit in 0..width {
+            if self.used.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= ((value >> bit & 1) as u8) << (self.used % 8);
+            self.used += 1;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    used: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, width: u32) -> Result<u32, DecodeError> {
+        let mut value = 0;
+        for bit in 0..width {
+            let byte = self.bytes.get(self.used / 8).ok_or(DecodeError::Truncated)?;
+            value |= ((byte >> (self.used % 8) & 1) as u32) << bit;
+            self.used += 1;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for uci in moves {
+            let mv = game.parse_uci(uci).expect("a legal move");
+            game.play_move(mv);
+        }
+    }
+
+    #[test]
+    fn position_round_trip() {
+        let start = Game::new();
+        let bytes = encode_position(&start);
+        assert_eq!(bytes.len(), 27);
+        let mut rest = bytes.as_slice();
+        assert_eq!(decode_position(&mut rest).unwrap().to_fen(), start.to_fen());
+        assert!(rest.is_empty());
+
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 0 12";
+        let bytes = encode_position(&Game::from_fen(fen).unwrap());
+        assert_eq!(decode_position(&mut bytes.as_slice()).unwrap().to_fen(), fen);
+    }
+
+    #[test]
+    fn game_round_trip() {
+        let mut game = Game::new();
+        play(&mut game, &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "g8f6", "e1g1", "f8e7"]);
+        let bytes = encode_game(&game);
+        assert_eq!(bytes.len(), 9);
+
+        // Records follow one another, and a set-up start comes back too.
+        let mut set_up = Game::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        play(&mut set_up, &["a7a8q", "e8d7"]);
+        let mut packed = bytes.clone();
+        packed.extend(encode_game(&set_up));
+        let mut rest = packed.as_slice();
+        for original in [&game, &set_up] {
+            let decoded = decode_game(&mut rest).unwrap();
+            assert_eq!(decoded.start, original.start);
+            assert_eq!(decoded.moves, original.moves);
+            assert_eq!(decoded.to_fen(), original.to_fen());
+        }
+        assert!(rest.is_empty());
+        assert_eq!(decode_game(&mut &bytes[..8]).err(), Some(DecodeError::Truncated));
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+use crate::{Board, ChessPiece, Color, File, Piece, Rank, Square};
+
+// A set of squares, one bit per square with a1 as the lowest bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Bitboard(pub u64);
+
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const LIGHT_SQUARES: Bitboard = Bitboard(0x55aa_55aa_55aa_55aa);
+
+    pub const fn from_square(square: Square) -> Bitboard {
+        Bitboard(1 << square.0)
+    }
+
+    pub const fn file(file: File) -> Bitboard {
+        Bitboard(0x0101_0101_0101_0101 << file.0)
+    }
+
+    pub const fn rank(rank: Rank) -> Bitboard {
+        Bitboard(0xff << (rank.0 * 8))
+    }
+
+    pub fn contains(self, square: Square) -> bool {
+        self.0 & (1 << square.0) != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    // The lowest square in the set.
+    pub fn first(self) -> Option<Square> {
+        (!self.is_empty()).then(|| Square(self.0.trailing_zeros() as u8))
+    }
+
+    pub fn squares(self) -> Squares {
+        Squares(self.0)
+    }
+}
+
+// Iterates a bitboard's squares from a1 upwards.
+pub(crate) struct Squares(u64);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let square = Square(self.0.trailing_zeros() as u8);
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+
+    fn bitand(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+
+    fn bitor(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 | rhs.0)
+    }
+}
+
+impl BitXor for Bitboard {
+    type Output = Bitboard;
+
+    fn bitxor(self, rhs: Bitboard) -> Bitboard {
+        Bitboard(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Bitboard) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Bitboard) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Bitboard) {
+        self.0 ^= rhs.0;
+    }
+}
+
+// The position as one bitboard per piece type plus one per color; the
+// squares of, say, the white knights are `pieces(Knight, White)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Bitboards {
+    by_piece: [Bitboard; 6],
+    by_color: [Bitboard; 2],
+}
+
+impl Bitboards {
+    pub fn from_mailbox(board: &Board) -> Bitboards {
+        let mut bitboards = Bitboards::default();
+        for square in Square::all() {
+            bitboards.set(square, board[square.rank().0 as usize][square.file().0 as usize]);
+        }
+        bitboards
+    }
+
+    // The board[rank][file] array view, as used for display.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn to_mailbox(self) -> Board {
+        let mut board: Board = [[None; 8]; 8];
+        for square in self.occupied().squares() {
+            board[square.rank().0 as usize][square.file().0 as usize] = self.piece_at(square);
+        }
+        board
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<ChessPiece> {
+        let color = if self.by_color[Color::White.index()].contains(square) {
+            Color::White
+        } else if self.by_color[Color::Black.index()].contains(square) {
+            Color::Black
+        } else {
+            return None;
+        };
+        Piece::ALL
+            .into_iter()
+            .find(|piece| self.by_piece[piece.index()].contains(square))
+            .map(|piece| ChessPiece { piece, color })
+    }
+
+    pub fn set(&mut self, square: Square, piece: Option<ChessPiece>) {
+        let bit = Bitboard::from_square(square);
+        for bitboard in self.by_piece.iter_mut().chain(self.by_color.iter_mut()) {
+            *bitboard &= !bit;
+        }
+        if let Some(ChessPiece { piece, color }) = piece {
+            self.by_piece[piece.index()] |= bit;
+            self.by_color[color.index()] |= bit;
+        }
+    }
+
+    pub fn pieces(&self, piece: Piece, color: Color) -> Bitboard {
+        self.by_piece[piece.index()] & self.by_color[color.index()]
+    }
+
+    // Pieces of one type regardless of color.
+    pub fn piece_type(&self, piece: Piece) -> Bitboard {
+        self.by_piece[piece.index()]
+    }
+
+    pub fn color(&self, color: Color) -> Bitboard {
+        self.by_color[color.index()]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn mailbox_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/8/8/8/8/k6K w - - 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            let board = game.bitboards.to_mailbox();
+            assert!(board == game.board, "{}", fen);
+            assert_eq!(Bitboards::from_mailbox(&board), game.bitboards, "{}", fen);
+        }
+    }
+}
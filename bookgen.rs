@@ -0,0 +1,97 @@
+// Building an opening book from games: every move played in the first
+// plies of each game is counted with the game's result, as a win, draw or
+// loss for the side that played it, and each move played often enough
+// goes into a Polyglot book (see polyglot.rs).
+//
+// A move's weight is what it scored, two for a win and one for a draw,
+// as in Polyglot's own book maker, so the book plays the moves that did
+// best most often. Moves that never scored are left out, and weights are
+// scaled down together if the largest would overflow. Games without a
+// result say nothing about their moves and are skipped.
+
+use std::collections::HashMap;
+
+use crate::pgn::PgnGame;
+use crate::polyglot::{self, Entry};
+use crate::{Game, GameResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookSettings {
+    // Plies from the start of each game to take moves from.
+    pub depth: u32,
+    // Games a move has to have been played in to go into the book.
+    pub min_games: u32,
+}
+
+impl Default for BookSettings {
+    fn default() -> BookSettings {
+        BookSettings { depth: 20, min_games: 3 }
+    }
+}
+
+// How a move did, for the side that played it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MoveStats {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // In half points.
+    pub fn score(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    settings: BookSettings,
+    // By Polyglot key and encoded move.
+    moves: HashMap<(u64, u16), MoveStats>,
+    pub games: usize,
+    // Games passed over for having no result.
+    pub unfinished: usize,
+}
+
+impl BookBuilder {
+    pub fn new(settings: BookSettings) -> BookBuilder {
+        BookBuilder { settings, ..BookBuilder::default() }
+    }
+
+    pub fn add(&mut self, record: &PgnGame) {
+        let Some(result) = record.result else {
+            self.unfinished += 1;
+            return;
+        };
+        self.games += 1;
+        let mut game = Game::from_fen(&record.game.start).expect("the game was read from this position");
+        for &mv in record.game.moves.iter().take(self.settings.depth as usize) {
+            let stats = self.moves.entry((polyglot::key(&game), polyglot::encode(mv))).or_default();
+            match (result, game.turn) {
+                (GameResult::Draw, _) => stats.draws += 1,
+                (result, turn) if result == GameResult::win_for(turn) => stats.wins += 1,
+                _ => stats.losses += 1,
+            }
+            game.play_move(mv);
+        }
+    }
+
+    // The book's entries, in no order.
+    pub fn entries(&self) -> Vec<Entry> {
+        let kept: Vec<(u64, u16, u64)> = self
+            .moves
+            .iter()
+            .filter(|(_, stats)| stats.games() >= self.settings.min_games && stats.score() > 0)
+            .map(|(&(key, mv), stats)| (key, mv, stats.score()))
+            .collect();
+        let top = kept.iter().map(|&(_, _, score)| score).max().unwrap_or(0);
+        let scale = top.div_ceil(u16::MAX as u64).max(1);
+        // A score scaled to nothing still keeps the move in play.
+        kept.into_iter().map(|(key, mv, score)| Entry { key, mv, weight: (score / scale).max(1) as u16, learn: 0 }).collect()
+    }
+}
@@ -0,0 +1,7 @@
+// Generates the gRPC service from proto/analysis.proto for the `grpc`
+// feature; see grpc.rs.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/analysis.proto").expect("proto/analysis.proto compiles");
+}
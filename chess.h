@@ -0,0 +1,55 @@
+/* C API of the ChessInRust engine, from a build with the `ffi` feature as
+ * a `cdylib`. See ffi.rs for the Rust side. */
+
+#ifndef CHESS_IN_RUST_H
+#define CHESS_IN_RUST_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CHESS_ONGOING 0
+#define CHESS_WHITE_WINS 1
+#define CHESS_BLACK_WINS 2
+#define CHESS_DRAW 3
+
+/* A game and the engine that plays it. Use one from one thread at a time. */
+typedef struct ChessGame ChessGame;
+
+/* A game from the usual starting position. */
+ChessGame *chess_game_new(void);
+
+/* A game from a FEN, or NULL if it isn't a valid position. */
+ChessGame *chess_game_from_fen(const char *fen);
+
+void chess_game_free(ChessGame *game);
+
+/* The position as FEN. Free it with chess_string_free. */
+char *chess_game_fen(const ChessGame *game);
+
+/* The legal moves in UCI coordinates, separated by spaces. Free it with
+ * chess_string_free. */
+char *chess_game_legal_moves(const ChessGame *game);
+
+/* Plays a move in UCI coordinates ("e2e4", "e7e8q") or SAN: 0 if it was
+ * played, -1 if it isn't legal. */
+int chess_game_make_move(ChessGame *game, const char *move);
+
+/* The engine's move in UCI coordinates, searching for movetime_ms
+ * milliseconds or to depth plies, whichever comes first; 0 leaves either
+ * unlimited, and with both 0 it thinks for a second. NULL once the game is
+ * over. Free it with chess_string_free. */
+char *chess_game_best_move(ChessGame *game, uint32_t movetime_ms, uint32_t depth);
+
+/* CHESS_ONGOING, or CHESS_WHITE_WINS, CHESS_BLACK_WINS or CHESS_DRAW. */
+int chess_game_result(const ChessGame *game);
+
+void chess_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
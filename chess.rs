@@ -1,6 +1,75 @@
-use std::time::Instant;
+// Built as a library for the bindings, main and the command line behind
+// it go unused.
+#![cfg_attr(any(feature = "ffi", feature = "python", feature = "wasm"), allow(dead_code))]
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(any(feature = "server", feature = "grpc"))]
+mod api;
+mod attacks;
+mod binary;
+mod bitboard;
+mod bookgen;
+#[cfg(feature = "dgt")]
+mod dgt;
+mod endgame;
+mod epd;
+mod eval;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "server")]
+mod http;
+#[cfg(feature = "online")]
+mod lichess_bot;
+mod magic;
+mod matchplay;
+mod movelist;
+mod netplay;
+mod nnue;
+mod notation;
+#[cfg(feature = "online")]
+mod online;
+mod options;
+mod packed;
+mod pawns;
+mod perft;
+mod pgn;
+mod polyglot;
+#[cfg(feature = "python")]
+mod python;
+mod san;
+mod search;
+mod svg;
+#[cfg(feature = "syzygy")]
+mod syzygy;
+mod timeman;
+mod tt;
+mod tune;
+mod uci;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "server")]
+mod websocket;
+mod zobrist;
+
+use bitboard::{Bitboard, Bitboards};
+use movelist::MoveList;
+use nnue::{Accumulator, Network};
+use options::EngineOptions;
+use packed::PackedMove;
+use pawns::PawnTable;
+use search::{SearchLimits, Searcher, Tablebase};
+use timeman::TimeControl;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum Piece {
     Pawn,
     Rook,
@@ -10,23 +79,529 @@ enum Piece {
     King,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum Color {
     White,
     Black,
 }
 
-#[derive(Clone, Copy)]
+impl Piece {
+    const ALL: [Piece; 6] = [Piece::Pawn, Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen, Piece::King];
+    // Cheapest first.
+    const BY_VALUE: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Color {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 struct ChessPiece {
     piece: Piece,
     color: Color,
 }
 
+impl ChessPiece {
+    // The letter FEN uses: upper case for White, lower case for Black.
+    fn to_char(self) -> char {
+        let c = match self.piece {
+            Piece::Pawn => 'p',
+            Piece::Rook => 'r',
+            Piece::Knight => 'n',
+            Piece::Bishop => 'b',
+            Piece::Queen => 'q',
+            Piece::King => 'k',
+        };
+        match self.color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        }
+    }
+
+    // The Unicode chess symbol, hollow for White and solid for Black.
+    fn to_symbol(self) -> char {
+        match (self.color, self.piece) {
+            (Color::White, Piece::Pawn) => '♙',
+            (Color::White, Piece::Rook) => '♖',
+            (Color::White, Piece::Knight) => '♘',
+            (Color::White, Piece::Bishop) => '♗',
+            (Color::White, Piece::Queen) => '♕',
+            (Color::White, Piece::King) => '♔',
+            (Color::Black, Piece::Pawn) => '♟',
+            (Color::Black, Piece::Rook) => '♜',
+            (Color::Black, Piece::Knight) => '♞',
+            (Color::Black, Piece::Bishop) => '♝',
+            (Color::Black, Piece::Queen) => '♛',
+            (Color::Black, Piece::King) => '♚',
+        }
+    }
+}
+
+// Files a to h are 0 to 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct File(u8);
+
+// Ranks 1 to 8 are 0 to 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Rank(u8);
+
+// Squares are numbered rank by rank from a1 = 0 to h8 = 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Square(u8);
+
+impl File {
+    fn from_char(c: char) -> Option<File> {
+        ('a'..='h').contains(&c).then(|| File(c as u8 - b'a'))
+    }
+}
+
+impl Rank {
+    fn from_char(c: char) -> Option<Rank> {
+        ('1'..='8').contains(&c).then(|| Rank(c as u8 - b'1'))
+    }
+}
+
+impl Square {
+    const fn new(file: File, rank: Rank) -> Square {
+        Square(rank.0 * 8 + file.0)
+    }
+
+    fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+
+    // The square `files` to the right and `ranks` up from White's point of
+    // view, if that is still on the board.
+    fn offset(self, files: i8, ranks: i8) -> Option<Square> {
+        let file = self.file().0 as i8 + files;
+        let rank = self.rank().0 as i8 + ranks;
+        if (0..8).contains(&file) && (0..8).contains(&rank) {
+            Some(Square::new(File(file as u8), Rank(rank as u8)))
+        } else {
+            None
+        }
+    }
+
+    fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    // How many king moves apart two squares are.
+    fn distance(self, other: Square) -> u32 {
+        let files = self.file().0.abs_diff(other.file().0);
+        let ranks = self.rank().0.abs_diff(other.rank().0);
+        files.max(ranks) as u32
+    }
+
+    // The squares strictly between two squares on a shared rank, file or
+    // diagonal; empty if they don't share one or are adjacent.
+    fn squares_between(self, other: Square) -> Vec<Square> {
+        let files = other.file().0 as i8 - self.file().0 as i8;
+        let ranks = other.rank().0 as i8 - self.rank().0 as i8;
+        if !(files == 0 || ranks == 0 || files.abs() == ranks.abs()) {
+            return vec![];
+        }
+
+        let step = (files.signum(), ranks.signum());
+        let mut squares = vec![];
+        let mut next = self.offset(step.0, step.1);
+        while let Some(square) = next.filter(|&square| square != other) {
+            squares.push(square);
+            next = square.offset(step.0, step.1);
+        }
+        squares
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", (b'a' + self.0) as char)
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParseSquareError(String);
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid square {:?}, expected a file and a rank such as \"e4\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    // Algebraic notation: "e4" is the e-file on the fourth rank.
+    fn from_str(s: &str) -> Result<Square, ParseSquareError> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(File::from_char), chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(file), Some(rank), None) => Ok(Square::new(file, rank)),
+            _ => Err(ParseSquareError(s.to_string())),
+        }
+    }
+}
+
+// Squares go over the wire by name, "e4", so that one off the board is
+// turned away on the way in.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Square {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Square {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Square, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum MoveKind {
+    Normal,
+    // A pawn advancing two squares from its starting rank.
+    DoublePush,
+    // A pawn capturing a double-pushed pawn on the square it skipped.
+    EnPassant,
+    // The king moving two squares towards a rook, which jumps over it.
+    Castle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Move {
+    from: Square,
+    to: Square,
+    piece: Piece,
+    captured: Option<Piece>,
+    // Piece a pawn turns into on reaching the last rank.
+    promotion: Option<Piece>,
+    kind: MoveKind,
+}
+
+impl Move {
+    fn new(piece: Piece, from: Square, to: Square, captured: Option<Piece>) -> Self {
+        Move { from, to, piece, captured, promotion: None, kind: MoveKind::Normal }
+    }
+
+    fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    fn pack(self) -> PackedMove {
+        PackedMove::from(self)
+    }
+}
+
+// Coordinate notation, as UCI uses and human players type it: "e2e4",
+// "e7e8q", and "e1g1" for castling; see `Game::parse_uci`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        match self.promotion {
+            Some(Piece::Queen) => write!(f, "q"),
+            Some(Piece::Rook) => write!(f, "r"),
+            Some(Piece::Bishop) => write!(f, "b"),
+            Some(Piece::Knight) => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Why a move in coordinate notation can't be played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseMoveError {
+    // Not two squares and perhaps a promotion piece.
+    Invalid(String),
+    Illegal(String),
+    // A pawn reaching the last rank without a piece to promote to.
+    MissingPromotion(String),
+}
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMoveError::Invalid(s) => write!(f, "invalid move {:?}, expected from and to squares such as \"e2e4\"", s),
+            ParseMoveError::Illegal(s) => write!(f, "illegal move {}", s),
+            ParseMoveError::MissingPromotion(s) => write!(f, "{} promotes, but to which piece?", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseMoveError {}
+
+// What `make_move` can't work out again from the move itself, so that
+// `unmake_move` can restore the position exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Undo {
+    mv: Move,
+    castling: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    fn win_for(color: Color) -> GameResult {
+        match color {
+            Color::White => GameResult::WhiteWins,
+            Color::Black => GameResult::BlackWins,
+        }
+    }
+}
+
+// Score notation as used in PGN.
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum TerminationReason {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    Resignation,
+    DrawAgreement,
+    // The overall time limit given to `play` ran out.
+    Timeout,
+    // The move limit given to `play` was reached.
+    MoveLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct GameOutcome {
+    result: GameResult,
+    reason: TerminationReason,
+}
+
+impl GameOutcome {
+    fn draw(reason: TerminationReason) -> GameOutcome {
+        GameOutcome { result: GameResult::Draw, reason }
+    }
+}
+
+// What a player chooses to do on their turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(Move),
+    OfferDraw,
+    Resign,
+}
+
+// How `Game::render` draws the board. The default is plain letters, as
+// in FEN, with dots for empty squares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct BoardStyle {
+    // Chess symbols rather than letters.
+    unicode: bool,
+    // Rank numbers down the left and file letters along the bottom.
+    coordinates: bool,
+    // A frame around the squares.
+    border: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CastlingRights {
+    white_kingside: bool,
+    white_queenside: bool,
+    black_kingside: bool,
+    black_queenside: bool,
+}
+
+impl CastlingRights {
+    const ALL: CastlingRights = CastlingRights {
+        white_kingside: true,
+        white_queenside: true,
+        black_kingside: true,
+        black_queenside: true,
+    };
+
+    const NONE: CastlingRights = CastlingRights {
+        white_kingside: false,
+        white_queenside: false,
+        black_kingside: false,
+        black_queenside: false,
+    };
+
+    // Moving the king or a rook, or having a rook captured on its home
+    // square, gives up the matching castling rights for good.
+    fn remove_for(&mut self, square: Square) {
+        match (square.file().0, square.rank().0) {
+            (4, 0) => {
+                self.white_kingside = false;
+                self.white_queenside = false;
+            }
+            (7, 0) => self.white_kingside = false,
+            (0, 0) => self.white_queenside = false,
+            (4, 7) => {
+                self.black_kingside = false;
+                self.black_queenside = false;
+            }
+            (7, 7) => self.black_kingside = false,
+            (0, 7) => self.black_queenside = false,
+            _ => {}
+        }
+    }
+}
+
+fn opponent(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [(2, 1), (2, -1), (-2, 1), (-2, -1), (1, 2), (1, -2), (-1, 2), (-1, -2)];
+// Orthogonal steps first, then diagonal ones, so the two halves double as
+// rook and bishop directions.
+const KING_OFFSETS: [(i8, i8); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// How the machine players choose their moves.
+#[derive(Debug, Clone)]
+struct AiSettings {
+    // Plies to search ahead at most.
+    depth: u32,
+    // Longest to think about one move, without a clock.
+    move_time: Option<Duration>,
+    // Threads, hash size, contempt and skill; see options.rs.
+    options: EngineOptions,
+    // Centipawns a move may score below the best and still be played, and
+    // the seed that picks among such moves.
+    variety: i32,
+    seed: u64,
+    // Starting time and increment for each side's clock.
+    clock: Option<(Duration, Duration)>,
+    // The hand-crafted evaluation's weights.
+    evaluation: Arc<eval::Parameters>,
+}
+
+// Indexed as board[rank][file], so board[0] is White's back rank.
 type Board = [[Option<ChessPiece>; 8]; 8];
 
+#[derive(Clone)]
 struct Game {
     board: Board,
+    // The same position as piece and color sets, kept in step with `board`.
+    bitboards: Bitboards,
+    // Zobrist hash of the position, updated as moves are made.
+    hash: u64,
+    // The same for the pawns alone, keying the pawn structure cache.
+    pawn_hash: u64,
     turn: Color,
+    // Square a pawn skipped over with a double push on the previous move.
+    en_passant: Option<Square>,
+    castling: CastlingRights,
+    // Halfmoves since the last pawn move or capture, for the fifty-move rule.
+    halfmove_clock: u32,
+    // Starts at 1 and goes up after each Black move, as in FEN and PGN.
+    fullmove_number: u32,
+    // Position keys of every position reached so far, oldest first.
+    history: Vec<u64>,
+    // The position the game started from, as FEN, and the moves played
+    // since with `play_move`, for writing the game out as PGN.
+    start: String,
+    moves: Vec<Move>,
+    // Side with a draw offer on the table, until it is accepted or declined.
+    draw_offer: Option<Color>,
+    // An ending the board alone can't show: a resignation or agreed draw.
+    declared_outcome: Option<GameOutcome>,
+    // The NNUE hidden layer, kept up to date as moves are made, when a
+    // network evaluates the position instead of the hand-crafted terms.
+    nnue: Option<Accumulator>,
+}
+
+// A game goes over the wire as where it started and the moves since,
+// rather than field by field, so a reader can't be handed a board that
+// disagrees with its own hashes and history. Reading it back replays the
+// moves, checking each is legal. An NNUE network isn't kept: call
+// `set_network` again after reading.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GameState {
+    start: String,
+    moves: Vec<Move>,
+    #[serde(default)]
+    draw_offer: Option<Color>,
+    #[serde(default)]
+    declared_outcome: Option<GameOutcome>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Game {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let state = GameState {
+            start: self.start.clone(),
+            moves: self.moves.clone(),
+            draw_offer: self.draw_offer,
+            declared_outcome: self.declared_outcome,
+        };
+        state.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Game {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Game, D::Error> {
+        use serde::de::Error;
+
+        let state = GameState::deserialize(deserializer)?;
+        let mut game = Game::from_fen(&state.start).map_err(D::Error::custom)?;
+        for mv in state.moves {
+            if !game.legal_moves().contains(&mv) {
+                return Err(D::Error::custom(format!("illegal move {}", mv)));
+            }
+            game.play_move(mv);
+        }
+        game.draw_offer = state.draw_offer;
+        game.declared_outcome = state.declared_outcome;
+        Ok(game)
+    }
 }
 
 impl Game {
@@ -34,10 +609,8 @@ impl Game {
         let mut board: Board = [[None; 8]; 8];
 
         // Place pawns
-        for i in 0..8 {
-            board[1][i] = Some(ChessPiece { piece: Piece::Pawn, color: Color::Black });
-            board[6][i] = Some(ChessPiece { piece: Piece::Pawn, color: Color::White });
-        }
+        board[1] = [Some(ChessPiece { piece: Piece::Pawn, color: Color::White }); 8];
+        board[6] = [Some(ChessPiece { piece: Piece::Pawn, color: Color::Black }); 8];
 
         // Place other pieces
         let back_rank = [
@@ -51,147 +624,2385 @@ impl Game {
             Piece::Rook,
         ];
 
-        for (i, &piece) in back_rank.iter().enumerate() {
-            board[0][i] = Some(ChessPiece { piece, color: Color::Black });
-            board[7][i] = Some(ChessPiece { piece, color: Color::White });
+        for (file, &piece) in back_rank.iter().enumerate() {
+            board[0][file] = Some(ChessPiece { piece, color: Color::White });
+            board[7][file] = Some(ChessPiece { piece, color: Color::Black });
         }
 
-        Game {
+        let mut game = Game {
             board,
+            bitboards: Bitboards::from_mailbox(&board),
+            hash: 0,
+            pawn_hash: 0,
             turn: Color::White,
+            en_passant: None,
+            castling: CastlingRights::ALL,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: vec![],
+            start: String::new(),
+            moves: vec![],
+            draw_offer: None,
+            declared_outcome: None,
+            nnue: None,
+        };
+        game.hash = game.compute_hash();
+        game.pawn_hash = game.compute_pawn_hash();
+        game.history.push(game.position_key());
+        game.start = game.to_fen();
+        game
+    }
+
+    // Reads a position in Forsyth-Edwards Notation: the placement, side to
+    // move, castling and en passant fields, then the halfmove clock and
+    // fullmove number, which may be left off together or just the last.
+    fn from_fen(fen: &str) -> Result<Game, FenError> {
+        let (builder, issues) = read_fen(fen);
+        match issues.into_iter().next() {
+            Some(issue) => Err(issue.error),
+            None => Ok(builder.build()?),
         }
     }
 
-    fn display(&self) {
-        for row in &self.board {
-            for square in row {
-                match square {
-                    Some(ChessPiece { piece, color }) => {
-                        let symbol = match (piece, color) {
-                            (Piece::Pawn, Color::White) => "P",
-                            (Piece::Pawn, Color::Black) => "p",
-                            (Piece::Rook, Color::White) => "R",
-                            (Piece::Rook, Color::Black) => "r",
-                            (Piece::Knight, Color::White) => "N",
-                            (Piece::Knight, Color::Black) => "n",
-                            (Piece::Bishop, Color::White) => "B",
-                            (Piece::Bishop, Color::Black) => "b",
-                            (Piece::Queen, Color::White) => "Q",
-                            (Piece::Queen, Color::Black) => "q",
-                            (Piece::King, Color::White) => "K",
-                            (Piece::King, Color::Black) => "k",
-                        };
-                        print!("{} ", symbol);
-                    }
-                    None => print!(". "),
-                }
-            }
-            println!();
+    // Checks that the position could occur in a game, as far as can be told
+    // without its history.
+    fn validate(&self) -> Result<(), PositionError> {
+        match self.position_errors().into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
-        println!();
     }
 
-    fn get_ai_move(&self) -> Option<((usize, usize), (usize, usize))> {
-        let mut moves = vec![];
+    // Everything `validate` objects to, in the order it checks.
+    fn position_errors(&self) -> Vec<PositionError> {
+        let mut errors = vec![];
+        for color in [Color::White, Color::Black] {
+            let king = ChessPiece { piece: Piece::King, color };
+            match Square::all().filter(|&square| self.piece_at(square) == Some(king)).count() {
+                0 => errors.push(PositionError::MissingKing(color)),
+                1 => {}
+                _ => errors.push(PositionError::TooManyKings(color)),
+            }
+        }
+        let kings_found = errors.is_empty();
 
-        for i in 0..8 {
-            for j in 0..8 {
-                if let Some(piece) = self.board[i][j] {
-                    if piece.color == self.turn {
-                        // Generate basic moves based on piece type
-                        let directions = match piece.piece {
-                            Piece::Pawn => vec![(1, 0), (-1, 0)],
-                            Piece::Rook => vec![(1, 0), (-1, 0), (0, 1), (0, -1)],
-                            Piece::Bishop => vec![(1, 1), (1, -1), (-1, 1), (-1, -1)],
-                            Piece::Queen => vec![(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)],
-                            Piece::King => vec![(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)],
-                            Piece::Knight => vec![(2, 1), (2, -1), (-2, 1), (-2, -1), (1, 2), (1, -2), (-1, 2), (-1, -2)],
-                        };
+        errors.extend(
+            Square::all()
+                .filter(|&square| {
+                    matches!(self.piece_at(square), Some(ChessPiece { piece: Piece::Pawn, .. }))
+                        && (square.rank() == Rank(0) || square.rank() == Rank(7))
+                })
+                .map(PositionError::PawnOnBackRank),
+        );
 
-                        for &(di, dj) in &directions {
-                            let ni = i as isize + di;
-                            let nj = j as isize + dj;
+        // Check needs a king on each side to mean anything.
+        if kings_found && self.is_in_check(opponent(self.turn)) {
+            errors.push(PositionError::OpponentInCheck);
+        }
 
-                            if ni >= 0 && ni < 8 && nj >= 0 && nj < 8 {
-                                let ni = ni as usize;
-                                let nj = nj as usize;
-                                if self.board[ni][nj].is_none() || self.board[ni][nj].unwrap().color != piece.color {
-                                    moves.push(((i, j), (ni, nj)));
-                                }
-                            }
-                        }
-                    }
-                }
+        let holds = |file: u8, rank: u8, piece: Piece, color: Color| {
+            self.piece_at(Square::new(File(file), Rank(rank))) == Some(ChessPiece { piece, color })
+        };
+        let rights = [
+            (self.castling.white_kingside, 'K', Color::White, 0, 7),
+            (self.castling.white_queenside, 'Q', Color::White, 0, 0),
+            (self.castling.black_kingside, 'k', Color::Black, 7, 7),
+            (self.castling.black_queenside, 'q', Color::Black, 7, 0),
+        ];
+        for (allowed, letter, color, rank, rook_file) in rights {
+            if allowed && !(holds(4, rank, Piece::King, color) && holds(rook_file, rank, Piece::Rook, color)) {
+                errors.push(PositionError::ImpossibleCastlingRights(letter));
             }
         }
 
-        if moves.is_empty() {
-            None
-        } else {
-            // Select the first valid move for simplicity (basic AI)
-            Some(moves[0])
+        // The pawn that just double-pushed stands in front of the skipped
+        // square, with the square behind it (its starting square) now empty.
+        if let Some(square) = self.en_passant {
+            let (skipped_rank, forward) = match self.turn {
+                Color::White => (Rank(5), -1),
+                Color::Black => (Rank(2), 1),
+            };
+            let pushed = square.offset(0, forward);
+            let start = square.offset(0, -forward);
+            let plausible = square.rank() == skipped_rank
+                && self.piece_at(square).is_none()
+                && start.is_some_and(|start| self.piece_at(start).is_none())
+                && pushed.and_then(|pushed| self.piece_at(pushed))
+                    == Some(ChessPiece { piece: Piece::Pawn, color: opponent(self.turn) });
+            if !plausible {
+                errors.push(PositionError::ImpossibleEnPassant(square));
+            }
+        }
+
+        errors
+    }
+
+    fn piece_at(&self, square: Square) -> Option<ChessPiece> {
+        self.board[square.rank().0 as usize][square.file().0 as usize]
+    }
+
+    fn set_piece(&mut self, square: Square, piece: Option<ChessPiece>) {
+        for piece in self.piece_at(square).iter().chain(piece.iter()) {
+            self.hash ^= zobrist::piece(*piece, square);
+            if piece.piece == Piece::Pawn {
+                self.pawn_hash ^= zobrist::piece(*piece, square);
+            }
+        }
+        if let Some(nnue) = &mut self.nnue {
+            if let Some(old) = self.board[square.rank().0 as usize][square.file().0 as usize] {
+                nnue.remove(old, square);
+            }
+            if let Some(new) = piece {
+                nnue.add(new, square);
+            }
         }
+        self.board[square.rank().0 as usize][square.file().0 as usize] = piece;
+        self.bitboards.set(square, piece);
     }
 
-    fn make_move(&mut self, mv: ((usize, usize), (usize, usize))) {
-        let ((from_x, from_y), (to_x, to_y)) = mv;
-        self.board[to_x][to_y] = self.board[from_x][from_y];
-        self.board[from_x][from_y] = None;
+    // Evaluates with `network` from now on, or with the hand-crafted
+    // evaluation again for None.
+    fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue = network.map(|network| {
+            let mut nnue = Accumulator::new(network);
+            for square in self.bitboards.occupied().squares() {
+                nnue.add(self.piece_at(square).unwrap(), square);
+            }
+            nnue
+        });
     }
 
-    fn switch_turn(&mut self) {
-        self.turn = match self.turn {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
+    // The position in Forsyth-Edwards Notation, as `from_fen` reads it.
+    fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    // The same in Shredder-FEN, which names the castling rooks' files
+    // ("HAha") rather than their sides, as Chess960 software expects.
+    fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
+        let ranks: Vec<String> = self
+            .board
+            .iter()
+            .rev()
+            .map(|row| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for square in row {
+                    match square {
+                        Some(piece) => {
+                            if empty > 0 {
+                                rank.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            rank.push(piece.to_char());
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect();
+
+        let turn = match self.turn {
+            Color::White => "w",
+            Color::Black => "b",
         };
+        let rights = [
+            (self.castling.white_kingside, 'K', 'H'),
+            (self.castling.white_queenside, 'Q', 'A'),
+            (self.castling.black_kingside, 'k', 'h'),
+            (self.castling.black_queenside, 'q', 'a'),
+        ];
+        let mut castling: String =
+            rights.iter().filter(|(right, ..)| *right).map(|&(_, side, file)| if shredder { file } else { side }).collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = self.en_passant.map_or("-".to_string(), |square| square.to_string());
+        format!("{} {} {} {} {} {}", ranks.join("/"), turn, castling, en_passant, self.halfmove_clock, self.fullmove_number)
     }
 
-    fn is_checkmate(&self) -> bool {
-        // Basic checkmate detection placeholder (can be expanded)
-        !self.get_ai_move().is_some()
+    // The board drawn as text, White at the bottom, one rank per line.
+    fn render(&self, style: BoardStyle) -> String {
+        let mut text = String::new();
+        let edge = format!("{}+{}+\n", if style.coordinates { "  " } else { "" }, "-".repeat(17));
+        if style.border {
+            text.push_str(&edge);
+        }
+        for (rank, row) in self.board.iter().enumerate().rev() {
+            if style.coordinates {
+                text.push_str(&format!("{} ", rank + 1));
+            }
+            if style.border {
+                text.push_str("| ");
+            }
+            let squares: Vec<String> = row
+                .iter()
+                .map(|square| match (square, style.unicode) {
+                    (Some(piece), false) => piece.to_char().to_string(),
+                    (Some(piece), true) => piece.to_symbol().to_string(),
+                    (None, false) => ".".to_string(),
+                    (None, true) => "·".to_string(),
+                })
+                .collect();
+            text.push_str(&squares.join(" "));
+            if style.border {
+                text.push_str(" |");
+            }
+            text.push('\n');
+        }
+        if style.border {
+            text.push_str(&edge);
+        }
+        if style.coordinates {
+            let files: Vec<String> = (0..8).map(|file| File(file).to_string()).collect();
+            text.push_str(&format!("{}{}\n", if style.border { "    " } else { "  " }, files.join(" ")));
+        }
+        text
     }
 
-    fn play(&mut self, game_limit: u64, move_limit: usize) {
-        let start_time = Instant::now();
-        let mut move_count = 0;
+    // Every move the side to move's pieces can make, without checking whether
+    // it leaves their own king in check. Cheap enough for use inside a search,
+    // which must then reject moves that fail `is_legal`.
+    fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        let occupied = self.bitboards.occupied();
+        let own = self.bitboards.color(self.turn);
 
-        loop {
-            if start_time.elapsed().as_secs() >= game_limit {
-                println!("Game over! Time limit of {} seconds reached.", game_limit);
-                break;
+        for piece in Piece::ALL {
+            let mover = ChessPiece { piece, color: self.turn };
+            for from in self.bitboards.pieces(piece, self.turn).squares() {
+                let targets = match piece {
+                    Piece::Pawn => {
+                        self.pawn_moves(mover, from, &mut moves);
+                        continue;
+                    }
+                    Piece::Rook => attacks::rook_attacks(from, occupied),
+                    Piece::Bishop => attacks::bishop_attacks(from, occupied),
+                    Piece::Queen => attacks::queen_attacks(from, occupied),
+                    Piece::King => attacks::king_attacks(from),
+                    Piece::Knight => attacks::knight_attacks(from),
+                };
+
+                for to in (targets & !own).squares() {
+                    push_move(&mut moves, mover, from, to, self.piece_at(to).map(|p| p.piece));
+                }
             }
+        }
 
-            if move_count >= move_limit {
-                println!("Game over! Move limit of {} moves reached.", move_limit);
-                break;
+        // En passant: a pawn beside the pawn that just double-pushed may
+        // take it by moving onto the square it skipped over.
+        if let Some(target) = self.en_passant {
+            let behind = match self.turn {
+                Color::White => -1,
+                Color::Black => 1,
+            };
+            for files in [-1, 1] {
+                if let Some(from) = target.offset(files, behind) {
+                    if self.piece_at(from) == Some(ChessPiece { piece: Piece::Pawn, color: self.turn }) {
+                        moves.push(Move { kind: MoveKind::EnPassant, ..Move::new(Piece::Pawn, from, target, Some(Piece::Pawn)) });
+                    }
+                }
             }
+        }
 
-            self.display();
+        self.castling_moves(&mut moves);
 
-            if self.is_checkmate() {
-                println!("Checkmate! {:?} wins!", match self.turn {
-                    Color::White => Color::Black,
-                    Color::Black => Color::White,
-                });
-                break;
-            }
+        moves
+    }
 
-            if let Some(mv) = self.get_ai_move() {
-                self.make_move(mv);
-                self.switch_turn();
-                move_count += 1;
-            } else {
-                println!("Game over! No more moves for {:?}", self.turn);
-                break;
+    // Castling needs the right to still be held, nothing standing between
+    // king and rook, and the king neither in check nor passing over an
+    // attacked square. Landing in check is left to the legality filter.
+    fn castling_moves(&self, moves: &mut MoveList) {
+        let (rank, kingside, queenside) = match self.turn {
+            Color::White => (Rank(0), self.castling.white_kingside, self.castling.white_queenside),
+            Color::Black => (Rank(7), self.castling.black_kingside, self.castling.black_queenside),
+        };
+        let enemy = opponent(self.turn);
+        let on_rank = |file: u8| Square::new(File(file), rank);
+        let king = on_rank(4);
+
+        if !(kingside || queenside) || self.is_square_attacked(king, enemy) {
+            return;
+        }
+
+        let empty = |files: &[u8]| files.iter().all(|&file| self.piece_at(on_rank(file)).is_none());
+        let castle = |to_file: u8| Move { kind: MoveKind::Castle, ..Move::new(Piece::King, king, on_rank(to_file), None) };
+
+        if kingside && empty(&[5, 6]) && !self.is_square_attacked(on_rank(5), enemy) {
+            moves.push(castle(6));
+        }
+        if queenside && empty(&[1, 2, 3]) && !self.is_square_attacked(on_rank(3), enemy) {
+            moves.push(castle(2));
+        }
+    }
+
+    // Pawns advance towards the opponent onto empty squares only, two
+    // squares from their starting rank, and capture diagonally. En passant
+    // is handled separately in `pseudo_legal_moves`.
+    fn pawn_moves(&self, pawn: ChessPiece, from: Square, moves: &mut MoveList) {
+        let (forward, start_rank) = match pawn.color {
+            Color::White => (1, Rank(1)),
+            Color::Black => (-1, Rank(6)),
+        };
+
+        let ahead = match from.offset(0, forward) {
+            Some(ahead) => ahead,
+            None => return,
+        };
+
+        if self.piece_at(ahead).is_none() {
+            push_move(moves, pawn, from, ahead, None);
+
+            if from.rank() == start_rank {
+                if let Some(two_ahead) = ahead.offset(0, forward).filter(|&sq| self.piece_at(sq).is_none()) {
+                    moves.push(Move { kind: MoveKind::DoublePush, ..Move::new(Piece::Pawn, from, two_ahead, None) });
+                }
             }
         }
+
+        let captures = attacks::pawn_attacks(pawn.color, from) & self.bitboards.color(opponent(pawn.color));
+        for to in captures.squares() {
+            push_move(moves, pawn, from, to, self.piece_at(to).map(|p| p.piece));
+        }
     }
-}
 
-fn main() {
-    let mut game = Game::new();
-    let game_limit = 300; // 5 minutes in seconds
-    let move_limit = 40;  // 20 moves per side
-    game.play(game_limit, move_limit);
-}
+    // Moves for the side to move that don't leave its own king in check,
+    // as a GUI or a human player needs them.
+    fn legal_moves(&self) -> MoveList {
+        let mut moves = if self.is_in_check(self.turn) { self.evasion_moves() } else { self.pseudo_legal_moves() };
+        let mut scratch = self.clone();
+        moves.retain(|&mv| scratch.is_legal(mv));
+        moves
+    }
+
+    // The only pseudo-legal moves that can answer a check: moving the king,
+    // capturing the checking piece, or blocking the line it checks along.
+    // Against a double check only king moves remain. Pinned blockers and
+    // king steps along the checking ray still need `is_legal`.
+    fn evasion_moves(&self) -> MoveList {
+        let mut moves = self.pseudo_legal_moves();
+        let king = match self.king_square(self.turn) {
+            Some(king) => king,
+            None => return moves,
+        };
+        let checkers = self.attackers_to(king, opponent(self.turn));
 
+        let checker = match checkers.as_slice() {
+            [] => return moves,
+            [checker] => *checker,
+            _ => {
+                moves.retain(|mv| mv.piece == Piece::King);
+                return moves;
+            }
+        };
+        let blocks = king.squares_between(checker);
+
+        moves.retain(|mv| {
+            mv.piece == Piece::King
+                || mv.to == checker
+                || blocks.contains(&mv.to)
+                // Taking a checking pawn en passant lands behind it.
+                || (mv.kind == MoveKind::EnPassant && Square::new(mv.to.file(), mv.from.rank()) == checker)
+        });
+        moves
+    }
+
+    // Whether a pseudo-legal move keeps the mover's king out of check. The
+    // move is tried and taken back, which takes care of pins, king moves
+    // onto attacked squares and ignored checks alike.
+    fn is_legal(&mut self, mv: Move) -> bool {
+        let mover = self.turn;
+        let undo = self.make_move(mv);
+        let legal = !self.is_in_check(mover);
+        self.unmake_move(undo);
+        legal
+    }
+
+    // The move the search thinks best. With a clock the time manager
+    // decides how long to think, and `ai.move_time` caps it either way.
+    fn get_ai_move(&self, ai: &AiSettings, clock: Option<TimeControl>) -> Option<Move> {
+        let mut searcher = ai.options.searcher();
+        if ai.variety > 0 {
+            searcher.variety = ai.variety;
+            searcher.seed = ai.seed;
+        }
+        searcher.set_evaluation(Arc::clone(&ai.evaluation));
+        let depth = ai.options.max_depth().map_or(ai.depth, |max_depth| ai.depth.min(max_depth));
+        let limits = SearchLimits { depth: Some(depth), move_time: ai.move_time, clock, ..SearchLimits::default() };
+        searcher.search(self, &limits).best_move
+    }
+
+    // Reads a move such as "e2e4" or, in SAN, "e4", or "draw" or "resign",
+    // from stdin, re-prompting until it is legal. Promotions may carry the
+    // piece ("e7e8n"); otherwise the player is asked for it. Returns None once
+    // stdin is closed. "eval" prints the evaluation with `params`, and "fen"
+    // the position as FEN.
+    fn read_human_action(&self, params: &eval::Parameters) -> Option<Action> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            print!("{:?} to move: ", self.turn);
+            io::stdout().flush().ok();
+            let line = lines.next()?.ok()?;
+            let input = line.trim();
+
+            match input {
+                "draw" => return Some(Action::OfferDraw),
+                "resign" => return Some(Action::Resign),
+                "eval" => {
+                    self.print_eval(params);
+                    continue;
+                }
+                "fen" => {
+                    println!("{}", self.to_fen());
+                    continue;
+                }
+                _ => {}
+            }
+
+            match self.parse_uci(input) {
+                Ok(mv) => return Some(Action::Move(mv)),
+                Err(ParseMoveError::Illegal(_)) => println!("Illegal move: {}", input),
+                Err(ParseMoveError::MissingPromotion(_)) => {
+                    let mut choice = None;
+                    while choice.is_none() {
+                        print!("Promote to (q/r/b/n): ");
+                        io::stdout().flush().ok();
+                        let answer = lines.next()?.ok()?;
+                        choice = parse_promotion(answer.trim()).and_then(|_| self.parse_uci(&format!("{}{}", input, answer.trim())).ok());
+                    }
+                    return choice.map(Action::Move);
+                }
+                Err(ParseMoveError::Invalid(_)) => match san::parse(self, input) {
+                    Ok(mv) => return Some(Action::Move(mv)),
+                    Err(san::SanError::Invalid(_)) => println!("Enter moves as from and to squares, e.g. g1f3, or in SAN, e.g. Nf3."),
+                    Err(err) => println!("{}", err),
+                },
+            }
+        }
+    }
+
+    // The legal move `s` gives in the coordinate notation UCI uses: the
+    // from and to squares, then the piece a pawn promotes to ("e7e8q").
+    // Castling is the king's move, "e1g1". The promotion piece may be
+    // upper case, as some GUIs send it.
+    fn parse_uci(&self, s: &str) -> Result<Move, ParseMoveError> {
+        let invalid = || ParseMoveError::Invalid(s.to_string());
+        let square = |range| s.get(range).and_then(|s: &str| s.parse::<Square>().ok());
+        let (Some(from), Some(to)) = (square(0..2), square(2..4)) else {
+            return Err(invalid());
+        };
+        let promotion = match s.get(4..) {
+            Some("") | None => None,
+            Some(piece) => Some(parse_promotion(piece).ok_or_else(invalid)?),
+        };
+
+        let candidates: Vec<Move> = self.legal_moves().iter().copied().filter(|mv| mv.from == from && mv.to == to).collect();
+        match candidates.iter().find(|mv| mv.promotion == promotion) {
+            Some(&mv) => Ok(mv),
+            // Only promotions share from and to squares.
+            None if promotion.is_none() && !candidates.is_empty() => Err(ParseMoveError::MissingPromotion(s.to_string())),
+            None => Err(ParseMoveError::Illegal(s.to_string())),
+        }
+    }
+
+    // `mv` in SAN after its move number, as "12. Nf3" for White and
+    // "12... Nf6" for Black.
+    fn numbered_san(&self, mv: Move) -> String {
+        match self.turn {
+            Color::White => format!("{}. {}", self.fullmove_number, san::format(self, mv)),
+            Color::Black => format!("{}... {}", self.fullmove_number, san::format(self, mv)),
+        }
+    }
+
+    // Prints the hand-crafted evaluation of the position term by term.
+    fn print_eval(&self, params: &eval::Parameters) {
+        println!("{}", eval::Trace::new(self, params));
+        if self.nnue.is_some() {
+            println!("NNUE: {:+} for White", self.evaluate_absolute());
+        }
+    }
+
+    // The static evaluation in centipawns from `color`'s side, positive
+    // when `color` stands better whoever is to move. `evaluate_for(turn)`
+    // is the score the search works with.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn evaluate_for(&self, color: Color) -> i32 {
+        eval::for_side(color, self.evaluate_absolute())
+    }
+
+    // The static evaluation in centipawns, positive when White stands
+    // better whoever is to move, as for an evaluation bar. The pawn
+    // structure is worked out afresh rather than cached.
+    fn evaluate_absolute(&self) -> i32 {
+        eval::absolute_with(self, &eval::Parameters::DEFAULT, &mut PawnTable::new(0))
+    }
+
+    fn king_square(&self, color: Color) -> Option<Square> {
+        self.bitboards.pieces(Piece::King, color).first()
+    }
+
+    // Whether `color`'s king is attacked by any enemy piece.
+    fn is_in_check(&self, color: Color) -> bool {
+        match self.king_square(color) {
+            Some(square) => self.is_square_attacked(square, opponent(color)),
+            None => false,
+        }
+    }
+
+    // The squares of all pieces of `by` that attack `square`, whether or
+    // not they could legally move there.
+    fn attackers_to(&self, square: Square, by: Color) -> Vec<Square> {
+        self.attackers(square, by).squares().collect()
+    }
+
+    fn is_square_attacked(&self, square: Square, by: Color) -> bool {
+        !self.attackers(square, by).is_empty()
+    }
+
+    // Attackers are found by looking outwards from the target with each
+    // piece's own attack pattern: a knight on the target would attack the
+    // enemy knights attacking it, a rook there the enemy rooks, and so on.
+    // Pawns are the exception, attacking only forwards, so the target is
+    // looked at as if it held a pawn of the other color.
+    fn attackers(&self, square: Square, by: Color) -> Bitboard {
+        self.attackers_through(square, by, self.bitboards.occupied())
+    }
+
+    // Static exchange evaluation: the material the side to move can expect
+    // to win (or, if negative, lose) by playing `mv` and letting both sides
+    // recapture on its target square for as long as that pays, always with
+    // their least valuable piece. Pieces behind a capturer on the same line
+    // join in once it has gone.
+    fn see(&self, mv: Move) -> i32 {
+        let value = |piece: Piece| if piece == Piece::King { 20_000 } else { eval::value(piece) };
+        let target = mv.to;
+        let mut occupied = self.bitboards.occupied() & !Bitboard::from_square(mv.from);
+        if mv.kind == MoveKind::EnPassant {
+            occupied &= !Bitboard::from_square(Square::new(mv.to.file(), mv.from.rank()));
+        }
+
+        // gains[i]: what the side making the ith capture has gained, if
+        // the exchange stops right after it. There are at most 32 pieces to
+        // capture with.
+        let mut gains = [0; 32];
+        gains[0] = mv.captured.map_or(0, eval::value) + mv.promotion.map_or(0, |piece| eval::value(piece) - eval::value(Piece::Pawn));
+        let mut captures = 1;
+        let mut on_target = value(mv.promotion.unwrap_or(mv.piece));
+        let mut side = opponent(self.turn);
+
+        loop {
+            let attackers = |color| self.attackers_through(target, color, occupied) & occupied;
+            let ours = attackers(side);
+            let Some((piece, from)) = Piece::BY_VALUE
+                .into_iter()
+                .find_map(|piece| (ours & self.bitboards.pieces(piece, side)).first().map(|from| (piece, from)))
+            else {
+                break;
+            };
+            // The king may only recapture once nothing defends the square.
+            if piece == Piece::King && !attackers(opponent(side)).is_empty() {
+                break;
+            }
+
+            gains[captures] = on_target - gains[captures - 1];
+            captures += 1;
+            on_target = value(piece);
+            occupied &= !Bitboard::from_square(from);
+            side = opponent(side);
+        }
+
+        // Either side may decline to continue the exchange when going on
+        // would cost it.
+        for i in (1..captures).rev() {
+            gains[i - 1] = -(-gains[i - 1]).max(gains[i]);
+        }
+        gains[0]
+    }
+
+    // Like `attackers`, but with the board as if only `occupied` were
+    // occupied, so sliders see through pieces already taken off.
+    fn attackers_through(&self, square: Square, by: Color, occupied: Bitboard) -> Bitboard {
+        let pieces = |piece| self.bitboards.pieces(piece, by);
+        let queens = pieces(Piece::Queen);
+
+        (attacks::pawn_attacks(opponent(by), square) & pieces(Piece::Pawn))
+            | (attacks::knight_attacks(square) & pieces(Piece::Knight))
+            | (attacks::king_attacks(square) & pieces(Piece::King))
+            | (attacks::rook_attacks(square, occupied) & (pieces(Piece::Rook) | queens))
+            | (attacks::bishop_attacks(square, occupied) & (pieces(Piece::Bishop) | queens))
+    }
+
+    // Plays a pseudo-legal move on the board and hands the turn over.
+    fn make_move(&mut self, mv: Move) -> Undo {
+        let undo = Undo {
+            mv,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.hash ^= self.en_passant_key() ^ zobrist::castling(self.castling);
+
+        if mv.piece == Piece::Pawn || mv.is_capture() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.turn == Color::Black {
+            self.fullmove_number += 1;
+        }
+
+        match mv.kind {
+            // The captured pawn stands beside the capturing pawn, not on the
+            // (empty) square it lands on.
+            MoveKind::EnPassant => self.set_piece(Square::new(mv.to.file(), mv.from.rank()), None),
+            // The rook jumps to the square the king passed over.
+            MoveKind::Castle => {
+                let (rook_from, rook_to) = castling_rook_squares(mv.to);
+                let rook = self.piece_at(rook_from);
+                self.set_piece(rook_to, rook);
+                self.set_piece(rook_from, None);
+            }
+            MoveKind::Normal | MoveKind::DoublePush => {}
+        }
+
+        let moving = self.piece_at(mv.from);
+        self.set_piece(mv.to, match (mv.promotion, moving) {
+            (Some(piece), Some(pawn)) => Some(ChessPiece { piece, color: pawn.color }),
+            (_, moving) => moving,
+        });
+        self.set_piece(mv.from, None);
+
+        self.en_passant = if mv.kind == MoveKind::DoublePush {
+            Some(Square::new(mv.from.file(), Rank((mv.from.rank().0 + mv.to.rank().0) / 2)))
+        } else {
+            None
+        };
+
+        self.castling.remove_for(mv.from);
+        self.castling.remove_for(mv.to);
+
+        self.switch_turn();
+        self.hash ^= self.en_passant_key() ^ zobrist::castling(self.castling);
+        undo
+    }
+
+    // Takes back the move `make_move` returned `undo` for, which must be the
+    // last move made.
+    fn unmake_move(&mut self, undo: Undo) {
+        let mv = undo.mv;
+        self.turn = opponent(self.turn);
+        if self.turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        let enemy = opponent(self.turn);
+        self.set_piece(mv.from, Some(ChessPiece { piece: mv.piece, color: self.turn }));
+        match mv.kind {
+            MoveKind::EnPassant => {
+                self.set_piece(mv.to, None);
+                self.set_piece(Square::new(mv.to.file(), mv.from.rank()), Some(ChessPiece { piece: Piece::Pawn, color: enemy }));
+            }
+            MoveKind::Castle => {
+                let (rook_from, rook_to) = castling_rook_squares(mv.to);
+                let rook = self.piece_at(rook_to);
+                self.set_piece(rook_from, rook);
+                self.set_piece(rook_to, None);
+                self.set_piece(mv.to, None);
+            }
+            MoveKind::Normal | MoveKind::DoublePush => {
+                self.set_piece(mv.to, mv.captured.map(|piece| ChessPiece { piece, color: enemy }));
+            }
+        }
+
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+    }
+
+    // Plays a move for the side to move and records the resulting position.
+    // Moving instead of answering the opponent's draw offer declines it.
+    fn play_move(&mut self, mv: Move) {
+        if self.draw_offer == Some(opponent(self.turn)) {
+            self.draw_offer = None;
+        }
+        self.make_move(mv);
+        self.history.push(self.position_key());
+        self.moves.push(mv);
+    }
+
+    fn switch_turn(&mut self) {
+        self.turn = opponent(self.turn);
+        self.hash ^= zobrist::side_to_move(Color::Black);
+    }
+
+    // The Zobrist hash of the position worked out from scratch, rather than
+    // kept up to date as in `hash`.
+    fn compute_hash(&self) -> u64 {
+        let pieces = Square::all()
+            .filter_map(|square| self.piece_at(square).map(|piece| zobrist::piece(piece, square)))
+            .fold(0, |hash, key| hash ^ key);
+        pieces ^ zobrist::side_to_move(self.turn) ^ zobrist::castling(self.castling) ^ self.en_passant_key()
+    }
+
+    fn compute_pawn_hash(&self) -> u64 {
+        [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(|color| {
+                let pawn = ChessPiece { piece: Piece::Pawn, color };
+                self.bitboards.pieces(Piece::Pawn, color).squares().map(move |square| zobrist::piece(pawn, square))
+            })
+            .fold(0, |hash, key| hash ^ key)
+    }
+
+    // The en passant file only goes into the hash when a pawn of the side to
+    // move stands ready to make the capture, so that a double push nobody
+    // can take doesn't make an otherwise identical position look new.
+    fn en_passant_key(&self) -> u64 {
+        match self.en_passant {
+            Some(square) if !(attacks::pawn_attacks(opponent(self.turn), square) & self.bitboards.pieces(Piece::Pawn, self.turn)).is_empty() => {
+                zobrist::en_passant(square.file())
+            }
+            _ => 0,
+        }
+    }
+
+    // Identifies a position for repetition purposes: the same pieces on the
+    // same squares, the same side to move, the same castling rights and the
+    // same en passant possibilities.
+    fn position_key(&self) -> u64 {
+        // A pawn that could in theory be taken en passant doesn't make the
+        // position different unless the capture is actually legal.
+        let en_passant = self.en_passant_key();
+        if en_passant != 0 && !self.legal_moves().iter().any(|mv| mv.kind == MoveKind::EnPassant) {
+            self.hash ^ en_passant
+        } else {
+            self.hash
+        }
+    }
+
+    // Neither side can possibly mate: bare kings, a single minor piece, or
+    // only bishops that all stand on squares of one color.
+    fn is_insufficient_material(&self) -> bool {
+        let pieces = &self.bitboards;
+        let heavy = pieces.piece_type(Piece::Pawn) | pieces.piece_type(Piece::Rook) | pieces.piece_type(Piece::Queen);
+        if !heavy.is_empty() {
+            return false;
+        }
+
+        let bishops = pieces.piece_type(Piece::Bishop);
+        match pieces.piece_type(Piece::Knight).count() {
+            0 => (bishops & Bitboard::LIGHT_SQUARES).is_empty() || (bishops & !Bitboard::LIGHT_SQUARES).is_empty(),
+            1 => bishops.is_empty(),
+            _ => false,
+        }
+    }
+
+    // Fifty moves by each side without a pawn move or a capture.
+    fn is_fifty_move_rule(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    // The current position has occurred at least three times.
+    fn is_threefold_repetition(&self) -> bool {
+        match self.history.last() {
+            Some(current) => self.history.iter().filter(|&key| key == current).count() >= 3,
+            None => false,
+        }
+    }
+
+    // The side to move is checkmated when it is in check and no legal move
+    // gets it out.
+    fn is_checkmate(&self) -> bool {
+        self.is_in_check(self.turn) && self.legal_moves().is_empty()
+    }
+
+    // Having no legal move without being in check is a draw, not a loss.
+    fn is_stalemate(&self) -> bool {
+        !self.is_in_check(self.turn) && self.legal_moves().is_empty()
+    }
+
+    fn resign(&mut self, color: Color) {
+        self.declared_outcome = Some(GameOutcome {
+            result: GameResult::win_for(opponent(color)),
+            reason: TerminationReason::Resignation,
+        });
+    }
+
+    // The offer stands until the opponent accepts it, declines it, or moves.
+    fn offer_draw(&mut self, color: Color) {
+        self.draw_offer = Some(color);
+    }
+
+    // Accepts the opponent's draw offer, if `color` has one to accept.
+    fn accept_draw(&mut self, color: Color) -> bool {
+        if self.draw_offer != Some(opponent(color)) {
+            return false;
+        }
+        self.draw_offer = None;
+        self.declared_outcome = Some(GameOutcome::draw(TerminationReason::DrawAgreement));
+        true
+    }
+
+    fn decline_draw(&mut self) {
+        self.draw_offer = None;
+    }
+
+    // Material in pawns, using the usual 1/3/3/5/9 piece values.
+    fn material(&self, color: Color) -> u32 {
+        [(Piece::Pawn, 1), (Piece::Knight, 3), (Piece::Bishop, 3), (Piece::Rook, 5), (Piece::Queen, 9)]
+            .into_iter()
+            .map(|(piece, value)| self.bitboards.pieces(piece, color).count() * value)
+            .sum()
+    }
+
+    // The AI takes a draw unless it is ahead in material.
+    fn ai_accepts_draw(&self, color: Color) -> bool {
+        self.material(color) <= self.material(opponent(color))
+    }
+
+    // How the game has ended, either by resignation or agreement or because
+    // the rules end it in the current position.
+    fn outcome(&self) -> Option<GameOutcome> {
+        if self.declared_outcome.is_some() {
+            self.declared_outcome
+        } else if self.is_checkmate() {
+            Some(GameOutcome { result: GameResult::win_for(opponent(self.turn)), reason: TerminationReason::Checkmate })
+        } else if self.is_stalemate() {
+            Some(GameOutcome::draw(TerminationReason::Stalemate))
+        } else if self.is_insufficient_material() {
+            Some(GameOutcome::draw(TerminationReason::InsufficientMaterial))
+        } else if self.is_threefold_repetition() {
+            Some(GameOutcome::draw(TerminationReason::ThreefoldRepetition))
+        } else if self.is_fifty_move_rule() {
+            Some(GameOutcome::draw(TerminationReason::FiftyMoveRule))
+        } else {
+            None
+        }
+    }
+
+    // Says how the rules have ended the game; resignations, agreed draws
+    // and the like are announced as they happen.
+    fn announce(&self, outcome: GameOutcome) {
+        match outcome.reason {
+            TerminationReason::Checkmate => println!("Checkmate! {:?} wins!", opponent(self.turn)),
+            TerminationReason::Stalemate => println!("Stalemate! {:?} has no legal moves. The game is a draw.", self.turn),
+            TerminationReason::ThreefoldRepetition => println!("Draw by threefold repetition."),
+            TerminationReason::FiftyMoveRule => println!("Draw by the fifty-move rule."),
+            TerminationReason::InsufficientMaterial => println!("Draw! Neither side has enough material to checkmate."),
+            TerminationReason::Resignation | TerminationReason::DrawAgreement | TerminationReason::Timeout | TerminationReason::MoveLimit => {}
+        }
+    }
+
+    // Plays the game out, with the AI moving for both sides except `human`,
+    // whose moves are read from the terminal, and reports how it ended.
+    fn play(&mut self, game_limit: u64, move_limit: usize, human: Option<Color>, ai: AiSettings) -> GameOutcome {
+        let start_time = Instant::now();
+        let mut move_count = 0;
+        // Time left on each side's clock, if the game is played with one.
+        let mut clocks = ai.clock.map(|(initial, _)| [initial; 2]);
+
+        let outcome = loop {
+            if start_time.elapsed().as_secs() >= game_limit {
+                println!("Game over! Time limit of {} seconds reached.", game_limit);
+                break GameOutcome::draw(TerminationReason::Timeout);
+            }
+
+            if move_count >= move_limit {
+                println!("Game over! Move limit of {} moves reached.", move_limit);
+                break GameOutcome::draw(TerminationReason::MoveLimit);
+            }
+
+            println!("{}", self.render(BoardStyle::default()));
+
+            if let Some(outcome) = self.outcome() {
+                self.announce(outcome);
+                break outcome;
+            }
+
+            let move_start = Instant::now();
+            let action = if human == Some(self.turn) {
+                self.read_human_action(&ai.evaluation)
+            } else {
+                let clock = ai.clock.zip(clocks).map(|((_, increment), clocks)| TimeControl {
+                    remaining: clocks[self.turn.index()],
+                    increment,
+                    moves_to_go: None,
+                });
+                self.get_ai_move(&ai, clock).map(Action::Move)
+            };
+            if let (Some((_, increment)), Some(clocks)) = (ai.clock, clocks.as_mut()) {
+                let clock = &mut clocks[self.turn.index()];
+                match clock.checked_sub(move_start.elapsed()) {
+                    Some(left) => *clock = left.saturating_add(increment),
+                    None => {
+                        println!("Game over! {:?} ran out of time.", self.turn);
+                        break GameOutcome { result: GameResult::win_for(opponent(self.turn)), reason: TerminationReason::Timeout };
+                    }
+                }
+            }
+            match action {
+                Some(Action::Move(mv)) => {
+                    println!("{:?} plays {}", self.turn, self.numbered_san(mv));
+                    self.play_move(mv);
+                    move_count += 1;
+                }
+                Some(Action::OfferDraw) => {
+                    let (offering, answering) = (self.turn, opponent(self.turn));
+                    self.offer_draw(offering);
+                    if self.ai_accepts_draw(answering) && self.accept_draw(answering) {
+                        println!("{:?} accepts the draw offer.", answering);
+                    } else {
+                        println!("{:?} declines the draw offer.", answering);
+                        self.decline_draw();
+                    }
+                }
+                // A human closing the input gives the game up.
+                Some(Action::Resign) | None => {
+                    println!("Game over! {:?} resigns.", self.turn);
+                    self.resign(self.turn);
+                }
+            }
+
+            if let Some(outcome) = self.declared_outcome {
+                break outcome;
+            }
+        };
+
+        println!("Result: {}", outcome.result);
+        outcome
+    }
+}
+
+// Why a custom position can't be played from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank(Square),
+    // The side that just moved has left its king in check.
+    OpponentInCheck,
+    // A castling right, by its FEN letter, without the king and that rook
+    // on their home squares.
+    ImpossibleCastlingRights(char),
+    // No pawn can just have double-pushed past this square.
+    ImpossibleEnPassant(Square),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{:?} has no king", color),
+            PositionError::TooManyKings(color) => write!(f, "{:?} has more than one king", color),
+            PositionError::PawnOnBackRank(square) => write!(f, "pawn on {}, which is on the first or last rank", square),
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            PositionError::ImpossibleCastlingRights(letter) => {
+                write!(f, "castling right {} without the king and rook on their home squares", letter)
+            }
+            PositionError::ImpossibleEnPassant(square) => write!(f, "no pawn can have just skipped over {}", square),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+// Why a FEN string can't be read, or what is wrong with its position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FenError {
+    // Named after the first field missing.
+    MissingField(&'static str),
+    TooManyFields(usize),
+    // The placement has this many ranks rather than eight.
+    WrongRankCount(usize),
+    // This rank's pieces and empty squares don't make eight squares.
+    WrongRankLength(u8),
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    // Chess960 castling, with the king or rook elsewhere than e1 and the
+    // corner, which this engine can't play.
+    UnsupportedCastling(char),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    // Well-formed, but not a position that can be played from.
+    Position(PositionError),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(name) => write!(f, "missing the {} field", name),
+            FenError::TooManyFields(count) => write!(f, "{} fields, more than the six of a FEN", count),
+            FenError::WrongRankCount(count) => write!(f, "{} ranks in the piece placement rather than 8", count),
+            FenError::WrongRankLength(rank) => write!(f, "rank {} does not have 8 squares", rank),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece {:?}, expected one of PNBRQK or pnbrqk", c),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move {:?}, expected \"w\" or \"b\"", side),
+            FenError::InvalidCastling(castling) => write!(f, "invalid castling rights {:?}, expected \"-\", some of \"KQkq\" or rook files such as \"HAha\"", castling),
+            FenError::UnsupportedCastling(c) => write!(f, "castling right {:?} is for Chess960, which is not supported", c),
+            FenError::InvalidEnPassant(square) => write!(f, "invalid en passant square {:?}, expected \"-\" or a square on the third or sixth rank", square),
+            FenError::InvalidHalfmoveClock(clock) => write!(f, "invalid halfmove clock {:?}", clock),
+            FenError::InvalidFullmoveNumber(number) => write!(f, "invalid fullmove number {:?}, expected 1 or more", number),
+            FenError::Position(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl From<PositionError> for FenError {
+    fn from(err: PositionError) -> FenError {
+        FenError::Position(err)
+    }
+}
+
+// Sets up a game from an arbitrary position, checked before it is played.
+#[derive(Clone)]
+struct PositionBuilder {
+    board: Board,
+    turn: Color,
+    en_passant: Option<Square>,
+    castling: CastlingRights,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl PositionBuilder {
+    // An empty board with White to move and nobody able to castle.
+    fn new() -> Self {
+        PositionBuilder {
+            board: [[None; 8]; 8],
+            turn: Color::White,
+            en_passant: None,
+            castling: CastlingRights::NONE,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    fn piece(mut self, square: Square, piece: ChessPiece) -> Self {
+        self.board[square.rank().0 as usize][square.file().0 as usize] = Some(piece);
+        self
+    }
+
+    fn turn(mut self, turn: Color) -> Self {
+        self.turn = turn;
+        self
+    }
+
+    fn castling(mut self, castling: CastlingRights) -> Self {
+        self.castling = castling;
+        self
+    }
+
+    fn en_passant(mut self, square: Square) -> Self {
+        self.en_passant = Some(square);
+        self
+    }
+
+    fn halfmove_clock(mut self, halfmove_clock: u32) -> Self {
+        self.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    fn fullmove_number(mut self, fullmove_number: u32) -> Self {
+        self.fullmove_number = fullmove_number;
+        self
+    }
+
+    fn build(self) -> Result<Game, PositionError> {
+        let mut game = self.assemble();
+        game.validate()?;
+        game.hash = game.compute_hash();
+        game.pawn_hash = game.compute_pawn_hash();
+        game.history.push(game.position_key());
+        game.start = game.to_fen();
+        Ok(game)
+    }
+
+    // The game as set up, unchecked and without its hashes or history.
+    fn assemble(self) -> Game {
+        Game {
+            board: self.board,
+            bitboards: Bitboards::from_mailbox(&self.board),
+            hash: 0,
+            pawn_hash: 0,
+            turn: self.turn,
+            en_passant: self.en_passant,
+            castling: self.castling,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            history: vec![],
+            start: String::new(),
+            moves: vec![],
+            draw_offer: None,
+            declared_outcome: None,
+            nnue: None,
+        }
+    }
+}
+
+// The names of a FEN's six fields, in order.
+const FEN_FIELDS: [&str; 6] = ["piece placement", "side to move", "castling rights", "en passant square", "halfmove clock", "fullmove number"];
+
+// One problem with a FEN, and the field it is in, by its index into
+// FEN_FIELDS; None when it is the FEN as a whole, with too many fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FenIssue {
+    field: Option<usize>,
+    error: FenError,
+}
+
+impl fmt::Display for FenIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.field {
+            Some(field) => write!(f, "{}: {}", FEN_FIELDS[field], self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+// Everything wrong with `fen`, by field, rather than only the first thing
+// as `Game::from_fen` reports: every rank of the placement and every
+// field is checked, and, if the placement and side to move can be read,
+// the position as a whole: the kings, pawns on the back ranks, the side
+// not to move in check, each castling right and the en passant square.
+// Empty for a FEN that `from_fen` accepts.
+fn validate_fen(fen: &str) -> Vec<FenIssue> {
+    let (builder, mut issues) = read_fen(fen);
+    if !issues.iter().any(|issue| issue.field == Some(0) || issue.field == Some(1)) {
+        issues.extend(builder.assemble().position_errors().into_iter().map(|err| {
+            let field = match err {
+                PositionError::MissingKing(_) | PositionError::TooManyKings(_) | PositionError::PawnOnBackRank(_) => 0,
+                PositionError::OpponentInCheck => 1,
+                PositionError::ImpossibleCastlingRights(_) => 2,
+                PositionError::ImpossibleEnPassant(_) => 3,
+            };
+            FenIssue { field: Some(field), error: FenError::Position(err) }
+        }));
+    }
+    issues.sort_by_key(|issue| issue.field);
+    issues
+}
+
+// Reads a position in Forsyth-Edwards Notation as far as it can, noting
+// each problem with its fields as it goes. Unreadable fields are left at
+// the builder's defaults. The position itself isn't checked.
+fn read_fen(fen: &str) -> (PositionBuilder, Vec<FenIssue>) {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let mut builder = PositionBuilder::new();
+    let mut issues = vec![];
+    let mut issue = |field: Option<usize>, error: FenError| issues.push(FenIssue { field, error });
+    if fields.len() > 6 {
+        issue(None, FenError::TooManyFields(fields.len()));
+    }
+    if let Some(missing) = (fields.len()..4).next() {
+        issue(Some(missing), FenError::MissingField(FEN_FIELDS[missing]));
+    }
+
+    if let Some(placement) = fields.first() {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            issue(Some(0), FenError::WrongRankCount(ranks.len()));
+        }
+        for (row, rank) in ranks.iter().enumerate().take(8) {
+            let number = 8 - row as u8;
+            let mut file = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10).filter(|skip| (1..=8).contains(skip)) {
+                    file += skip as u8;
+                    continue;
+                }
+                let piece = match c.to_ascii_lowercase() {
+                    'p' => Some(Piece::Pawn),
+                    'n' => Some(Piece::Knight),
+                    'b' => Some(Piece::Bishop),
+                    'r' => Some(Piece::Rook),
+                    'q' => Some(Piece::Queen),
+                    'k' => Some(Piece::King),
+                    _ => None,
+                };
+                match piece {
+                    Some(piece) if file < 8 => {
+                        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+                        builder = builder.piece(Square((number - 1) * 8 + file), ChessPiece { piece, color });
+                    }
+                    Some(_) => {}
+                    None => issue(Some(0), FenError::InvalidPiece(c)),
+                }
+                file += 1;
+            }
+            if file != 8 {
+                issue(Some(0), FenError::WrongRankLength(number));
+            }
+        }
+    }
+
+    match fields.get(1).copied() {
+        Some("w") | None => {}
+        Some("b") => builder = builder.turn(Color::Black),
+        Some(side) => issue(Some(1), FenError::InvalidSideToMove(side.to_string())),
+    }
+
+    if let Some(castling) = fields.get(2) {
+        match parse_castling(castling, &builder.board) {
+            Ok(rights) => builder = builder.castling(rights),
+            Err(err) => issue(Some(2), err),
+        }
+    }
+
+    if let Some(&en_passant) = fields.get(3).filter(|&&field| field != "-") {
+        match en_passant.parse::<Square>() {
+            Ok(square) if square.rank() == Rank(2) || square.rank() == Rank(5) => builder = builder.en_passant(square),
+            _ => issue(Some(3), FenError::InvalidEnPassant(en_passant.to_string())),
+        }
+    }
+
+    if let Some(&clock) = fields.get(4) {
+        match clock.parse() {
+            Ok(clock) => builder = builder.halfmove_clock(clock),
+            Err(_) => issue(Some(4), FenError::InvalidHalfmoveClock(clock.to_string())),
+        }
+    }
+    if let Some(&number) = fields.get(5) {
+        match number.parse() {
+            Ok(number) if number > 0 => builder = builder.fullmove_number(number),
+            _ => issue(Some(5), FenError::InvalidFullmoveNumber(number.to_string())),
+        }
+    }
+    (builder, issues)
+}
+
+// Besides KQkq, the castling rook's file may be given, as Chess960 needs:
+// Shredder-FEN always names it ("HAha") and X-FEN when K or Q would be
+// ambiguous. Only the usual castling, with the king on the e-file and the
+// rooks in the corners, can be played, though.
+fn parse_castling(castling: &str, board: &Board) -> Result<CastlingRights, FenError> {
+    let mut rights = CastlingRights::NONE;
+    if castling == "-" {
+        return Ok(rights);
+    }
+    for c in castling.chars() {
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        let back_rank = &board[if color == Color::White { 0 } else { 7 }];
+        let king = back_rank.iter().position(|&piece| piece == Some(ChessPiece { piece: Piece::King, color }));
+        let (kingside, rook) = match c.to_ascii_lowercase() {
+            'k' => (true, None),
+            'q' => (false, None),
+            file @ 'a'..='h' => {
+                let rook = (file as u8 - b'a') as usize;
+                match king {
+                    Some(king) if king != rook => (rook > king, Some(rook)),
+                    _ => return Err(FenError::InvalidCastling(castling.to_string())),
+                }
+            }
+            _ => return Err(FenError::InvalidCastling(castling.to_string())),
+        };
+        if king.is_some_and(|king| king != 4) || rook.is_some_and(|rook| rook != if kingside { 7 } else { 0 }) {
+            return Err(FenError::UnsupportedCastling(c));
+        }
+        let right = match (color, kingside) {
+            (Color::White, true) => &mut rights.white_kingside,
+            (Color::White, false) => &mut rights.white_queenside,
+            (Color::Black, true) => &mut rights.black_kingside,
+            (Color::Black, false) => &mut rights.black_queenside,
+        };
+        if *right {
+            return Err(FenError::InvalidCastling(castling.to_string()));
+        }
+        *right = true;
+    }
+    Ok(rights)
+}
+
+// Where the rook starts and ends when the king castles to `king_to`.
+fn castling_rook_squares(king_to: Square) -> (Square, Square) {
+    let rank = king_to.rank();
+    if king_to.file() == File(6) {
+        (Square::new(File(7), rank), Square::new(File(5), rank))
+    } else {
+        (Square::new(File(0), rank), Square::new(File(3), rank))
+    }
+}
+
+// Pawns reaching the last rank are expanded into one move per promotion piece.
+fn push_move(moves: &mut MoveList, piece: ChessPiece, from: Square, to: Square, captured: Option<Piece>) {
+    let last_rank = match piece.color {
+        Color::White => Rank(7),
+        Color::Black => Rank(0),
+    };
+    let mv = Move::new(piece.piece, from, to, captured);
+
+    if piece.piece == Piece::Pawn && to.rank() == last_rank {
+        for promotion in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+            moves.push(Move { promotion: Some(promotion), ..mv });
+        }
+    } else {
+        moves.push(mv);
+    }
+}
+
+fn parse_promotion(s: &str) -> Option<Piece> {
+    match s.to_ascii_lowercase().as_str() {
+        "q" => Some(Piece::Queen),
+        "r" => Some(Piece::Rook),
+        "b" => Some(Piece::Bishop),
+        "n" => Some(Piece::Knight),
+        _ => None,
+    }
+}
+
+// "300" or "300+2": seconds on the clock, and seconds added per move.
+fn parse_clock(s: &str) -> Option<(Duration, Duration)> {
+    let (initial, increment) = s.split_once('+').unwrap_or((s, "0"));
+    let seconds = |s: &str| s.parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok());
+    Some((seconds(initial)?, seconds(increment)?))
+}
+
+fn run_perft(game: &Game, depth: u32, threads: usize) {
+    let start = Instant::now();
+    let counts = perft::divide(game, depth, threads);
+    for (mv, nodes) in &counts {
+        println!("{}: {}", mv, nodes);
+    }
+    let total: u64 = counts.iter().map(|(_, nodes)| nodes).sum();
+    let elapsed = start.elapsed();
+    println!();
+    println!("Nodes: {}", total);
+    println!("Time: {:.2}s ({:.0} nodes/s)", elapsed.as_secs_f64(), total as f64 / elapsed.as_secs_f64().max(1e-9));
+}
+
+fn run_eval(args: &[String]) {
+    let game = match args.get(2).filter(|fen| !fen.starts_with("--")) {
+        None => Game::new(),
+        Some(fen) => Game::from_fen(fen).unwrap_or_else(|_| {
+            eprintln!("{}:", fen);
+            for issue in validate_fen(fen) {
+                eprintln!("  {}", issue);
+            }
+            std::process::exit(2);
+        }),
+    };
+    let params = load_params(args);
+    println!("FEN: {}", game.to_fen());
+    println!("Shredder-FEN: {}", game.to_shredder_fen());
+    println!();
+    game.print_eval(&params);
+
+    // A one-ply search, quiescence included, so the scores count captures
+    // that are hanging.
+    let mut searcher = Searcher::new();
+    searcher.multi_pv = EVAL_CANDIDATES;
+    searcher.set_evaluation(Arc::new(params));
+    let result = searcher.search(&game, &SearchLimits { depth: Some(1), ..SearchLimits::default() });
+    if result.lines.is_empty() {
+        println!("No legal moves");
+        return;
+    }
+    println!("Best moves, for {:?}:", game.turn);
+    for line in &result.lines {
+        println!("  {:<8} {:+}", san::format(&game, line.moves[0]), line.score);
+    }
+
+    // A diagram with an arrow for each of the moves, the best in green.
+    if let Some(i) = args.iter().position(|a| a == "--svg") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("usage: chess eval [\"<fen>\"] [--params <file>] [--svg <file>]");
+            std::process::exit(2);
+        };
+        let mut diagram = svg::Diagram { flipped: game.turn == Color::Black, ..svg::Diagram::default() };
+        for (rank, line) in result.lines.iter().enumerate().rev() {
+            let color = if rank == 0 { "#15781b" } else { "#003088" };
+            diagram.arrows.push(svg::Arrow::new(line.moves[0].from, line.moves[0].to, color));
+        }
+        if let Err(err) = fs::write(path, diagram.render(&game)) {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The evaluation parameters in the file after `--params`, or the defaults
+// without one.
+fn load_params(args: &[String]) -> eval::Parameters {
+    let Some(i) = args.iter().position(|a| a == "--params") else {
+        return eval::Parameters::default();
+    };
+    let Some(path) = args.get(i + 1) else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    eval::Parameters::load(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    })
+}
+
+// The engine options given as flags in `args`; see options.rs.
+fn load_options(args: &[String]) -> EngineOptions {
+    EngineOptions::from_args(args).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    })
+}
+
+// The Syzygy tablebases on the path given as `--syzygy <path>` in `args`,
+// if any; see syzygy.rs.
+#[cfg(feature = "syzygy")]
+fn load_tablebase(args: &[String]) -> Option<Arc<dyn Tablebase>> {
+    let path = args.iter().position(|a| a == "--syzygy").map(|i| args.get(i + 1))?;
+    let Some(path) = path else {
+        eprintln!("--syzygy takes a path to the tablebase files");
+        std::process::exit(2);
+    };
+    match syzygy::Syzygy::open(path) {
+        Ok(tables) => Some(Arc::new(tables)),
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "syzygy"))]
+fn load_tablebase(args: &[String]) -> Option<Arc<dyn Tablebase>> {
+    if args.iter().any(|a| a == "--syzygy") {
+        eprintln!("--syzygy needs a build with the syzygy feature");
+        std::process::exit(2);
+    }
+    None
+}
+
+// Builds a Polyglot opening book from the games in PGN files, skipping any
+// game that can't be read.
+fn run_bookgen(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess bookgen <games.pgn>... --out <book.bin> [--depth <plies>] [--min-games <n>]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let number = |flag: &str| option(flag).map(|n| n.parse().unwrap_or_else(|_| usage()));
+    let paths: Vec<&String> = args[2..].iter().take_while(|path| !path.starts_with("--")).collect();
+    let (false, Some(out)) = (paths.is_empty(), option("--out")) else {
+        usage();
+    };
+    let settings = bookgen::BookSettings {
+        depth: number("--depth").unwrap_or(bookgen::BookSettings::default().depth),
+        min_games: number("--min-games").unwrap_or(bookgen::BookSettings::default().min_games),
+    };
+
+    let mut builder = bookgen::BookBuilder::new(settings);
+    let mut unreadable = 0;
+    for path in paths {
+        let file = fs::File::open(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        });
+        let mut reader = pgn::Reader::new(io::BufReader::new(file));
+        while let Some(record) = reader.next() {
+            match record {
+                Ok(record) => builder.add(&record),
+                Err(pgn::PgnError::Io(err)) => {
+                    eprintln!("{}: {}", path, err);
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("{}:{}: {}", path, reader.start_line(), err);
+                    unreadable += 1;
+                }
+            }
+        }
+    }
+    let mut entries = builder.entries();
+    if let Err(err) = fs::File::create(out).and_then(|file| {
+        let mut file = io::BufWriter::new(file);
+        polyglot::write(&mut file, &mut entries)?;
+        file.flush()
+    }) {
+        eprintln!("{}: {}", out, err);
+        std::process::exit(1);
+    }
+    println!("{} games read, {} without a result and {} unreadable skipped", builder.games, builder.unfinished, unreadable);
+    let positions = entries.chunk_by(|a, b| a.key == b.key).count();
+    println!("{} moves in {} positions written to {}", entries.len(), positions, out);
+}
+
+fn run_pack(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess pack <games.pgn>... --out <file> | chess pack --fen <fen>");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    if let Some(fen) = option("--fen") {
+        let game = Game::from_fen(fen).unwrap_or_else(|err| {
+            eprintln!("{}: {}", fen, err);
+            std::process::exit(1);
+        });
+        println!("{}", binary::encode_position(&game).iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+        return;
+    }
+    let paths: Vec<&String> = args[2..].iter().take_while(|path| !path.starts_with("--")).collect();
+    let (false, Some(out)) = (paths.is_empty(), option("--out")) else {
+        usage();
+    };
+
+    let (mut bytes, mut games, mut unreadable) = (vec![], 0, 0);
+    for path in paths {
+        let file = fs::File::open(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        });
+        let mut reader = pgn::Reader::new(io::BufReader::new(file));
+        while let Some(record) = reader.next() {
+            match record {
+                Ok(record) => {
+                    bytes.extend(binary::encode_game(&record.game));
+                    games += 1;
+                }
+                Err(pgn::PgnError::Io(err)) => {
+                    eprintln!("{}: {}", path, err);
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("{}:{}: {}", path, reader.start_line(), err);
+                    unreadable += 1;
+                }
+            }
+        }
+    }
+    if let Err(err) = fs::write(out, &bytes) {
+        eprintln!("{}: {}", out, err);
+        std::process::exit(1);
+    }
+    println!("{} games packed into {} bytes in {}, {} unreadable skipped", games, bytes.len(), out, unreadable);
+}
+
+fn run_unpack(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess unpack <file> [--out <games.pgn>] | chess unpack --hex <hex>");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    if let Some(hex) = option("--hex") {
+        let bytes: Option<Vec<u8>> = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok()))
+            .collect();
+        let bytes = bytes.unwrap_or_else(|| usage());
+        match binary::decode_position(&mut bytes.as_slice()) {
+            Ok(game) => println!("{}", game.to_fen()),
+            Err(err) => {
+                eprintln!("{}: {}", hex, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let Some(path) = args.get(2).filter(|path| !path.starts_with("--")) else {
+        usage();
+    };
+
+    let packed = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    });
+    // The games keep no tags, so each has its result, if it finished, and
+    // "?" for the rest of the roster.
+    let mut text = vec![];
+    let mut rest = packed.as_slice();
+    for i in 1.. {
+        if rest.is_empty() {
+            break;
+        }
+        let game = binary::decode_game(&mut rest).unwrap_or_else(|err| {
+            eprintln!("{}: game {}: {}", path, i, err);
+            std::process::exit(1);
+        });
+        let result = game.outcome().map(|outcome| outcome.result);
+        let record = pgn::PgnGame::new(pgn::Tags::new(), game, result);
+        pgn::write(&mut text, &record).expect("writing to memory doesn't fail");
+    }
+    let written = match option("--out") {
+        Some(out) => fs::write(out, &text).map_err(|err| format!("{}: {}", out, err)),
+        None => io::stdout().write_all(&text).map_err(|err| err.to_string()),
+    };
+    if let Err(err) = written {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run_tune(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess tune <positions> [--params <file>] [--epochs <n>] [--threads <n>] [--out <file>]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let number = |flag: &str| option(flag).map(|n| n.parse().unwrap_or_else(|_| usage()));
+    let Some(path) = args.get(2).filter(|path| !path.starts_with("--")) else {
+        usage();
+    };
+    let tuning = tune::Tuning {
+        epochs: number("--epochs").unwrap_or(tune::Tuning::default().epochs),
+        threads: number("--threads").unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+        ..tune::Tuning::default()
+    };
+    let out = option("--out").map_or("parameters.toml", String::as_str);
+
+    let start = load_params(args);
+    let entries = match tune::load(path, &start) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    println!("{} positions", entries.len());
+    let params = tune::tune(&entries, &start, &tuning, |epoch, error| {
+        println!("Epoch {}: error {:.6}", epoch, error);
+    });
+    if let Err(err) = fs::File::create(out).and_then(|mut file| params.write(&mut file)) {
+        eprintln!("{}: {}", out, err);
+        std::process::exit(1);
+    }
+    println!("Weights written to {}", out);
+}
+
+// Plays through the game in a PGN file, showing the board after each move.
+// With `--game <n>`, the nth game in the file, skipping any before it
+// that can't be read.
+fn run_replay(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess replay <games.pgn> [--game <n>] [--moves <san|long|uci>]");
+        std::process::exit(2);
+    };
+    let Some(path) = args.get(2).filter(|path| !path.starts_with("--")) else {
+        usage();
+    };
+    let number = match args.iter().position(|a| a == "--game").map(|i| args.get(i + 1).and_then(|n| n.parse::<usize>().ok())) {
+        None => 1,
+        Some(Some(number)) if number > 0 => number,
+        Some(_) => usage(),
+    };
+    let notation = match args.iter().position(|a| a == "--moves").map(|i| args.get(i + 1).map(|n| n.parse::<notation::Notation>())) {
+        None => None,
+        Some(Some(Ok(notation))) => Some(notation),
+        Some(Some(Err(err))) => {
+            eprintln!("{}", err);
+            usage();
+        }
+        Some(None) => usage(),
+    };
+    let file = fs::File::open(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    });
+    let mut reader = pgn::Reader::new(io::BufReader::new(file));
+    let record = match reader.nth(number - 1) {
+        Some(Ok(record)) => record,
+        Some(Err(err)) => {
+            eprintln!("{}:{}: {}", path, reader.start_line(), err);
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("{}: fewer than {} games", path, number);
+            std::process::exit(1);
+        }
+    };
+    // Just the moves, for other tools, in place of the boards.
+    if let Some(notation) = notation {
+        println!("{}", notation::write(&record.game, notation, notation != notation::Notation::Uci));
+        return;
+    }
+    for (name, value) in record.tags.iter() {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    for comment in &record.comments {
+        println!("{}", comment);
+    }
+
+    let mut game = Game::from_fen(&record.game.start).expect("the game was read from this position");
+    println!("{}", game.render(BoardStyle::default()));
+    for (i, &mv) in record.game.moves.iter().enumerate() {
+        let annotation = record.annotations.get(i).cloned().unwrap_or_default();
+        let nags: String = annotation.nags.iter().map(|nag| format!(" ${}", nag)).collect();
+        println!("{}{}", game.numbered_san(mv), nags);
+        for comment in &annotation.comments {
+            println!("{}", comment);
+        }
+        for variation in &annotation.variations {
+            println!("({})", pgn::variation_text(&game, variation));
+        }
+        game.play_move(mv);
+        println!("{}", game.render(BoardStyle::default()));
+    }
+    println!("Result: {}", record.result.map_or("*".to_string(), |result| result.to_string()));
+}
+
+// Downloads a game and writes it out as PGN, to `--out` or the terminal.
+#[cfg(feature = "online")]
+fn run_fetch(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess fetch <url|id> [--player <name>] [--out <file>]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let Some(source) = args.get(2).filter(|source| !source.starts_with("--")) else {
+        usage();
+    };
+    let record = online::fetch(source, option("--player").map(String::as_str)).unwrap_or_else(|err| {
+        eprintln!("{}: {}", source, err);
+        std::process::exit(1);
+    });
+    let written = match option("--out") {
+        Some(path) => fs::File::create(path).and_then(|mut file| pgn::write(&mut file, &record)).map_err(|err| format!("{}: {}", path, err)),
+        None => pgn::write(&mut io::stdout(), &record).map_err(|err| err.to_string()),
+    };
+    if let Err(err) = written {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "online"))]
+fn run_fetch(_args: &[String]) {
+    eprintln!("chess fetch needs a build with the online feature");
+    std::process::exit(2);
+}
+
+// Plays on Lichess as a bot account until interrupted. The token comes
+// from `--token` or else the LICHESS_BOT_TOKEN environment variable, which
+// keeps it out of the process list.
+#[cfg(feature = "online")]
+fn run_lichess_bot(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess lichess-bot [--token <token>] [--games <n>] [--threads <n>] [--hash <mb>] [--params <file>]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let number = |flag: &str| option(flag).map(|n| n.parse::<usize>().ok().filter(|&n| n > 0).unwrap_or_else(|| usage()));
+    let Some(token) = option("--token").cloned().or_else(|| std::env::var("LICHESS_BOT_TOKEN").ok()) else {
+        eprintln!("chess lichess-bot needs an API token, from --token or LICHESS_BOT_TOKEN");
+        std::process::exit(2);
+    };
+    let settings = lichess_bot::BotSettings {
+        evaluation: Arc::new(load_params(args)),
+        options: load_options(args),
+        max_games: number("--games").unwrap_or(1),
+    };
+    if let Err(err) = lichess_bot::run(&token, settings) {
+        eprintln!("lichess-bot: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "online"))]
+fn run_lichess_bot(_args: &[String]) {
+    eprintln!("chess lichess-bot needs a build with the online feature");
+    std::process::exit(2);
+}
+
+// Hosts a game over the network and plays it, from the terminal or, with
+// `--ai`, with the engine.
+fn run_host(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess host [--port <n>] [--color white|black] [--clock <s>[+<inc>]] [--fen \"<fen>\"] [--ai [--depth <plies>] [--movetime <ms>] [--threads <n>] [--params <file>]]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let port = option("--port").map_or(netplay::DEFAULT_PORT, |port| port.parse().unwrap_or_else(|_| usage()));
+    let color = match option("--color").map(String::as_str) {
+        None | Some("white") => Color::White,
+        Some("black") => Color::Black,
+        Some(_) => usage(),
+    };
+    let clock = option("--clock").map(|clock| parse_clock(clock).unwrap_or_else(|| usage()));
+    let mut game = match option("--fen") {
+        Some(fen) => Game::from_fen(fen).unwrap_or_else(|err| {
+            eprintln!("{}: {}", fen, err);
+            std::process::exit(1);
+        }),
+        None => Game::new(),
+    };
+    let human = !args.iter().any(|a| a == "--ai");
+    if let Err(err) = netplay::host(port, &mut game, color, human, network_ai(args, clock).unwrap_or_else(|| usage())) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// Joins a game hosted with `chess host`.
+fn run_connect(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess connect <host>[:<port>] [--ai [--depth <plies>] [--movetime <ms>] [--threads <n>] [--params <file>]]");
+        std::process::exit(2);
+    };
+    let Some(address) = args.get(2).filter(|address| !address.starts_with("--")) else {
+        usage();
+    };
+    // A bare host name or IPv4 address takes the default port.
+    let address = if address.contains(':') { address.clone() } else { format!("{}:{}", address, netplay::DEFAULT_PORT) };
+    let human = !args.iter().any(|a| a == "--ai");
+    if let Err(err) = netplay::connect(address.as_str(), human, network_ai(args, None).unwrap_or_else(|| usage())) {
+        eprintln!("{}: {}", address, err);
+        std::process::exit(1);
+    }
+}
+
+// The engine's settings for a network game, from the same flags as a game
+// at the terminal; None if a number among them doesn't parse.
+fn network_ai(args: &[String], clock: Option<(Duration, Duration)>) -> Option<AiSettings> {
+    let number = |flag: &str| match args.iter().position(|a| a == flag) {
+        Some(i) => args.get(i + 1).and_then(|n| n.parse::<u64>().ok()).map(Some),
+        None => Some(None),
+    };
+    Some(AiSettings {
+        depth: number("--depth")?.map_or(64, |depth| depth as u32),
+        // Without a clock, a few seconds a move.
+        move_time: number("--movetime")?.map(Duration::from_millis).or(if clock.is_some() { None } else { Some(Duration::from_secs(5)) }),
+        options: load_options(args),
+        variety: 0,
+        seed: 0,
+        clock,
+        evaluation: Arc::new(load_params(args)),
+    })
+}
+
+// Plays the engine over the board on a DGT electronic board.
+#[cfg(feature = "dgt")]
+fn run_dgt(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess dgt <port> [--color white|black] [--fen \"<fen>\"] [--depth <plies>] [--movetime <ms>] [--threads <n>] [--params <file>] [--pgn <file>]");
+        std::process::exit(2);
+    };
+    let Some(port) = args.get(2).filter(|port| !port.starts_with("--")) else {
+        usage();
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let human = match option("--color").map(String::as_str) {
+        None | Some("white") => Color::White,
+        Some("black") => Color::Black,
+        Some(_) => usage(),
+    };
+    let mut game = match option("--fen") {
+        Some(fen) => Game::from_fen(fen).unwrap_or_else(|err| {
+            eprintln!("{}: {}", fen, err);
+            std::process::exit(1);
+        }),
+        None => Game::new(),
+    };
+    let ai = network_ai(args, None).unwrap_or_else(|| usage());
+    let outcome = dgt::open(port).and_then(|mut board| dgt::play(&mut board, &mut game, human, ai)).unwrap_or_else(|err| {
+        eprintln!("{}: {}", port, err);
+        std::process::exit(1);
+    });
+    if let Some(path) = option("--pgn") {
+        let player = |color| if color == human { "Human" } else { "ChessInRust" };
+        let tags = pgn::Tags::roster(player(Color::White), player(Color::Black), Some(outcome.result));
+        let record = pgn::PgnGame::new(tags, game, Some(outcome.result));
+        if let Err(err) = fs::File::create(path).and_then(|mut file| pgn::write(&mut file, &record)) {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "dgt"))]
+fn run_dgt(_args: &[String]) {
+    eprintln!("chess dgt needs a build with the dgt feature");
+    std::process::exit(2);
+}
+
+// Plays two UCI engines against each other.
+fn run_match(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess match <engine> <engine> [--games <n>] [--clock <s>[+<inc>]] [--movetime <ms>] [--depth <plies>] [--openings <file>] [--option <name>=<value>]... [--pgn <file>]");
+        std::process::exit(2);
+    };
+    let (Some(first), Some(second)) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+    if first.starts_with("--") || second.starts_with("--") {
+        usage();
+    }
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let number = |flag: &str| option(flag).map(|n| n.parse::<u64>().unwrap_or_else(|_| usage()));
+    let openings = match option("--openings") {
+        Some(path) => matchplay::read_openings(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }),
+        None => vec![],
+    };
+    let options = args
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| *a == "--option")
+        .map(|(i, _)| match args.get(i + 1).and_then(|option| option.split_once('=')) {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => usage(),
+        })
+        .collect();
+    let mut settings = matchplay::MatchSettings {
+        games: number("--games").map_or(10, |games| games as usize),
+        clock: option("--clock").map(|clock| parse_clock(clock).unwrap_or_else(|| usage())),
+        move_time: number("--movetime").map(Duration::from_millis),
+        depth: number("--depth").map(|depth| depth as u32),
+        openings,
+        options,
+        pgn: option("--pgn").cloned(),
+    };
+    if settings.clock.is_none() && settings.move_time.is_none() && settings.depth.is_none() {
+        settings.clock = Some(MATCH_CLOCK);
+    }
+    if let Err(err) = matchplay::run([first, second], &settings) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// Serves the HTTP API until killed.
+#[cfg(feature = "server")]
+fn run_serve(args: &[String]) {
+    let port = match args.iter().position(|a| a == "--port").map(|i| args.get(i + 1).and_then(|port| port.parse().ok())) {
+        None => http::DEFAULT_PORT,
+        Some(Some(port)) => port,
+        Some(None) => {
+            eprintln!("usage: chess serve [--port <n>] [--params <file>] [--nnue <file>] [--hash <mb>] ...");
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = http::serve(port, load_params(args), load_options(args)) {
+        eprintln!("port {}: {}", port, err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn run_serve(_args: &[String]) {
+    eprintln!("chess serve needs a build with the server feature");
+    std::process::exit(2);
+}
+
+// Serves the gRPC analysis service until killed.
+#[cfg(feature = "grpc")]
+fn run_grpc(args: &[String]) {
+    let port = match args.iter().position(|a| a == "--port").map(|i| args.get(i + 1).and_then(|port| port.parse().ok())) {
+        None => grpc::DEFAULT_PORT,
+        Some(Some(port)) => port,
+        Some(None) => {
+            eprintln!("usage: chess grpc [--port <n>] [--params <file>] [--nnue <file>] [--hash <mb>] ...");
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = grpc::serve(port, load_params(args), load_options(args)) {
+        eprintln!("port {}: {}", port, err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn run_grpc(_args: &[String]) {
+    eprintln!("chess grpc needs a build with the grpc feature");
+    std::process::exit(2);
+}
+
+// Serves games and analysis to web pages over WebSocket until killed.
+#[cfg(feature = "server")]
+fn run_websocket(args: &[String]) {
+    let port = match args.iter().position(|a| a == "--port").map(|i| args.get(i + 1).and_then(|port| port.parse().ok())) {
+        None => websocket::DEFAULT_PORT,
+        Some(Some(port)) => port,
+        Some(None) => {
+            eprintln!("usage: chess websocket [--port <n>] [--params <file>] [--nnue <file>] [--hash <mb>] ...");
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = websocket::serve(port, load_params(args), load_options(args)) {
+        eprintln!("port {}: {}", port, err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn run_websocket(_args: &[String]) {
+    eprintln!("chess websocket needs a build with the server feature");
+    std::process::exit(2);
+}
+
+// Runs a test suite of EPD records, searching each position and checking
+// the move found against its `bm` and `am` operations. With `--annotate`,
+// writes the records back out with the engine's `bm` and `ce`.
+fn run_epd(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!("usage: chess epd <suite.epd> [--depth <plies>] [--movetime <ms>] [--annotate <file>]");
+        std::process::exit(2);
+    };
+    let option = |flag: &str| args.iter().position(|a| a == flag).map(|i| args.get(i + 1).unwrap_or_else(|| usage()));
+    let number = |flag: &str| option(flag).map(|n| n.parse::<u64>().unwrap_or_else(|_| usage()));
+    let Some(path) = args.get(2).filter(|path| !path.starts_with("--")) else {
+        usage();
+    };
+    let move_time = number("--movetime").map(Duration::from_millis);
+    // Without either limit, a fixed depth keeps the suite quick.
+    let depth = number("--depth").map(|depth| depth as u32).or(if move_time.is_some() { None } else { Some(EPD_DEPTH) });
+    let text = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    });
+
+    let mut annotated = vec![];
+    let (mut solved, mut total) = (0, 0);
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let mut record = match epd::Epd::parse(line) {
+            Ok(record) => record,
+            Err(err) => {
+                eprintln!("{}:{}: {}", path, number + 1, err);
+                continue;
+            }
+        };
+        let mut searcher = Searcher::new();
+        let result = searcher.search(&record.game, &SearchLimits { depth, move_time, ..SearchLimits::default() });
+        let Some(best) = result.best_move else {
+            continue;
+        };
+        total += 1;
+        let passed = record.is_solved_by(best);
+        if passed {
+            solved += 1;
+        }
+        let expected: Vec<String> = ["bm", "am"]
+            .into_iter()
+            .filter_map(|opcode| record.get(opcode).map(|moves| format!("{} {}", opcode, moves.join(" "))))
+            .collect();
+        println!(
+            "{} {}: {} {:+} ({})",
+            if passed { "pass" } else { "FAIL" },
+            record.id().map_or_else(|| format!("line {}", number + 1), str::to_string),
+            san::format(&record.game, best),
+            result.score,
+            expected.join(", ")
+        );
+        record.set_best_moves(&[best]);
+        record.set_centipawns(result.score);
+        annotated.push(record.to_string());
+    }
+    println!("Solved {} of {}", solved, total);
+
+    if let Some(out) = option("--annotate") {
+        if let Err(err) = fs::write(out, annotated.join("\n") + "\n") {
+            eprintln!("{}: {}", out, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Moves `chess eval` lists.
+const EVAL_CANDIDATES: usize = 3;
+// Plies `chess epd` searches each position without other limits.
+const EPD_DEPTH: u32 = 6;
+// The clock `chess match` plays on without other limits.
+const MATCH_CLOCK: (Duration, Duration) = (Duration::from_secs(10), Duration::from_millis(100));
+
+const USAGE: &str = "usage: chess [--human white|black] [--depth <plies>] [--movetime <ms>] [--threads <n>] [--hash <mb>] [--contempt <cp>] [--skill <0-20>] [--clock <s>[+<inc>]] [--variety <cp> [--seed <n>]] [--params <file>] [--nnue <file>] [--pgn <file>]";
+
+fn main() {
+    magic::init();
+
+    let args: Vec<String> = std::env::args().collect();
+
+    // `perft <depth> [--threads <n>]` counts the move tree from the start
+    // position, printing the count below each first move.
+    if args.get(1).map(String::as_str) == Some("perft") {
+        let depth = args.get(2).and_then(|d| d.parse().ok());
+        let threads = match args.iter().position(|a| a == "--threads") {
+            Some(i) => args.get(i + 1).and_then(|n| n.parse().ok()),
+            None => Some(std::thread::available_parallelism().map_or(1, |n| n.get())),
+        };
+        let (Some(depth), Some(threads)) = (depth, threads) else {
+            eprintln!("usage: chess perft <depth> [--threads <n>]");
+            std::process::exit(2);
+        };
+        run_perft(&Game::new(), depth, threads);
+        return;
+    }
+
+    // `eval ["<fen>"] [--params <file>]` prints a position, or else the
+    // start position, as FEN and Shredder-FEN, its evaluation and its best
+    // moves.
+    if args.get(1).map(String::as_str) == Some("eval") {
+        run_eval(&args);
+        return;
+    }
+
+    // `epd <suite.epd> [--depth <plies>] [--movetime <ms>] [--annotate
+    // <file>]` runs a test suite, searching each position for its best
+    // move.
+    if args.get(1).map(String::as_str) == Some("epd") {
+        run_epd(&args);
+        return;
+    }
+
+    // `replay <games.pgn> [--game <n>]` plays through a game saved as PGN.
+    if args.get(1).map(String::as_str) == Some("replay") {
+        run_replay(&args);
+        return;
+    }
+
+    // `fetch <url|id> [--player <name>] [--out <file>]` downloads a game
+    // from Lichess or Chess.com as PGN, for `replay` and other tools.
+    if args.get(1).map(String::as_str) == Some("fetch") {
+        run_fetch(&args);
+        return;
+    }
+
+    // `lichess-bot [--token <token>] [--games <n>] [--threads <n>]
+    // [--params <file>]` plays on Lichess as a bot account, taking on
+    // challenges to standard games.
+    if args.get(1).map(String::as_str) == Some("lichess-bot") {
+        run_lichess_bot(&args);
+        return;
+    }
+
+    // `host [--port <n>] [--color white|black] [--clock <s>[+<inc>]]
+    // [--fen "<fen>"] [--ai]` waits for an opponent to connect over TCP
+    // and plays them, and `connect <host>[:<port>] [--ai]` joins such a
+    // game. `--ai` has the engine play instead of the terminal.
+    if args.get(1).map(String::as_str) == Some("host") {
+        run_host(&args);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("connect") {
+        run_connect(&args);
+        return;
+    }
+
+    // `dgt <port> [--color white|black] [--fen "<fen>"] [--pgn <file>]`
+    // plays the engine over the board on a DGT electronic board connected
+    // to the serial port `port`, taking the engine's flags as `--ai` does
+    // for `host`.
+    if args.get(1).map(String::as_str) == Some("dgt") {
+        run_dgt(&args);
+        return;
+    }
+
+    // `match <engine> <engine> [--games <n>] [--clock <s>[+<inc>]]
+    // [--movetime <ms>] [--depth <plies>] [--openings <file>] [--option
+    // <name>=<value>]... [--pgn <file>]` plays two UCI engines, each a
+    // command or "self" for this one, against each other.
+    if args.get(1).map(String::as_str) == Some("match") {
+        run_match(&args);
+        return;
+    }
+
+    // `uci [--params <file>] [--log <file>] [--syzygy <path>] [--hash <mb>]
+    // [--threads <n>] ...` speaks the Universal Chess Interface on standard
+    // input and output, for chess GUIs, starting from the engine options
+    // given as flags.
+    if args.get(1).map(String::as_str) == Some("uci") {
+        // `--log <file>` records the traffic with the GUI, added to the
+        // end of `file`.
+        let log = match args.iter().position(|a| a == "--log").map(|i| args.get(i + 1)) {
+            None => None,
+            Some(Some(path)) => Some(fs::OpenOptions::new().create(true).append(true).open(path).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path, err);
+                std::process::exit(1);
+            })),
+            Some(None) => {
+                eprintln!("usage: chess uci [--params <file>] [--log <file>] [--syzygy <path>] [--hash <mb>] [--threads <n>] ...");
+                std::process::exit(2);
+            }
+        };
+        uci::run(load_params(&args), load_options(&args), load_tablebase(&args), log);
+        return;
+    }
+
+    // `serve [--port <n>] [--params <file>] [options]` answers an HTTP API with
+    // JSON, for creating games, playing moves and asking the engine.
+    if args.get(1).map(String::as_str) == Some("serve") {
+        run_serve(&args);
+        return;
+    }
+
+    // `grpc [--port <n>] [--params <file>] [options]` serves evaluation, streamed
+    // searches and batch analysis over gRPC.
+    if args.get(1).map(String::as_str) == Some("grpc") {
+        run_grpc(&args);
+        return;
+    }
+
+    // `websocket [--port <n>] [--params <file>] [options]` serves games, legal moves
+    // and live analysis to browser frontends as JSON over WebSocket.
+    if args.get(1).map(String::as_str) == Some("websocket") {
+        run_websocket(&args);
+        return;
+    }
+
+    // `tune <positions> [--params <file>] [--epochs <n>] [--threads <n>]
+    // [--out <file>]` fits the evaluation weights, starting from those in
+    // `--params`, to a file of positions labelled with their games'
+    // results and writes them to `--out`.
+    if args.get(1).map(String::as_str) == Some("tune") {
+        run_tune(&args);
+        return;
+    }
+
+    // `bookgen <games.pgn>... --out <book.bin> [--depth <plies>]
+    // [--min-games <n>]` builds a Polyglot opening book from the first
+    // plies of the games, weighting each move by how it scored.
+    if args.get(1).map(String::as_str) == Some("bookgen") {
+        run_bookgen(&args);
+        return;
+    }
+
+    // `pack <games.pgn>... --out <file>` stores games in the compact binary
+    // format of binary.rs, and `unpack <file> [--out <games.pgn>]` turns
+    // them back into PGN. `pack --fen <fen>` and `unpack --hex <hex>` do
+    // the same for a single position, in hexadecimal.
+    if args.get(1).map(String::as_str) == Some("pack") {
+        run_pack(&args);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("unpack") {
+        run_unpack(&args);
+        return;
+    }
+
+    let mut game = Game::new();
+    let game_limit = 300; // 5 minutes in seconds
+    let move_limit = 40;  // 20 moves per side
+
+    // `--human white` or `--human black` plays that side from the terminal.
+    let human = match args.iter().position(|a| a == "--human").map(|i| args.get(i + 1).map(String::as_str)) {
+        None => None,
+        Some(Some("white")) => Some(Color::White),
+        Some(Some("black")) => Some(Color::Black),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // `--depth <plies>` sets how far ahead the machine players look,
+    // `--movetime <ms>` how long they may think about each move and
+    // `--threads <n>` how many threads they think with, and the other
+    // engine options (see options.rs) set up their searchers. `--variety
+    // <cp>` lets them play moves up to that many centipawns worse than the
+    // best, chosen by `--seed <n>` or else differently every run.
+    let number = |flag: &str| match args.iter().position(|a| a == flag).map(|i| args.get(i + 1).and_then(|n| n.parse().ok())) {
+        None => None,
+        Some(Some(n)) => Some(n),
+        Some(None) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    // `--clock <seconds>[+<increment>]` gives each side a clock, which the
+    // time manager spends.
+    let clock = match args.iter().position(|a| a == "--clock").map(|i| args.get(i + 1).and_then(|c| parse_clock(c))) {
+        None => None,
+        Some(Some(clock)) => Some(clock),
+        Some(None) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    // On a clock, time rather than depth limits the search by default.
+    let default_depth = if clock.is_some() { 64 } else { 3 };
+    let ai = AiSettings {
+        depth: number("--depth").map_or(default_depth, |depth| depth as u32),
+        move_time: number("--movetime").map(Duration::from_millis),
+        options: load_options(&args),
+        variety: number("--variety").map_or(0, |variety| variety as i32),
+        seed: number("--seed").unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+        }),
+        clock,
+        // `--params <file>` loads the hand-crafted evaluation's weights.
+        evaluation: Arc::new(load_params(&args)),
+    };
+
+    // `--nnue <file>`, the EvalFile option, evaluates with a neural
+    // network instead of the hand-crafted evaluation, for the board's
+    // `eval` as well as the searches.
+    game.set_network(ai.options.network());
+
+    // `--pgn <file>` writes the game to `file` as PGN once it is over.
+    let pgn = match args.iter().position(|a| a == "--pgn").map(|i| args.get(i + 1)) {
+        None => None,
+        Some(Some(path)) => Some(path),
+        Some(None) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let outcome = game.play(game_limit, move_limit, human, ai);
+
+    if let Some(path) = pgn {
+        let player = |color| if human == Some(color) { "Human" } else { "ChessInRust" };
+        let tags = pgn::Tags::roster(player(Color::White), player(Color::Black), Some(outcome.result));
+        let record = pgn::PgnGame::new(tags, game, Some(outcome.result));
+        if let Err(err) = fs::File::create(path).and_then(|mut file| pgn::write(&mut file, &record)) {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_for_is_from_the_side_given() {
+        // White is a queen up, with either side to move.
+        for fen in ["4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "4k3/8/8/8/8/8/8/3QK3 b - - 0 1"] {
+            let game = Game::from_fen(fen).unwrap();
+            let white = game.evaluate_absolute();
+            assert!(white > 500, "{}: {}", fen, white);
+            assert_eq!(game.evaluate_for(Color::White), white);
+            assert_eq!(game.evaluate_for(Color::Black), -white);
+            assert_eq!(game.evaluate_for(game.turn), eval::evaluate_with(&game, &eval::Parameters::DEFAULT, &mut PawnTable::new(0)));
+        }
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 112",
+        ] {
+            assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn fen_after_moves() {
+        let mut game = Game::new();
+        for uci in ["e2e4", "c7c5", "g1f3"] {
+            let mv = game.parse_uci(uci).unwrap();
+            game.play_move(mv);
+        }
+        let fen = "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+    }
+}
@@ -0,0 +1,263 @@
+// Over-the-board play against the engine on a DGT electronic board,
+// connected over its serial or USB port and built with the `dgt` feature,
+// which brings in `serialport`.
+//
+// The board speaks a byte protocol at 9600 baud. It is sent single-byte
+// commands, and answers with messages whose first byte is the message's
+// code with the top bit set and whose next two are its length, header
+// included, seven bits at a time:
+//
+//     0x40  reset               -> nothing
+//     0x42  send the board      -> 0x86 board dump, a piece code for each
+//                                  square from a8 along each rank to h1
+//     0x44  send updates        -> 0x8e field update, a square and the piece
+//                                  code now on it, whenever one changes
+//
+// The board only knows which piece stands on which square, not what was
+// played, so a move is recognised once the pieces stand as they would
+// after it: lifting a piece, or taking off the one it captures, matches
+// no position and is waited out. Castle by moving the king first, as DGT
+// asks, or moving the rook alone is taken for a rook move. The engine's
+// replies are announced, and the game waits for them to be made on the
+// board.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crate::{AiSettings, Board, BoardStyle, ChessPiece, Color, Game, GameOutcome, Move, Piece};
+
+const BAUD_RATE: u32 = 9600;
+// How long a read waits before the driver asks for the board again, in
+// case an update went missing.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+const RESET: u8 = 0x40;
+const SEND_BOARD: u8 = 0x42;
+const SEND_UPDATES: u8 = 0x44;
+
+const MESSAGE_BIT: u8 = 0x80;
+const BOARD_DUMP: u8 = MESSAGE_BIT | 0x06;
+const FIELD_UPDATE: u8 = MESSAGE_BIT | 0x0e;
+// Longer than any message the board sends in the modes used here.
+const MAX_MESSAGE: usize = 256;
+
+#[derive(Debug)]
+pub enum DgtError {
+    Io(io::Error),
+    // A message that makes no sense, such as a square past h1.
+    Protocol(String),
+}
+
+impl fmt::Display for DgtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DgtError::Io(err) => write!(f, "{}", err),
+            DgtError::Protocol(message) => write!(f, "the board sent {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DgtError {}
+
+impl From<io::Error> for DgtError {
+    fn from(err: io::Error) -> DgtError {
+        DgtError::Io(err)
+    }
+}
+
+// A DGT board on `port`, and what it last said stands on it.
+pub struct DgtBoard<P: Read + Write> {
+    port: P,
+    board: Board,
+    // Black at the near end, so the board reports every square mirrored.
+    flipped: bool,
+}
+
+// Opens the board on the serial device `path`, such as /dev/ttyUSB0 or
+// COM3.
+pub fn open(path: &str) -> Result<DgtBoard<Box<dyn serialport::SerialPort>>, DgtError> {
+    let port = serialport::new(path, BAUD_RATE).timeout(POLL_INTERVAL).open().map_err(io::Error::from)?;
+    DgtBoard::new(port)
+}
+
+impl<P: Read + Write> DgtBoard<P> {
+    // Resets the board, reads where the pieces stand and asks for every
+    // change from then on.
+    pub fn new(port: P) -> Result<DgtBoard<P>, DgtError> {
+        let mut board = DgtBoard { port, board: [[None; 8]; 8], flipped: false };
+        board.send(RESET)?;
+        board.refresh()?;
+        board.send(SEND_UPDATES)?;
+        Ok(board)
+    }
+
+    fn send(&mut self, command: u8) -> Result<(), DgtError> {
+        self.port.write_all(&[command])?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    // Asks for the whole board and waits until it comes.
+    fn refresh(&mut self) -> Result<(), DgtError> {
+        self.send(SEND_BOARD)?;
+        while self.read_message()? != Some(BOARD_DUMP) {}
+        Ok(())
+    }
+
+    // Reads one message and applies it to `board`: the message's code, or
+    // None if none came before the port's timeout.
+    fn read_message(&mut self) -> Result<Option<u8>, DgtError> {
+        let mut header = [0; 3];
+        match self.port.read_exact(&mut header[..1]) {
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            result => result?,
+        }
+        // Anything out of step is skipped until the next message starts.
+        if header[0] & MESSAGE_BIT == 0 {
+            return Ok(Some(header[0]));
+        }
+        self.port.read_exact(&mut header[1..])?;
+        let length = ((header[1] as usize) << 7) | header[2] as usize;
+        if !(3..=MAX_MESSAGE).contains(&length) {
+            return Err(DgtError::Protocol(format!("a message {} bytes long", length)));
+        }
+        let mut body = vec![0; length - 3];
+        self.port.read_exact(&mut body)?;
+        match (header[0], body.as_slice()) {
+            (BOARD_DUMP, squares) if squares.len() == 64 => {
+                for (index, &code) in squares.iter().enumerate() {
+                    self.set(index, code)?;
+                }
+            }
+            (FIELD_UPDATE, &[index, code]) => self.set(index as usize, code)?,
+            (BOARD_DUMP | FIELD_UPDATE, _) => return Err(DgtError::Protocol(format!("a message {:#04x} of {} bytes", header[0], length))),
+            _ => {}
+        }
+        Ok(Some(header[0]))
+    }
+
+    // Puts the piece with the board's `code` on the board's square `index`.
+    fn set(&mut self, index: usize, code: u8) -> Result<(), DgtError> {
+        if index >= 64 {
+            return Err(DgtError::Protocol(format!("square {}", index)));
+        }
+        // From a8 along each rank to h1 when White is at the near end.
+        let (rank, file) = if self.flipped { (index / 8, 7 - index % 8) } else { (7 - index / 8, index % 8) };
+        self.board[rank][file] = piece(code)?;
+        Ok(())
+    }
+
+    // Waits for the pieces to stand as in one of `positions`, and returns
+    // its index.
+    fn wait_for(&mut self, positions: &[&Board]) -> Result<usize, DgtError> {
+        loop {
+            if let Some(index) = positions.iter().position(|&position| *position == self.board) {
+                return Ok(index);
+            }
+            if self.read_message()?.is_none() {
+                self.refresh()?;
+            }
+        }
+    }
+
+    // Waits for the pieces to be set up as in `game`, either way round.
+    pub fn wait_for_setup(&mut self, game: &Game) -> Result<(), DgtError> {
+        loop {
+            if self.board == game.board {
+                return Ok(());
+            }
+            if mirrored(&self.board) == game.board {
+                self.flipped = !self.flipped;
+                self.board = game.board;
+                println!("The board is the other way round, with Black at the near end.");
+                return Ok(());
+            }
+            if self.read_message()?.is_none() {
+                self.refresh()?;
+            }
+        }
+    }
+
+    // Waits for a move to be made on the board that is one of `game`'s
+    // legal moves. A pawn promotes to whatever piece is put down for it.
+    pub fn read_move(&mut self, game: &Game) -> Result<Move, DgtError> {
+        let moves: Vec<Move> = game.legal_moves().iter().copied().collect();
+        let positions: Vec<Board> = moves
+            .iter()
+            .map(|&mv| {
+                let mut after = game.clone();
+                after.play_move(mv);
+                after.board
+            })
+            .collect();
+        let index = self.wait_for(&positions.iter().collect::<Vec<_>>())?;
+        Ok(moves[index])
+    }
+}
+
+// The board as seen from the other end.
+fn mirrored(board: &Board) -> Board {
+    let mut mirrored = [[None; 8]; 8];
+    for (rank, row) in board.iter().enumerate() {
+        for (file, square) in row.iter().enumerate() {
+            mirrored[7 - rank][7 - file] = *square;
+        }
+    }
+    mirrored
+}
+
+// The piece a board's piece code stands for.
+fn piece(code: u8) -> Result<Option<ChessPiece>, DgtError> {
+    let (piece, color) = match code {
+        0x00 => return Ok(None),
+        0x01 => (Piece::Pawn, Color::White),
+        0x02 => (Piece::Rook, Color::White),
+        0x03 => (Piece::Knight, Color::White),
+        0x04 => (Piece::Bishop, Color::White),
+        0x05 => (Piece::King, Color::White),
+        0x06 => (Piece::Queen, Color::White),
+        0x07 => (Piece::Pawn, Color::Black),
+        0x08 => (Piece::Rook, Color::Black),
+        0x09 => (Piece::Knight, Color::Black),
+        0x0a => (Piece::Bishop, Color::Black),
+        0x0b => (Piece::King, Color::Black),
+        0x0c => (Piece::Queen, Color::Black),
+        code => return Err(DgtError::Protocol(format!("piece code {:#04x}", code))),
+    };
+    Ok(Some(ChessPiece { piece, color }))
+}
+
+// Plays `human` on the board against the engine, from the position in
+// `game`, until the game is over.
+pub fn play<P: Read + Write>(board: &mut DgtBoard<P>, game: &mut Game, human: Color, ai: AiSettings) -> Result<GameOutcome, DgtError> {
+    println!("Set up the pieces:");
+    println!("{}", game.render(BoardStyle::default()));
+    board.wait_for_setup(game)?;
+
+    let outcome = loop {
+        if let Some(outcome) = game.outcome() {
+            game.announce(outcome);
+            break outcome;
+        }
+        let mover = game.turn;
+        let mv = if mover == human {
+            println!("{:?} to move on the board...", mover);
+            let mv = board.read_move(game)?;
+            println!("{:?} plays {}", mover, game.numbered_san(mv));
+            mv
+        } else {
+            // A game that isn't over has a move to play.
+            let mv = game.get_ai_move(&ai, None).expect("the game isn't over");
+            println!("{:?} plays {}: make the move on the board.", mover, game.numbered_san(mv));
+            let mut after = game.clone();
+            after.play_move(mv);
+            board.wait_for(&[&after.board])?;
+            mv
+        };
+        game.play_move(mv);
+        println!("{}", game.render(BoardStyle::default()));
+    };
+    println!("Result: {}", outcome.result);
+    Ok(outcome)
+}
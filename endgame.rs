@@ -0,0 +1,281 @@
+// Knowledge of endgames the evaluation would otherwise misjudge.
+//
+// King and pawn against king is looked up in a bitbase: every such
+// position, worked out once by retrograde analysis, as a win or a draw.
+// Bishop and knight against king is a win the evaluation can't see the way
+// to, so the score drives the lone king towards a corner the bishop
+// covers, where it can be mated.
+//
+// Other endings get a scale factor, out of 64, for the stronger side's
+// score: a bishop and rook pawns whose promotion square the bishop
+// doesn't cover are no use against a king in front of them, a minor
+// piece more with no pawns left is seldom enough to win, and opposite
+// coloured bishops leave many pawn endings drawn.
+
+use std::sync::OnceLock;
+
+use crate::attacks;
+use crate::bitboard::{Bitboard, Bitboards};
+use crate::eval;
+use crate::{opponent, Color, File, Piece, Rank, Square};
+
+// A win the search can't see the end of yet, below any mate score.
+pub const KNOWN_WIN: i32 = 10_000;
+const FULL_SCALE: i32 = 64;
+
+// White's score after the endgame knowledge, for the evaluation's own
+// score `white` of a position with `turn` to move.
+pub fn adjust(pieces: &Bitboards, turn: Color, white: i32) -> i32 {
+    let strong = if white >= 0 { Color::White } else { Color::Black };
+    let for_white = |score: i32, color: Color| if color == Color::White { score } else { -score };
+
+    if let Some((color, pawn)) = lone_pawn(pieces) {
+        let score = match kpk_win(pieces, color, pawn, turn) {
+            true => KNOWN_WIN + 10 * advanced(pawn, color),
+            false => 0,
+        };
+        return for_white(score, color);
+    }
+    if let Some(color) = [Color::White, Color::Black].into_iter().find(|&color| is_kbnk(pieces, color)) {
+        return for_white(KNOWN_WIN + mating_drive(pieces, color), color);
+    }
+    white * scale(pieces, strong) / FULL_SCALE
+}
+
+// The pawn and its side, when a king and pawn against a bare king is all
+// there is on the board.
+fn lone_pawn(pieces: &Bitboards) -> Option<(Color, Square)> {
+    let pawns = pieces.piece_type(Piece::Pawn);
+    if pawns.count() != 1 || (pieces.occupied() & !pawns & !pieces.piece_type(Piece::King)).count() != 0 {
+        return None;
+    }
+    let pawn = pawns.first()?;
+    let color = if pieces.color(Color::White).contains(pawn) { Color::White } else { Color::Black };
+    Some((color, pawn))
+}
+
+// `color` has a king, bishop and knight, the other side a bare king.
+fn is_kbnk(pieces: &Bitboards, color: Color) -> bool {
+    pieces.color(color).count() == 3
+        && pieces.pieces(Piece::Bishop, color).count() == 1
+        && pieces.pieces(Piece::Knight, color).count() == 1
+        && pieces.color(opponent(color)).count() == 1
+}
+
+// The closer the lone king is to a corner of the bishop's colour, and the
+// closer the kings are together, the better for `color`.
+fn mating_drive(pieces: &Bitboards, color: Color) -> i32 {
+    let (Some(king), Some(lone_king), Some(bishop)) = (
+        pieces.pieces(Piece::King, color).first(),
+        pieces.pieces(Piece::King, opponent(color)).first(),
+        pieces.pieces(Piece::Bishop, color).first(),
+    ) else {
+        return 0;
+    };
+    let corners = if Bitboard::LIGHT_SQUARES.contains(bishop) { [Square(7), Square(56)] } else { [Square(0), Square(63)] };
+    let corner_distance = corners.iter().map(|&corner| lone_king.distance(corner)).min().unwrap_or(7) as i32;
+    50 * (7 - corner_distance) + 10 * (7 - king.distance(lone_king) as i32)
+}
+
+// How much of the stronger side's score counts, out of FULL_SCALE.
+fn scale(pieces: &Bitboards, strong: Color) -> i32 {
+    let weak = opponent(strong);
+    let strong_pawns = pieces.pieces(Piece::Pawn, strong);
+    let count = |piece: Piece, color: Color| pieces.pieces(piece, color).count();
+    let minors_and_majors =
+        |color: Color| [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].into_iter().map(|piece| count(piece, color)).sum::<u32>();
+    let material =
+        |color: Color| [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen].into_iter().map(|piece| count(piece, color) as i32 * eval::value(piece)).sum::<i32>();
+
+    // Rook pawns, with at most a bishop that can't help them promote,
+    // against a king that has reached the promotion square.
+    if !strong_pawns.is_empty() && minors_and_majors(strong) == count(Piece::Bishop, strong) && count(Piece::Bishop, strong) <= 1 {
+        let rook_file = [File(0), File(7)].into_iter().find(|&file| (strong_pawns & !Bitboard::file(file)).is_empty());
+        if let (Some(file), Some(weak_king)) = (rook_file, pieces.pieces(Piece::King, weak).first()) {
+            let promotion = Square::new(file, if strong == Color::White { Rank(7) } else { Rank(0) });
+            let bishop_covers = pieces.pieces(Piece::Bishop, strong).first().is_some_and(|bishop| {
+                Bitboard::LIGHT_SQUARES.contains(bishop) == Bitboard::LIGHT_SQUARES.contains(promotion)
+            });
+            if !bishop_covers && weak_king.distance(promotion) <= 1 {
+                return 0;
+            }
+        }
+    }
+
+    // No pawns, and no more than a minor piece up.
+    if strong_pawns.is_empty() {
+        if minors_and_majors(strong) == count(Piece::Knight, strong) && count(Piece::Knight, strong) <= 2 && material(weak) == 0 {
+            return 0;
+        }
+        if material(strong) - material(weak) <= eval::value(Piece::Bishop) {
+            return if material(strong) < eval::value(Piece::Rook) {
+                0
+            } else if material(weak) <= eval::value(Piece::Bishop) {
+                4
+            } else {
+                14
+            };
+        }
+    }
+
+    // A bishop each, on opposite colours, and nothing else but pawns.
+    let bishops = pieces.piece_type(Piece::Bishop);
+    if minors_and_majors(strong) == 1
+        && minors_and_majors(weak) == 1
+        && count(Piece::Bishop, strong) == 1
+        && count(Piece::Bishop, weak) == 1
+        && (bishops & Bitboard::LIGHT_SQUARES).count() == 1
+    {
+        return FULL_SCALE / 2;
+    }
+
+    FULL_SCALE
+}
+
+// How many ranks a pawn has come from its own side's back rank.
+fn advanced(square: Square, color: Color) -> i32 {
+    match color {
+        Color::White => square.rank().0 as i32,
+        Color::Black => 7 - square.rank().0 as i32,
+    }
+}
+
+// Whether `color`, with its king and the pawn on `pawn` against the other
+// king, wins with `turn` to move.
+fn kpk_win(pieces: &Bitboards, color: Color, pawn: Square, turn: Color) -> bool {
+    let (Some(strong_king), Some(weak_king)) =
+        (pieces.pieces(Piece::King, color).first(), pieces.pieces(Piece::King, opponent(color)).first())
+    else {
+        return false;
+    };
+    // The bitbase has White with the pawn, on the queen's side of the
+    // board: turn the board round for Black and mirror it for the king's
+    // side.
+    let flip = if color == Color::Black { 56 } else { 0 };
+    let mirror = if pawn.file().0 >= 4 { 7 } else { 0 };
+    let normal = |square: Square| Square(square.0 ^ flip ^ mirror);
+    let table = KPK.get_or_init(generate_kpk);
+    let index = kpk_index(turn == color, normal(strong_king), normal(weak_king), normal(pawn));
+    table[index / 64] & (1 << (index % 64)) != 0
+}
+
+// By side to move, pawn (on files a to d, ranks 2 to 7) and both kings:
+// set for White wins.
+static KPK: OnceLock<Vec<u64>> = OnceLock::new();
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+
+fn kpk_index(white_to_move: bool, white_king: Square, black_king: Square, pawn: Square) -> usize {
+    let pawn = pawn.file().0 as usize * 6 + pawn.rank().0 as usize - 1;
+    ((white_to_move as usize * 24 + pawn) * 64 + white_king.0 as usize) * 64 + black_king.0 as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+// Classifies the positions that are won or drawn outright, then settles
+// the rest a move at a time: White wins when some move wins, Black draws
+// when some move draws. Whatever is still unknown once nothing changes is
+// a draw.
+fn generate_kpk() -> Vec<u64> {
+    let mut outcomes = vec![Outcome::Unknown; KPK_SIZE];
+    let positions = || {
+        [false, true].into_iter().flat_map(|white_to_move| {
+            (0..4).flat_map(move |file| {
+                (1..7).flat_map(move |rank| {
+                    (0..64).flat_map(move |white_king| {
+                        (0..64).map(move |black_king| {
+                            (white_to_move, Square(white_king), Square(black_king), Square::new(File(file), Rank(rank)))
+                        })
+                    })
+                })
+            })
+        })
+    };
+
+    for (white_to_move, white_king, black_king, pawn) in positions() {
+        outcomes[kpk_index(white_to_move, white_king, black_king, pawn)] = initial(white_to_move, white_king, black_king, pawn);
+    }
+    loop {
+        let mut changed = false;
+        for (white_to_move, white_king, black_king, pawn) in positions() {
+            let index = kpk_index(white_to_move, white_king, black_king, pawn);
+            if outcomes[index] == Outcome::Unknown {
+                outcomes[index] = settle(&outcomes, white_to_move, white_king, black_king, pawn);
+                changed |= outcomes[index] != Outcome::Unknown;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut table = vec![0; KPK_SIZE / 64];
+    for (index, outcome) in outcomes.iter().enumerate() {
+        if *outcome == Outcome::Win {
+            table[index / 64] |= 1 << (index % 64);
+        }
+    }
+    table
+}
+
+fn initial(white_to_move: bool, white_king: Square, black_king: Square, pawn: Square) -> Outcome {
+    let pawn_attacks = attacks::pawn_attacks(Color::White, pawn);
+    if white_king == pawn || black_king == pawn || white_king.distance(black_king) <= 1 || (white_to_move && pawn_attacks.contains(black_king)) {
+        return Outcome::Invalid;
+    }
+    if white_to_move && pawn.rank() == Rank(6) {
+        let promotion = Square(pawn.0 + 8);
+        if promotion != white_king && promotion != black_king && (black_king.distance(promotion) > 1 || white_king.distance(promotion) == 1) {
+            return Outcome::Win;
+        }
+    }
+    if !white_to_move {
+        let escapes = attacks::king_attacks(black_king) & !(attacks::king_attacks(white_king) | pawn_attacks);
+        let takes_pawn = black_king.distance(pawn) == 1 && white_king.distance(pawn) > 1;
+        if escapes.is_empty() || takes_pawn {
+            return Outcome::Draw;
+        }
+    }
+    Outcome::Unknown
+}
+
+fn settle(outcomes: &[Outcome], white_to_move: bool, white_king: Square, black_king: Square, pawn: Square) -> Outcome {
+    let mut children = vec![];
+    if white_to_move {
+        let king_moves = attacks::king_attacks(white_king) & !attacks::king_attacks(black_king) & !Bitboard::from_square(pawn);
+        children.extend(king_moves.squares().map(|to| kpk_index(false, to, black_king, pawn)));
+        // Promotions are settled by `initial`.
+        let push = Square(pawn.0 + 8);
+        if pawn.rank() < Rank(6) && push != white_king && push != black_king {
+            children.push(kpk_index(false, white_king, black_king, push));
+            let double = Square(pawn.0 + 16);
+            if pawn.rank() == Rank(1) && double != white_king && double != black_king {
+                children.push(kpk_index(false, white_king, black_king, double));
+            }
+        }
+    } else {
+        let guarded = attacks::king_attacks(white_king) | attacks::pawn_attacks(Color::White, pawn) | Bitboard::from_square(pawn);
+        let king_moves = attacks::king_attacks(black_king) & !guarded;
+        children.extend(king_moves.squares().map(|to| kpk_index(true, white_king, to, pawn)));
+    }
+
+    let (good, bad) = if white_to_move { (Outcome::Win, Outcome::Draw) } else { (Outcome::Draw, Outcome::Win) };
+    let outcomes = children.iter().map(|&child| outcomes[child]).filter(|&outcome| outcome != Outcome::Invalid);
+    let mut all_bad = true;
+    for outcome in outcomes {
+        if outcome == good {
+            return good;
+        }
+        all_bad &= outcome == bad;
+    }
+    if all_bad {
+        bad
+    } else {
+        Outcome::Unknown
+    }
+}
@@ -0,0 +1,245 @@
+// Extended Position Description (EPD), the format of test suites and
+// annotated positions: the first four fields of a FEN (placement, side to
+// move, castling and en passant) followed by operations, each an opcode,
+// its operands and a semicolon:
+//
+//     r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5; id "Ruy Lopez";
+//
+// The opcodes understood here are `bm` (best moves) and `am` (moves to
+// avoid), with moves in SAN, `id` (the position's name), `ce` (an
+// evaluation in centipawns for the side to move), and `hmvc` and `fmvn`,
+// which stand in for FEN's move counters. Any others are kept as they
+// are, so records pass through unchanged.
+
+use std::fmt;
+
+use crate::san::{self, SanError};
+use crate::{FenError, Game, Move};
+
+#[derive(Clone)]
+pub struct Epd {
+    pub game: Game,
+    // In the order given, opcodes unique.
+    operations: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    // Fewer than the four position fields.
+    MissingField,
+    Position(FenError),
+    // An operation without its semicolon, or a string left open.
+    Unterminated(String),
+    // An operand that should be a number and isn't, by opcode.
+    BadNumber(String),
+    // A `bm` or `am` move that isn't legal in the position.
+    BadMove(SanError),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::MissingField => write!(f, "fewer than the four position fields"),
+            EpdError::Position(err) => write!(f, "{}", err),
+            EpdError::Unterminated(opcode) => write!(f, "operation {} has no closing semicolon", opcode),
+            EpdError::BadNumber(opcode) => write!(f, "operation {} needs a number", opcode),
+            EpdError::BadMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl From<FenError> for EpdError {
+    fn from(err: FenError) -> EpdError {
+        EpdError::Position(err)
+    }
+}
+
+impl Epd {
+    // A record for the position in `game`, without any operations.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn new(game: Game) -> Epd {
+        Epd { game, operations: vec![] }
+    }
+
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let line = line.trim();
+        let mut rest = line;
+        let mut fields = vec![];
+        for _ in 0..4 {
+            let (field, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return Err(EpdError::MissingField);
+            }
+            fields.push(field);
+            rest = after.trim_start();
+        }
+        let operations = parse_operations(rest)?;
+
+        let operand = |opcode: &str| operations.iter().find(|(op, _)| op == opcode).and_then(|(_, operands)| operands.first());
+        let mut fen = fields.join(" ");
+        let counters = [operand("hmvc").map_or("0", String::as_str), operand("fmvn").map_or("1", String::as_str)];
+        for (opcode, counter) in ["hmvc", "fmvn"].into_iter().zip(counters) {
+            if counter.parse::<u32>().is_err() {
+                return Err(EpdError::BadNumber(opcode.to_string()));
+            }
+            fen.push(' ');
+            fen.push_str(counter);
+        }
+        let epd = Epd { game: Game::from_fen(&fen)?, operations };
+        epd.best_moves()?;
+        epd.avoid_moves()?;
+        epd.centipawns().transpose()?;
+        Ok(epd)
+    }
+
+    // The operands of `opcode`, if the record has it.
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    // Sets `opcode`'s operands, in its old place if it was there already
+    // and otherwise last.
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|(op, _)| op == opcode) {
+            Some((_, old)) => *old = operands,
+            None => self.operations.push((opcode.to_string(), operands)),
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("id").and_then(|operands| operands.first()).map(String::as_str)
+    }
+
+    // The `bm` moves: any of them solves the position.
+    pub fn best_moves(&self) -> Result<Vec<Move>, EpdError> {
+        self.moves("bm")
+    }
+
+    // The `am` moves: playing any of them fails.
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, EpdError> {
+        self.moves("am")
+    }
+
+    // The `ce` evaluation, in centipawns for the side to move.
+    pub fn centipawns(&self) -> Option<Result<i32, EpdError>> {
+        let operand = self.get("ce")?.first()?;
+        Some(operand.parse().map_err(|_| EpdError::BadNumber("ce".to_string())))
+    }
+
+    pub fn set_best_moves(&mut self, moves: &[Move]) {
+        let sans = moves.iter().map(|&mv| san::format(&self.game, mv)).collect();
+        self.set("bm", sans);
+    }
+
+    pub fn set_centipawns(&mut self, centipawns: i32) {
+        self.set("ce", vec![centipawns.to_string()]);
+    }
+
+    // Whether playing `mv` solves the position: it is one of the `bm`
+    // moves, if there are any, and none of the `am` moves.
+    pub fn is_solved_by(&self, mv: Move) -> bool {
+        let best = self.best_moves().unwrap_or_default();
+        let avoid = self.avoid_moves().unwrap_or_default();
+        (best.is_empty() || best.contains(&mv)) && !avoid.contains(&mv)
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, EpdError> {
+        let operands = self.get(opcode).unwrap_or_default();
+        operands.iter().map(|san| san::parse(&self.game, san).map_err(EpdError::BadMove)).collect()
+    }
+}
+
+// The position's four fields and the operations, with `hmvc` and `fmvn`
+// standing for the counters when they aren't the defaults.
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fen = self.game.to_fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        write!(f, "{}", fields[..4].join(" "))?;
+        let mut counters = vec![];
+        if self.get("hmvc").is_none() && self.game.halfmove_clock != 0 {
+            counters.push(("hmvc".to_string(), vec![self.game.halfmove_clock.to_string()]));
+        }
+        if self.get("fmvn").is_none() && self.game.fullmove_number != 1 {
+            counters.push(("fmvn".to_string(), vec![self.game.fullmove_number.to_string()]));
+        }
+        for (opcode, operands) in self.operations.iter().chain(&counters) {
+            write!(f, " {}", opcode)?;
+            for operand in operands {
+                if is_text(opcode) || operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"') {
+                    write!(f, " \"{}\"", operand.replace('"', "'"))?;
+                } else {
+                    write!(f, " {}", operand)?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+// Opcodes whose operands are strings: the id and the comments.
+fn is_text(opcode: &str) -> bool {
+    opcode == "id" || (opcode.len() == 2 && opcode.starts_with('c') && opcode.as_bytes()[1].is_ascii_digit())
+}
+
+// `opcode operand ...;` repeated, with strings in double quotes.
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations: Vec<(String, Vec<String>)> = vec![];
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(operations);
+        }
+        let opcode: String = std::iter::from_fn(|| chars.next_if(|&c| !c.is_whitespace() && c != ';')).collect();
+        let mut operands = vec![];
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                None => return Err(EpdError::Unterminated(opcode)),
+                Some(';') => break,
+                Some('"') => {
+                    let mut operand = String::new();
+                    loop {
+                        match chars.next() {
+                            None => return Err(EpdError::Unterminated(opcode)),
+                            Some('"') => break,
+                            Some(c) => operand.push(c),
+                        }
+                    }
+                    operands.push(operand);
+                }
+                Some(c) => {
+                    let rest: String = std::iter::from_fn(|| chars.next_if(|&c| !c.is_whitespace() && c != ';')).collect();
+                    operands.push(format!("{}{}", c, rest));
+                }
+            }
+        }
+        match operations.iter_mut().find(|(op, _)| *op == opcode) {
+            Some((_, old)) => *old = operands,
+            None => operations.push((opcode, operands)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_record_round_trip() {
+        let game = Game::new();
+        let e4 = game.parse_uci("e2e4").unwrap();
+        let mut record = Epd::new(game);
+        assert_eq!(record.to_string(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -");
+        record.set_best_moves(&[e4]);
+        record.set("id", vec!["start".to_string()]);
+        let parsed = Epd::parse(&record.to_string()).unwrap();
+        assert_eq!(parsed.best_moves(), Ok(vec![e4]));
+        assert_eq!(parsed.id(), Some("start"));
+        assert_eq!(parsed.to_string(), record.to_string());
+    }
+}
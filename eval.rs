@@ -0,0 +1,1104 @@
+// Static evaluation: how good a position looks without searching it, in
+// centipawns. The search wants it from the point of view of the side to
+// move (`evaluate_with`), positive when that side is better; a display
+// wants it from White's (`absolute_with`, or `Game::evaluate_absolute`),
+// positive when White is better whoever is to move. `for_side` turns one
+// into the other.
+//
+// Each piece counts its material value plus a bonus or penalty for the
+// square it stands on, from a piece-square table: knights are worth more
+// in the centre than on the rim, pawns more the further they advance, the
+// king more tucked away behind its pawns.
+//
+// Every term has a middlegame and an endgame value, blended by how much
+// material is left (a tapered evaluation), so that for instance the king
+// shelters while queens and rooks are about and comes out to the centre
+// once they are gone.
+//
+// On top of that comes the pawn structure, from `pawns`, and the safety
+// of each king: the pawns sheltering it, files opened up beside it, and
+// the enemy pieces bearing down on the squares around it. An attack
+// grows much more dangerous with each piece that joins it, so the
+// attackers' weight is scaled by how many there are.
+//
+// Pieces are also worth more the more squares they can safely move to
+// (mobility): squares held by their own side or attacked by an enemy pawn
+// don't count.
+//
+// Two bishops together cover both square colours and are worth more than
+// the sum of each. Knights get better and bishops worse the more pawns
+// are left to block the board, and a bishop hemmed in by its own pawns
+// on its own square colour is a bad one.
+//
+// Rooks want files free of their own pawns, the seventh rank where the
+// enemy pawns and king are, and each other's support.
+//
+// Last of all `endgame` corrects the score of endings the terms misjudge.
+//
+// The values all live in `Parameters`, which can be written to and read
+// back from a TOML or JSON file.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Neg, Sub, SubAssign};
+use std::path::Path;
+
+use crate::attacks;
+use crate::bitboard::Bitboard;
+use crate::endgame;
+use crate::pawns::{self, PawnTable};
+use crate::{opponent, Color, File, Game, Piece, Rank, Square};
+
+// A piece's worth in centipawns. The king is never traded off, so it has
+// no material value.
+pub const fn value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 300,
+        Piece::Bishop => 300,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+// A term's middlegame and endgame values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const ZERO: Score = Score::new(0, 0);
+
+    pub const fn new(mg: i32, eg: i32) -> Score {
+        Score { mg, eg }
+    }
+
+    // The blend for a game `phase` of MAX_PHASE (all pieces on) down to 0.
+    pub fn taper(self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, rhs: Score) -> Score {
+        Score::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, rhs: Score) -> Score {
+        Score::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, rhs: i32) -> Score {
+        Score::new(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sum for Score {
+    fn sum<I: Iterator<Item = Score>>(iter: I) -> Score {
+        iter.fold(Score::ZERO, Add::add)
+    }
+}
+
+// Piece-square tables, laid out as the board looks from White's side: the
+// first row is the eighth rank, the last the first. Pawns and the king
+// have separate middlegame and endgame tables; the other pieces' squares
+// matter much the same throughout.
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+// In the endgame a pawn is worth more the closer it is to promoting.
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+// Castled behind its pawns while there are pieces to attack it...
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// ...and in the centre once there aren't, where it helps its pawns.
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// The tables by Piece::ALL, paired up.
+const PST: [[Score; 64]; 6] = [
+    pair(&PAWN_MG, &PAWN_EG),
+    pair(&ROOK, &ROOK),
+    pair(&KNIGHT, &KNIGHT),
+    pair(&BISHOP, &BISHOP),
+    pair(&QUEEN, &QUEEN),
+    pair(&KING_MG, &KING_EG),
+];
+
+const fn pair(middlegame: &[i32; 64], endgame: &[i32; 64]) -> [Score; 64] {
+    let mut table = [Score::ZERO; 64];
+    let mut square = 0;
+    while square < 64 {
+        table[square] = Score::new(middlegame[square], endgame[square]);
+        square += 1;
+    }
+    table
+}
+
+// Material in each phase, by Piece::ALL. Knights lose a little as the
+// board empties and the long-range pieces and pawns gain; `value` stays
+// the plain exchange value.
+const MATERIAL: [Score; 6] = [
+    Score::new(100, 120),
+    Score::new(500, 530),
+    Score::new(300, 290),
+    Score::new(300, 310),
+    Score::new(900, 930),
+    Score::ZERO,
+];
+
+// How much each piece counts towards the middlegame: all of them on the
+// board add up to MAX_PHASE, none (bar kings and pawns) to 0.
+const PHASE: [i32; 6] = [0, 2, 1, 1, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// Per file on or beside the king's: a shield pawn that has moved up
+// two squares or more, or is gone altogether...
+const SHIELD_ADVANCED: Score = Score::new(-10, 0);
+const SHIELD_MISSING: Score = Score::new(-25, 0);
+// ...and a file without pawns of the king's side, or without any.
+const SEMI_OPEN_FILE: Score = Score::new(-10, 0);
+const OPEN_FILE: Score = Score::new(-20, 0);
+// Per square of the king zone a piece attacks, by Piece::ALL.
+const ATTACK_UNITS: [Score; 6] = [
+    Score::ZERO,
+    Score::new(-40, 0),
+    Score::new(-20, 0),
+    Score::new(-20, 0),
+    Score::new(-80, 0),
+    Score::ZERO,
+];
+// The percentage of the attack units that counts, by how many pieces
+// attack the zone: one piece alone is rarely a threat.
+const ATTACKERS_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+// Per safe square a piece can move to, by Piece::ALL, counted from a
+// typical number of such squares so that an average piece scores 0.
+const MOBILITY: [Score; 6] = [
+    Score::ZERO,
+    Score::new(2, 4),
+    Score::new(4, 4),
+    Score::new(5, 5),
+    Score::new(1, 2),
+    Score::ZERO,
+];
+const TYPICAL_MOBILITY: [i32; 6] = [0, 7, 4, 6, 13, 0];
+
+// For a pair of bishops on opposite colours.
+const BISHOP_PAIR: Score = Score::new(30, 50);
+// Per knight, and taken off per bishop, for each pawn on the board above
+// TYPICAL_PAWNS.
+const KNIGHT_PAWNS: Score = Score::new(2, 3);
+const TYPICAL_PAWNS: i32 = 10;
+// Per own pawn on a bishop's square colour; a blocked one counts twice,
+// as it can't move off.
+const BAD_BISHOP: Score = Score::new(-2, -4);
+
+// A rook on a file without pawns of its own side, or without any.
+const ROOK_SEMI_OPEN_FILE: Score = Score::new(10, 5);
+const ROOK_OPEN_FILE: Score = Score::new(25, 10);
+// On the seventh rank, from its own side's point of view, while the enemy
+// has pawns there or its king on the eighth.
+const ROOK_ON_SEVENTH: Score = Score::new(20, 30);
+// Two rooks defending each other along a rank or file.
+const CONNECTED_ROOKS: Score = Score::new(15, 5);
+
+// Everything the evaluation is built from: the weights, every Score above
+// that a term counts some number of times, and the numbers that shape the
+// terms (the game phase, the typical mobility and pawn count, how the king
+// attackers scale). DEFAULT holds the values set by hand; `tune` fits the
+// weights to game results, and `load` reads a file of either, so they can
+// be tried out with `--params <file>` without rebuilding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    pub material: [Score; 6],
+    // By Piece::ALL, laid out as the tables above.
+    pub squares: [[Score; 64]; 6],
+    pub shield_advanced: Score,
+    pub shield_missing: Score,
+    pub semi_open_file: Score,
+    pub open_file: Score,
+    pub attack_units: [Score; 6],
+    pub mobility: [Score; 6],
+    pub bishop_pair: Score,
+    pub knight_pawns: Score,
+    pub bad_bishop: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_open_file: Score,
+    pub rook_on_seventh: Score,
+    pub connected_rooks: Score,
+    pub doubled: Score,
+    pub isolated: Score,
+    pub backward: Score,
+    pub passed: [Score; 8],
+    pub passed_own_king: Score,
+    pub passed_enemy_king: Score,
+    // The evaluation's shape, which `tune` leaves alone.
+    pub phase: [i32; 6],
+    pub attackers_scale: [i32; 8],
+    pub typical_mobility: [i32; 6],
+    pub typical_pawns: i32,
+    pub passed_king_weight: [i32; 8],
+}
+
+impl Parameters {
+    pub const DEFAULT: Parameters = Parameters {
+        material: MATERIAL,
+        squares: PST,
+        shield_advanced: SHIELD_ADVANCED,
+        shield_missing: SHIELD_MISSING,
+        semi_open_file: SEMI_OPEN_FILE,
+        open_file: OPEN_FILE,
+        attack_units: ATTACK_UNITS,
+        mobility: MOBILITY,
+        bishop_pair: BISHOP_PAIR,
+        knight_pawns: KNIGHT_PAWNS,
+        bad_bishop: BAD_BISHOP,
+        rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+        rook_open_file: ROOK_OPEN_FILE,
+        rook_on_seventh: ROOK_ON_SEVENTH,
+        connected_rooks: CONNECTED_ROOKS,
+        doubled: pawns::DOUBLED,
+        isolated: pawns::ISOLATED,
+        backward: pawns::BACKWARD,
+        passed: pawns::PASSED,
+        passed_own_king: pawns::PASSED_OWN_KING,
+        passed_enemy_king: pawns::PASSED_ENEMY_KING,
+        phase: PHASE,
+        attackers_scale: ATTACKERS_SCALE,
+        typical_mobility: TYPICAL_MOBILITY,
+        typical_pawns: TYPICAL_PAWNS,
+        passed_king_weight: pawns::PASSED_KING_WEIGHT,
+    };
+
+    // Reads parameters written by `write`, or the same keys and values as
+    // a JSON object. Fields the file leaves out keep their default values.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Parameters> {
+        Parameters::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Parameters> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut params = Parameters::DEFAULT;
+        let mut fields = params.fields();
+        let mut tokens = tokenize(text).into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let Token::Key(key) = token else {
+                return Err(invalid("expected a key".to_string()));
+            };
+            let value = parse_value(&mut tokens).ok_or_else(|| invalid(format!("{}: expected a number or list", key)))?;
+            let Some((_, field)) = fields.iter_mut().find(|(name, _)| *name == key) else {
+                return Err(invalid(format!("unknown parameter: {}", key)));
+            };
+            if !field.set(&value) {
+                return Err(invalid(format!("{}: wrong shape of value", key)));
+            }
+        }
+        Ok(params)
+    }
+
+    // Writes the parameters out as TOML, one key per field, each weight a
+    // [middlegame, endgame] pair.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "# Evaluation parameters, the weights as [middlegame, endgame] centipawns.")?;
+        // `fields` lends the fields out mutably, for `parse`; a copy will
+        // do for reading them.
+        let mut params = self.clone();
+        for (key, field) in params.fields() {
+            match field {
+                Field::Score(score) => writeln!(out, "{} = [{}, {}]", key, score.mg, score.eg)?,
+                Field::Scores(scores, per_line) => {
+                    writeln!(out, "{} = [", key)?;
+                    for line in scores.chunks(per_line) {
+                        let pairs: Vec<String> = line.iter().map(|score| format!("[{}, {}]", score.mg, score.eg)).collect();
+                        writeln!(out, "    {},", pairs.join(", "))?;
+                    }
+                    writeln!(out, "]")?;
+                }
+                Field::Number(number) => writeln!(out, "{} = {}", key, number)?,
+                Field::Numbers(numbers) => {
+                    let numbers: Vec<String> = numbers.iter().map(i32::to_string).collect();
+                    writeln!(out, "{} = [{}]", key, numbers.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Every field by its key in a parameters file.
+    fn fields(&mut self) -> Vec<(String, Field<'_>)> {
+        let mut fields = vec![("material".to_string(), Field::Scores(&mut self.material, 6))];
+        for (piece, squares) in Piece::ALL.into_iter().zip(self.squares.iter_mut()) {
+            fields.push((format!("{}_squares", piece_name(piece)), Field::Scores(squares, 8)));
+        }
+        let named = [
+            ("shield_advanced", Field::Score(&mut self.shield_advanced)),
+            ("shield_missing", Field::Score(&mut self.shield_missing)),
+            ("semi_open_file", Field::Score(&mut self.semi_open_file)),
+            ("open_file", Field::Score(&mut self.open_file)),
+            ("attack_units", Field::Scores(&mut self.attack_units, 6)),
+            ("mobility", Field::Scores(&mut self.mobility, 6)),
+            ("bishop_pair", Field::Score(&mut self.bishop_pair)),
+            ("knight_pawns", Field::Score(&mut self.knight_pawns)),
+            ("bad_bishop", Field::Score(&mut self.bad_bishop)),
+            ("rook_semi_open_file", Field::Score(&mut self.rook_semi_open_file)),
+            ("rook_open_file", Field::Score(&mut self.rook_open_file)),
+            ("rook_on_seventh", Field::Score(&mut self.rook_on_seventh)),
+            ("connected_rooks", Field::Score(&mut self.connected_rooks)),
+            ("doubled", Field::Score(&mut self.doubled)),
+            ("isolated", Field::Score(&mut self.isolated)),
+            ("backward", Field::Score(&mut self.backward)),
+            ("passed", Field::Scores(&mut self.passed, 8)),
+            ("passed_own_king", Field::Score(&mut self.passed_own_king)),
+            ("passed_enemy_king", Field::Score(&mut self.passed_enemy_king)),
+            ("phase", Field::Numbers(&mut self.phase)),
+            ("attackers_scale", Field::Numbers(&mut self.attackers_scale)),
+            ("typical_mobility", Field::Numbers(&mut self.typical_mobility)),
+            ("typical_pawns", Field::Number(&mut self.typical_pawns)),
+            ("passed_king_weight", Field::Numbers(&mut self.passed_king_weight)),
+        ];
+        fields.extend(named.into_iter().map(|(key, field)| (key.to_string(), field)));
+        fields
+    }
+}
+
+// A field of the Parameters, for reading and writing files. Lists of
+// scores are written `per_line` to a line.
+enum Field<'a> {
+    Score(&'a mut Score),
+    Scores(&'a mut [Score], usize),
+    Number(&'a mut i32),
+    Numbers(&'a mut [i32]),
+}
+
+impl Field<'_> {
+    // Whether `value` had the field's shape and was stored.
+    fn set(&mut self, value: &Value) -> bool {
+        let pair = |value: &Value| match value {
+            Value::List(pair) => match pair[..] {
+                [Value::Number(mg), Value::Number(eg)] => Some(Score::new(mg, eg)),
+                _ => None,
+            },
+            Value::Number(_) => None,
+        };
+        let number = |value: &Value| match value {
+            Value::Number(number) => Some(*number),
+            Value::List(_) => None,
+        };
+        let list = |value: &Value, len: usize| match value {
+            Value::List(values) if values.len() == len => Some(values.clone()),
+            _ => None,
+        };
+        match self {
+            Field::Score(score) => pair(value).map(|value| **score = value).is_some(),
+            Field::Number(field) => number(value).map(|value| **field = value).is_some(),
+            Field::Scores(scores, _) => match list(value, scores.len()).and_then(|values| values.iter().map(pair).collect::<Option<Vec<_>>>()) {
+                Some(values) => {
+                    scores.copy_from_slice(&values);
+                    true
+                }
+                None => false,
+            },
+            Field::Numbers(numbers) => match list(value, numbers.len()).and_then(|values| values.iter().map(number).collect::<Option<Vec<_>>>()) {
+                Some(values) => {
+                    numbers.copy_from_slice(&values);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Number(i32),
+    List(Vec<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Key(String),
+    Number(i32),
+    Open,
+    Close,
+    // Anything else, which no key or value starts with.
+    Stray(char),
+}
+
+// Splits a parameters file into keys, numbers and brackets. Braces,
+// commas, colons, equals signs and `#` comments only separate them, so
+// TOML and JSON read alike.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '[' => tokens.push(Token::Open),
+                ']' => tokens.push(Token::Close),
+                '{' | '}' | ',' | ':' | '=' => {}
+                c if c.is_whitespace() => {}
+                '"' => {
+                    let key: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                    tokens.push(Token::Key(key));
+                }
+                c if c == '-' || c.is_ascii_digit() => {
+                    let mut number = c.to_string();
+                    while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        number.push(c);
+                        chars.next();
+                    }
+                    tokens.push(number.parse().map_or(Token::Stray(c), Token::Number));
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut key = c.to_string();
+                    while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                        key.push(c);
+                        chars.next();
+                    }
+                    tokens.push(Token::Key(key));
+                }
+                c => tokens.push(Token::Stray(c)),
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_value(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Option<Value> {
+    match tokens.next()? {
+        Token::Number(number) => Some(Value::Number(number)),
+        Token::Open => {
+            let mut values = vec![];
+            while tokens.peek() != Some(&Token::Close) {
+                values.push(parse_value(tokens)?);
+            }
+            tokens.next();
+            Some(Value::List(values))
+        }
+        Token::Key(_) | Token::Close | Token::Stray(_) => None,
+    }
+}
+
+impl Default for Parameters {
+    fn default() -> Parameters {
+        Parameters::DEFAULT
+    }
+}
+
+fn piece_name(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "pawn",
+        Piece::Rook => "rook",
+        Piece::Knight => "knight",
+        Piece::Bishop => "bishop",
+        Piece::Queen => "queen",
+        Piece::King => "king",
+    }
+}
+
+// One weight in the Parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weight {
+    Material(Piece),
+    // A piece-square table entry, by index into the table.
+    Square(Piece, usize),
+    ShieldAdvanced,
+    ShieldMissing,
+    SemiOpenFile,
+    OpenFile,
+    AttackUnits(Piece),
+    Mobility(Piece),
+    BishopPair,
+    KnightPawns,
+    BadBishop,
+    RookSemiOpenFile,
+    RookOpenFile,
+    RookOnSeventh,
+    ConnectedRooks,
+    Doubled,
+    Isolated,
+    Backward,
+    Passed(usize),
+    PassedOwnKing,
+    PassedEnemyKing,
+}
+
+impl Weight {
+    // Every weight, in the order of the Parameters' fields.
+    pub fn all() -> Vec<Weight> {
+        let mut weights: Vec<Weight> = Piece::ALL.into_iter().map(Weight::Material).collect();
+        weights.extend(Piece::ALL.into_iter().flat_map(|piece| (0..64).map(move |index| Weight::Square(piece, index))));
+        weights.extend([Weight::ShieldAdvanced, Weight::ShieldMissing, Weight::SemiOpenFile, Weight::OpenFile]);
+        weights.extend(Piece::ALL.into_iter().map(Weight::AttackUnits));
+        weights.extend(Piece::ALL.into_iter().map(Weight::Mobility));
+        weights.extend([
+            Weight::BishopPair,
+            Weight::KnightPawns,
+            Weight::BadBishop,
+            Weight::RookSemiOpenFile,
+            Weight::RookOpenFile,
+            Weight::RookOnSeventh,
+            Weight::ConnectedRooks,
+            Weight::Doubled,
+            Weight::Isolated,
+            Weight::Backward,
+        ]);
+        weights.extend((0..8).map(Weight::Passed));
+        weights.extend([Weight::PassedOwnKing, Weight::PassedEnemyKing]);
+        weights
+    }
+}
+
+impl Index<Weight> for Parameters {
+    type Output = Score;
+
+    fn index(&self, weight: Weight) -> &Score {
+        match weight {
+            Weight::Material(piece) => &self.material[piece.index()],
+            Weight::Square(piece, index) => &self.squares[piece.index()][index],
+            Weight::ShieldAdvanced => &self.shield_advanced,
+            Weight::ShieldMissing => &self.shield_missing,
+            Weight::SemiOpenFile => &self.semi_open_file,
+            Weight::OpenFile => &self.open_file,
+            Weight::AttackUnits(piece) => &self.attack_units[piece.index()],
+            Weight::Mobility(piece) => &self.mobility[piece.index()],
+            Weight::BishopPair => &self.bishop_pair,
+            Weight::KnightPawns => &self.knight_pawns,
+            Weight::BadBishop => &self.bad_bishop,
+            Weight::RookSemiOpenFile => &self.rook_semi_open_file,
+            Weight::RookOpenFile => &self.rook_open_file,
+            Weight::RookOnSeventh => &self.rook_on_seventh,
+            Weight::ConnectedRooks => &self.connected_rooks,
+            Weight::Doubled => &self.doubled,
+            Weight::Isolated => &self.isolated,
+            Weight::Backward => &self.backward,
+            Weight::Passed(advanced) => &self.passed[advanced],
+            Weight::PassedOwnKing => &self.passed_own_king,
+            Weight::PassedEnemyKing => &self.passed_enemy_king,
+        }
+    }
+}
+
+impl IndexMut<Weight> for Parameters {
+    fn index_mut(&mut self, weight: Weight) -> &mut Score {
+        match weight {
+            Weight::Material(piece) => &mut self.material[piece.index()],
+            Weight::Square(piece, index) => &mut self.squares[piece.index()][index],
+            Weight::ShieldAdvanced => &mut self.shield_advanced,
+            Weight::ShieldMissing => &mut self.shield_missing,
+            Weight::SemiOpenFile => &mut self.semi_open_file,
+            Weight::OpenFile => &mut self.open_file,
+            Weight::AttackUnits(piece) => &mut self.attack_units[piece.index()],
+            Weight::Mobility(piece) => &mut self.mobility[piece.index()],
+            Weight::BishopPair => &mut self.bishop_pair,
+            Weight::KnightPawns => &mut self.knight_pawns,
+            Weight::BadBishop => &mut self.bad_bishop,
+            Weight::RookSemiOpenFile => &mut self.rook_semi_open_file,
+            Weight::RookOpenFile => &mut self.rook_open_file,
+            Weight::RookOnSeventh => &mut self.rook_on_seventh,
+            Weight::ConnectedRooks => &mut self.connected_rooks,
+            Weight::Doubled => &mut self.doubled,
+            Weight::Isolated => &mut self.isolated,
+            Weight::Backward => &mut self.backward,
+            Weight::Passed(advanced) => &mut self.passed[advanced],
+            Weight::PassedOwnKing => &mut self.passed_own_king,
+            Weight::PassedEnemyKing => &mut self.passed_enemy_king,
+        }
+    }
+}
+
+// Where the evaluation's terms go as it works through a position: each is
+// a weight counted some number of times for one side. Summing them gives
+// the score (`Totals`); the tuner records the counts instead. How the
+// counts come out depends on the parameters' shape fields, such as
+// `typical_mobility`, so every collector has parameters too.
+pub trait Terms {
+    fn params(&self) -> &Parameters;
+
+    fn add(&mut self, color: Color, weight: Weight, count: i32);
+
+    // `percent` per cent of `count` times the weight.
+    fn add_percent(&mut self, color: Color, weight: Weight, count: i32, percent: i32);
+}
+
+// Each side's terms summed with `params`' weights.
+pub struct Totals<'a> {
+    params: &'a Parameters,
+    // By Color::index.
+    sides: [Score; 2],
+}
+
+impl<'a> Totals<'a> {
+    pub fn new(params: &'a Parameters) -> Totals<'a> {
+        Totals { params, sides: [Score::ZERO; 2] }
+    }
+
+    // Positive when it favours White.
+    pub fn white(&self) -> Score {
+        self.sides[Color::White.index()] - self.sides[Color::Black.index()]
+    }
+}
+
+impl Terms for Totals<'_> {
+    fn params(&self) -> &Parameters {
+        self.params
+    }
+
+    fn add(&mut self, color: Color, weight: Weight, count: i32) {
+        self.sides[color.index()] += self.params[weight] * count;
+    }
+
+    fn add_percent(&mut self, color: Color, weight: Weight, count: i32, percent: i32) {
+        let score = self.params[weight] * (count * percent);
+        self.sides[color.index()] += Score::new(score.mg / 100, score.eg / 100);
+    }
+}
+
+// The evaluation's terms grouped for display, by what they are about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    PieceSquares,
+    Pawns,
+    KingSafety,
+    Mobility,
+    MinorPieces,
+    Rooks,
+}
+
+impl Term {
+    pub const ALL: [Term; 7] =
+        [Term::Material, Term::PieceSquares, Term::Pawns, Term::KingSafety, Term::Mobility, Term::MinorPieces, Term::Rooks];
+
+    fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquares => "Piece squares",
+            Term::Pawns => "Pawns",
+            Term::KingSafety => "King safety",
+            Term::Mobility => "Mobility",
+            Term::MinorPieces => "Minor pieces",
+            Term::Rooks => "Rooks",
+        }
+    }
+}
+
+impl Weight {
+    pub fn term(self) -> Term {
+        match self {
+            Weight::Material(_) => Term::Material,
+            Weight::Square(..) => Term::PieceSquares,
+            Weight::ShieldAdvanced | Weight::ShieldMissing | Weight::SemiOpenFile | Weight::OpenFile | Weight::AttackUnits(_) => {
+                Term::KingSafety
+            }
+            Weight::Mobility(_) => Term::Mobility,
+            Weight::BishopPair | Weight::KnightPawns | Weight::BadBishop => Term::MinorPieces,
+            Weight::RookSemiOpenFile | Weight::RookOpenFile | Weight::RookOnSeventh | Weight::ConnectedRooks => Term::Rooks,
+            Weight::Doubled | Weight::Isolated | Weight::Backward | Weight::Passed(_) | Weight::PassedOwnKing | Weight::PassedEnemyKing => {
+                Term::Pawns
+            }
+        }
+    }
+}
+
+// A breakdown of the hand-crafted evaluation: each side's total for every
+// Term, and the game phase they are blended by. Displays as a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace<'a> {
+    params: &'a Parameters,
+    // By Term::ALL, then Color::index.
+    pub terms: [[Score; 2]; 7],
+    pub phase: i32,
+    pub turn: Color,
+    // White's tapered score once the endgame knowledge has had its say.
+    pub adjusted: i32,
+}
+
+impl<'a> Trace<'a> {
+    pub fn new(game: &Game, params: &'a Parameters) -> Trace<'a> {
+        let mut trace = Trace { params, terms: [[Score::ZERO; 2]; 7], phase: phase(game, params), turn: game.turn, adjusted: 0 };
+        collect(game, &mut trace);
+        trace.adjusted = endgame::adjust(&game.bitboards, game.turn, trace.white().taper(trace.phase));
+        trace
+    }
+
+    // Positive when it favours White.
+    pub fn white(&self) -> Score {
+        self.terms.iter().map(|sides| sides[Color::White.index()] - sides[Color::Black.index()]).sum()
+    }
+
+    // The score from the side to move's point of view, as `evaluate_with`
+    // gives it.
+    pub fn score(&self) -> i32 {
+        for_side(self.turn, self.adjusted)
+    }
+}
+
+impl Terms for Trace<'_> {
+    fn params(&self) -> &Parameters {
+        self.params
+    }
+
+    fn add(&mut self, color: Color, weight: Weight, count: i32) {
+        self.terms[weight.term() as usize][color.index()] += self.params[weight] * count;
+    }
+
+    fn add_percent(&mut self, color: Color, weight: Weight, count: i32, percent: i32) {
+        let score = self.params[weight] * (count * percent);
+        self.terms[weight.term() as usize][color.index()] += Score::new(score.mg / 100, score.eg / 100);
+    }
+}
+
+impl fmt::Display for Trace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row = |f: &mut fmt::Formatter, name: &str, white: Score, black: Score| {
+            let cell = |score: Score| format!(" {:>6} {:>6} ", score.mg, score.eg);
+            writeln!(f, "{:<14}|{}|{}|{}", name, cell(white), cell(black), cell(white - black))
+        };
+        writeln!(f, "{:<14}|{:^15}|{:^15}|{:^15}", "Term", "White", "Black", "Total")?;
+        let heading = format!(" {:>6} {:>6} ", "MG", "EG");
+        writeln!(f, "{:<14}|{}|{}|{}", "", heading, heading, heading)?;
+        writeln!(f, "{}", "-".repeat(14 + 3 * 16))?;
+        for term in Term::ALL {
+            let [white, black] = self.terms[term as usize];
+            row(f, term.name(), white, black)?;
+        }
+        writeln!(f, "{}", "-".repeat(14 + 3 * 16))?;
+        let white: Score = self.terms.iter().map(|sides| sides[Color::White.index()]).sum();
+        let black: Score = self.terms.iter().map(|sides| sides[Color::Black.index()]).sum();
+        row(f, "Total", white, black)?;
+        writeln!(f)?;
+        let tapered = self.white().taper(self.phase);
+        if self.adjusted != tapered {
+            writeln!(f, "Endgame knowledge: {:+} for White rather than {:+}", self.adjusted, tapered)?;
+        }
+        write!(f, "Phase {}/{}: {:+} for White, {:+} for {:?} to move", self.phase, MAX_PHASE, self.adjusted, self.score(), self.turn)
+    }
+}
+
+// The score for the side to move with `params`' weights, as the search
+// wants it. `pawns` caches the pawn structure terms (see `pawns.rs`), and
+// must only ever have been used with the same `params`, since the cached
+// pawn structures depend on them. A game with a network loaded is scored
+// by the network instead (see `nnue.rs`).
+pub fn evaluate_with(game: &Game, params: &Parameters, pawns: &mut PawnTable) -> i32 {
+    for_side(game.turn, absolute_with(game, params, pawns))
+}
+
+// The same score from White's point of view whoever is to move, as an
+// evaluation bar shows it.
+pub fn absolute_with(game: &Game, params: &Parameters, pawns: &mut PawnTable) -> i32 {
+    if let Some(nnue) = &game.nnue {
+        return for_side(game.turn, nnue.evaluate(game.turn));
+    }
+    let mut totals = Totals::new(params);
+    pieces(game, &mut totals);
+    // The kings' distance from the passed pawns is added on top of the
+    // cached structure, since the table can't know where the kings stand.
+    let structure = pawns.probe(game.pawn_hash, &game.bitboards, params);
+    pawns::passed_pawn_kings(&game.bitboards, structure.passed, &mut totals);
+    endgame::adjust(&game.bitboards, game.turn, (totals.white() + structure.score).taper(phase(game, params)))
+}
+
+// Turns White's score into `color`'s, or `color`'s into White's.
+pub fn for_side(color: Color, score: i32) -> i32 {
+    match color {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// Hands every term of the hand-crafted evaluation to `terms`, the pawn
+// structure worked out afresh.
+pub fn collect(game: &Game, terms: &mut impl Terms) {
+    pieces(game, terms);
+    let passed = pawns::collect(&game.bitboards, terms);
+    pawns::passed_pawn_kings(&game.bitboards, passed, terms);
+}
+
+// How far the game is from the endgame, by the pieces left on the board.
+pub fn phase(game: &Game, params: &Parameters) -> i32 {
+    let pieces = &game.bitboards;
+    Piece::ALL.into_iter().map(|piece| pieces.piece_type(piece).count() as i32 * params.phase[piece.index()]).sum::<i32>().min(MAX_PHASE)
+}
+
+// Everything but the pawn structure, for both sides.
+fn pieces(game: &Game, terms: &mut impl Terms) {
+    for color in [Color::White, Color::Black] {
+        side(game, color, terms);
+        king_safety(game, color, terms);
+        mobility(game, color, terms);
+        minor_pieces(game, color, terms);
+        rooks(game, color, terms);
+    }
+}
+
+// Material and piece-square bonuses for one side's pieces.
+fn side(game: &Game, color: Color, terms: &mut impl Terms) {
+    for piece in Piece::ALL {
+        for square in game.bitboards.pieces(piece, color).squares() {
+            terms.add(color, Weight::Material(piece), 1);
+            terms.add(color, Weight::Square(piece, table_index(color, square)), 1);
+        }
+    }
+}
+
+// The pawn shelter and open files around `color`'s king, and the enemy
+// pieces attacking the squares next to it. Only the middlegame counts:
+// with the queens off the king is more piece than target.
+fn king_safety(game: &Game, color: Color, terms: &mut impl Terms) {
+    let pieces = &game.bitboards;
+    let Some(king) = pieces.pieces(Piece::King, color).first() else {
+        return;
+    };
+    let enemy = opponent(color);
+    let own_pawns = pieces.pieces(Piece::Pawn, color);
+    let all_pawns = pieces.piece_type(Piece::Pawn);
+
+    let files = king.file().0.saturating_sub(1)..=(king.file().0 + 1).min(7);
+    for file in files {
+        let on_file = Bitboard::file(File(file));
+        let sheltered = |ranks: i8| {
+            king.offset(file as i8 - king.file().0 as i8, ranks * pawns::forward(color)).is_some_and(|square| own_pawns.contains(square))
+        };
+        if (own_pawns & on_file).is_empty() {
+            terms.add(color, Weight::ShieldMissing, 1);
+            terms.add(color, if (all_pawns & on_file).is_empty() { Weight::OpenFile } else { Weight::SemiOpenFile }, 1);
+        } else if !sheltered(1) {
+            terms.add(color, if sheltered(2) { Weight::ShieldAdvanced } else { Weight::ShieldMissing }, 1);
+        }
+    }
+
+    let zone = attacks::king_attacks(king) | Bitboard::from_square(king);
+    let occupied = pieces.occupied();
+    let mut hits = [0; 6];
+    let mut attackers = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for square in pieces.pieces(piece, enemy).squares() {
+            let count = (piece_attacks(piece, square, occupied) & zone).count() as i32;
+            if count > 0 {
+                attackers += 1;
+                hits[piece.index()] += count;
+            }
+        }
+    }
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        terms.add_percent(color, Weight::AttackUnits(piece), hits[piece.index()], terms.params().attackers_scale[attackers.min(7)]);
+    }
+}
+
+// The knights', bishops', rooks' and queens' safe squares.
+fn mobility(game: &Game, color: Color, terms: &mut impl Terms) {
+    let pieces = &game.bitboards;
+    let enemy = opponent(color);
+    let pawn_attacks =
+        pieces.pieces(Piece::Pawn, enemy).squares().fold(Bitboard::EMPTY, |attacked, square| attacked | attacks::pawn_attacks(enemy, square));
+    let safe = !(pieces.color(color) | pawn_attacks);
+    let occupied = pieces.occupied();
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for square in pieces.pieces(piece, color).squares() {
+            let moves = (piece_attacks(piece, square, occupied) & safe).count() as i32;
+            terms.add(color, Weight::Mobility(piece), moves - terms.params().typical_mobility[piece.index()]);
+        }
+    }
+}
+
+// The bishop pair, the knights and bishops against the pawn count, and
+// bad bishops.
+fn minor_pieces(game: &Game, color: Color, terms: &mut impl Terms) {
+    let pieces = &game.bitboards;
+    let bishops = pieces.pieces(Piece::Bishop, color);
+    let own_pawns = pieces.pieces(Piece::Pawn, color);
+
+    if !(bishops & Bitboard::LIGHT_SQUARES).is_empty() && !(bishops & !Bitboard::LIGHT_SQUARES).is_empty() {
+        terms.add(color, Weight::BishopPair, 1);
+    }
+
+    let extra_pawns = pieces.piece_type(Piece::Pawn).count() as i32 - terms.params().typical_pawns;
+    terms.add(color, Weight::KnightPawns, extra_pawns * (pieces.pieces(Piece::Knight, color).count() as i32 - bishops.count() as i32));
+
+    let occupied = pieces.occupied();
+    let blocked = own_pawns.squares().filter(|&square| square.offset(0, pawns::forward(color)).is_some_and(|stop| occupied.contains(stop)));
+    let blocked = blocked.fold(Bitboard::EMPTY, |blocked, square| blocked | Bitboard::from_square(square));
+    for bishop in bishops.squares() {
+        let bishop_squares = if Bitboard::LIGHT_SQUARES.contains(bishop) { Bitboard::LIGHT_SQUARES } else { !Bitboard::LIGHT_SQUARES };
+        terms.add(color, Weight::BadBishop, (own_pawns & bishop_squares).count() as i32 + (blocked & bishop_squares).count() as i32);
+    }
+}
+
+// Rooks on open and semi-open files, on the seventh rank, and connected.
+fn rooks(game: &Game, color: Color, terms: &mut impl Terms) {
+    let pieces = &game.bitboards;
+    let rooks = pieces.pieces(Piece::Rook, color);
+    let enemy = opponent(color);
+    let (seventh, eighth) = match color {
+        Color::White => (Bitboard::rank(Rank(6)), Bitboard::rank(Rank(7))),
+        Color::Black => (Bitboard::rank(Rank(1)), Bitboard::rank(Rank(0))),
+    };
+    let seventh_matters =
+        !(pieces.pieces(Piece::Pawn, enemy) & seventh).is_empty() || !(pieces.pieces(Piece::King, enemy) & eighth).is_empty();
+    let occupied = pieces.occupied();
+
+    for rook in rooks.squares() {
+        let file = Bitboard::file(rook.file());
+        if (pieces.piece_type(Piece::Pawn) & file).is_empty() {
+            terms.add(color, Weight::RookOpenFile, 1);
+        } else if (pieces.pieces(Piece::Pawn, color) & file).is_empty() {
+            terms.add(color, Weight::RookSemiOpenFile, 1);
+        }
+        if seventh_matters && seventh.contains(rook) {
+            terms.add(color, Weight::RookOnSeventh, 1);
+        }
+    }
+
+    if rooks.squares().any(|rook| !(attacks::rook_attacks(rook, occupied) & rooks).is_empty()) {
+        terms.add(color, Weight::ConnectedRooks, 1);
+    }
+}
+
+// The squares a knight, bishop, rook or queen on `square` attacks.
+fn piece_attacks(piece: Piece, square: Square, occupied: Bitboard) -> Bitboard {
+    match piece {
+        Piece::Knight => attacks::knight_attacks(square),
+        Piece::Bishop => attacks::bishop_attacks(square, occupied),
+        Piece::Rook => attacks::rook_attacks(square, occupied),
+        Piece::Queen => attacks::queen_attacks(square, occupied),
+        Piece::Pawn | Piece::King => Bitboard::EMPTY,
+    }
+}
+
+// Where a `color` piece on `square` finds its entry in the piece-square
+// tables. The tables are written for White; Black reads them upside down.
+fn table_index(color: Color, square: Square) -> usize {
+    match color {
+        Color::White => (square.0 ^ 56) as usize,
+        Color::Black => square.0 as usize,
+    }
+}
@@ -0,0 +1,155 @@
+// A C API, for embedding the engine in C and C++ programs and anything
+// else that can call C, built with the `ffi` feature as a `cdylib`. The
+// declarations are in chess.h.
+//
+// A game is an opaque `ChessGame` pointer from `chess_game_new` or
+// `chess_game_from_fen`, to be given back to `chess_game_free`. Moves go in
+// and out as NUL-terminated strings in UCI coordinates ("e2e4", "e7e8q"),
+// though SAN is taken as well. Strings the library returns belong to the
+// caller, who frees them with `chess_string_free`. Nothing here is
+// thread-safe: a game is to be used from one thread at a time.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+use std::time::Duration;
+
+use crate::san;
+use crate::search::{SearchLimits, Searcher};
+use crate::{Game, GameResult, Move, ParseMoveError};
+
+pub const CHESS_ONGOING: c_int = 0;
+pub const CHESS_WHITE_WINS: c_int = 1;
+pub const CHESS_BLACK_WINS: c_int = 2;
+pub const CHESS_DRAW: c_int = 3;
+
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+
+pub struct ChessGame {
+    game: Game,
+    searcher: Searcher,
+}
+
+// A game from the usual starting position.
+#[no_mangle]
+pub extern "C" fn chess_game_new() -> *mut ChessGame {
+    Box::into_raw(Box::new(ChessGame { game: Game::new(), searcher: Searcher::new() }))
+}
+
+// A game from `fen`, or null if it isn't a valid position.
+//
+// Safety: `fen` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_from_fen(fen: *const c_char) -> *mut ChessGame {
+    let Some(fen) = string(fen) else {
+        return ptr::null_mut();
+    };
+    match Game::from_fen(fen) {
+        Ok(game) => Box::into_raw(Box::new(ChessGame { game, searcher: Searcher::new() })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+// Safety: `game` is null or came from this library and hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_free(game: *mut ChessGame) {
+    if !game.is_null() {
+        drop(Box::from_raw(game));
+    }
+}
+
+// The position as FEN.
+//
+// Safety: `game` is a live game from this library.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_fen(game: *const ChessGame) -> *mut c_char {
+    owned((*game).game.to_fen())
+}
+
+// The legal moves, separated by spaces; empty once the game is over.
+//
+// Safety: `game` is a live game from this library.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_legal_moves(game: *const ChessGame) -> *mut c_char {
+    let moves: Vec<String> = (*game).game.legal_moves().iter().map(|mv| mv.to_string()).collect();
+    owned(moves.join(" "))
+}
+
+// Plays `mv`: 0 if it was played, -1 if it isn't a legal move.
+//
+// Safety: `game` is a live game from this library and `mv` is null or a
+// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_make_move(game: *mut ChessGame, mv: *const c_char) -> c_int {
+    let game = &mut (*game).game;
+    match string(mv).and_then(|text| parse_move(game, text)) {
+        Some(mv) => {
+            game.play_move(mv);
+            0
+        }
+        None => -1,
+    }
+}
+
+// The move the engine would play, searching for `movetime_ms`
+// milliseconds or to `depth` plies, whichever comes first; 0 leaves either
+// unlimited, and with both 0 it thinks for a second. Null once the game is
+// over.
+//
+// Safety: `game` is a live game from this library.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_best_move(game: *mut ChessGame, movetime_ms: u32, depth: u32) -> *mut c_char {
+    let ChessGame { game, searcher } = &mut *game;
+    let move_time = match (movetime_ms, depth) {
+        (0, 0) => Some(DEFAULT_MOVE_TIME),
+        (0, _) => None,
+        (ms, _) => Some(Duration::from_millis(ms as u64)),
+    };
+    let limits = SearchLimits { depth: (depth > 0).then_some(depth), move_time, ..SearchLimits::default() };
+    match searcher.search(game, &limits).best_move {
+        Some(mv) => owned(mv.to_string()),
+        None => ptr::null_mut(),
+    }
+}
+
+// CHESS_ONGOING, or how the game ended: CHESS_WHITE_WINS,
+// CHESS_BLACK_WINS or CHESS_DRAW.
+//
+// Safety: `game` is a live game from this library.
+#[no_mangle]
+pub unsafe extern "C" fn chess_game_result(game: *const ChessGame) -> c_int {
+    match (*game).game.outcome().map(|outcome| outcome.result) {
+        None => CHESS_ONGOING,
+        Some(GameResult::WhiteWins) => CHESS_WHITE_WINS,
+        Some(GameResult::BlackWins) => CHESS_BLACK_WINS,
+        Some(GameResult::Draw) => CHESS_DRAW,
+    }
+}
+
+// Safety: `s` is null or a string this library returned, not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn chess_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// Safety: `s` is null or a NUL-terminated string.
+unsafe fn string<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+fn owned(s: String) -> *mut c_char {
+    CString::new(s).expect("no NULs in FEN or moves").into_raw()
+}
+
+// A move in UCI coordinates or, failing that, SAN.
+fn parse_move(game: &Game, text: &str) -> Option<Move> {
+    match game.parse_uci(text) {
+        Ok(mv) => Some(mv),
+        Err(ParseMoveError::Invalid(_)) => san::parse(game, text).ok(),
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,198 @@
+// A gRPC analysis service, for analysis pipelines and other services that
+// would rather have typed messages than JSON, built with the `grpc`
+// feature through `tonic`. build.rs generates the messages and the service
+// from proto/analysis.proto, which documents them:
+//
+//     Evaluate(Position) -> Evaluation          the static evaluation
+//     Search(SearchRequest) -> stream SearchUpdate
+//                                               Info as each line is found,
+//                                               then the BestMove
+//     AnalyzeBatch(BatchRequest) -> stream BatchResult
+//                                               one result per FEN, in order
+//
+// Each search has a searcher of its own on a blocking thread, so that
+// searches don't hold up one another or the runtime, and stops as soon as
+// its client hangs up. At most `SEARCHES` calls search at once; a Search
+// or AnalyzeBatch beyond that fails with RESOURCE_EXHAUSTED.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::api;
+use crate::eval::{self, Parameters};
+use crate::options::EngineOptions;
+use crate::pawns::PawnTable;
+use crate::search::{mate_in, SearchHandle, SearchInfo, SearchLimits, SearchResult, Searcher};
+use crate::Game;
+
+pub mod proto {
+    tonic::include_proto!("chess");
+}
+
+use proto::analysis_server::{Analysis, AnalysisServer};
+use proto::{score, search_update, BatchRequest, BatchResult, BestMove, Evaluation, Info, Position, Score, SearchRequest, SearchUpdate};
+
+pub const DEFAULT_PORT: u16 = 50051;
+// Updates a slow client hasn't read yet before the search waits for it.
+const BUFFER: usize = 64;
+const SEARCHES: usize = 4;
+
+struct Service {
+    evaluation: Arc<Parameters>,
+    // Sets up each search's searcher; see options.rs.
+    options: EngineOptions,
+    // A permit for each search that may run at once.
+    searches: Arc<Semaphore>,
+}
+
+// Serves the analysis service on `port` until the process ends, with
+// searchers set up by `options`.
+pub fn serve(port: u16, evaluation: Parameters, options: EngineOptions) -> io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    println!("Serving gRPC analysis on port {}", port);
+    let service = Service { evaluation: Arc::new(evaluation), options, searches: Arc::new(Semaphore::new(SEARCHES)) };
+    runtime.block_on(Server::builder().add_service(AnalysisServer::new(service)).serve(address)).map_err(io::Error::other)
+}
+
+#[tonic::async_trait]
+impl Analysis for Service {
+    type SearchStream = ReceiverStream<Result<SearchUpdate, Status>>;
+    type AnalyzeBatchStream = ReceiverStream<Result<BatchResult, Status>>;
+
+    async fn evaluate(&self, request: Request<Position>) -> Result<Response<Evaluation>, Status> {
+        let game = position(request.get_ref())?;
+        let white = eval::absolute_with(&game, &self.evaluation, &mut PawnTable::default());
+        Ok(Response::new(Evaluation { white, side_to_move: eval::for_side(game.turn, white) }))
+    }
+
+    async fn search(&self, request: Request<SearchRequest>) -> Result<Response<Self::SearchStream>, Status> {
+        let request = request.into_inner();
+        let game = position(&request.position.unwrap_or_default())?;
+        let (limits, multi_pv) = limits(request.limits.unwrap_or_default());
+        let permit = self.permit()?;
+        let (sender, receiver) = mpsc::channel(BUFFER);
+        let mut searcher = self.searcher(multi_pv);
+        let updates = sender.clone();
+        searcher.on_info(move |info| {
+            updates.blocking_send(Ok(SearchUpdate { update: Some(search_update::Update::Info(info_message(info))) })).ok();
+        });
+        stop_on_hang_up(searcher.prepare(false), &sender);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let result = searcher.search(&game, &limits);
+            sender.blocking_send(Ok(SearchUpdate { update: Some(search_update::Update::BestMove(best_move(&result))) })).ok();
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn analyze_batch(&self, request: Request<BatchRequest>) -> Result<Response<Self::AnalyzeBatchStream>, Status> {
+        let request = request.into_inner();
+        let (limits, multi_pv) = limits(request.limits.unwrap_or_default());
+        let permit = self.permit()?;
+        let (sender, receiver) = mpsc::channel(BUFFER);
+        let mut searcher = self.searcher(multi_pv);
+        stop_on_hang_up(searcher.prepare(false), &sender);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            for fen in request.fens {
+                // A hang-up stops the search in progress, and the client
+                // is checked for between searches, after the flags left by
+                // the last one are cleared, so none is missed.
+                searcher.prepare(false);
+                if sender.is_closed() {
+                    return;
+                }
+                let reply = match Game::from_fen(&fen) {
+                    Ok(game) => BatchResult { result: Some(best_move(&searcher.search(&game, &limits))), fen, error: String::new() },
+                    Err(err) => BatchResult { result: None, fen, error: err.to_string() },
+                };
+                sender.blocking_send(Ok(reply)).ok();
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+impl Service {
+    // A permit for one more search, to hold until it is over; an error
+    // when as many are running as may.
+    fn permit(&self) -> Result<OwnedSemaphorePermit, Status> {
+        Arc::clone(&self.searches).try_acquire_owned().map_err(|_| Status::resource_exhausted("every searcher is busy; try again shortly"))
+    }
+
+    fn searcher(&self, multi_pv: usize) -> Searcher {
+        let mut searcher = self.options.searcher();
+        searcher.set_evaluation(Arc::clone(&self.evaluation));
+        searcher.multi_pv = multi_pv;
+        searcher
+    }
+}
+
+// Stops the searches `handle` steers once the client reading from
+// `sender` has gone.
+fn stop_on_hang_up<T: Send + 'static>(handle: SearchHandle, sender: &mpsc::Sender<T>) {
+    let sender = sender.clone();
+    tokio::spawn(async move {
+        sender.closed().await;
+        handle.stop();
+    });
+}
+
+// The game a Position describes, the moves played from its FEN.
+fn position(position: &Position) -> Result<Game, Status> {
+    let mut game = if position.fen.is_empty() {
+        Game::new()
+    } else {
+        Game::from_fen(&position.fen).map_err(|err| Status::invalid_argument(format!("bad FEN: {}", err)))?
+    };
+    for text in &position.moves {
+        let mv = game.parse_uci(text).map_err(|err| Status::invalid_argument(err.to_string()))?;
+        game.play_move(mv);
+    }
+    Ok(game)
+}
+
+// The search limits and number of lines the request asks for, held to
+// those of `api::search_limits`. Zero leaves a limit unset.
+fn limits(limits: proto::Limits) -> (SearchLimits, usize) {
+    let given = |value: u64| (value > 0).then_some(value);
+    api::search_limits(given(limits.depth as u64), given(limits.nodes), given(limits.movetime_ms), given(limits.multipv as u64))
+}
+
+fn score(score: i32) -> Score {
+    let value = match mate_in(score) {
+        Some(moves) => score::Value::Mate(moves),
+        None => score::Value::Centipawns(score),
+    };
+    Score { value: Some(value) }
+}
+
+fn info_message(info: &SearchInfo) -> Info {
+    Info {
+        depth: info.depth,
+        seldepth: info.seldepth,
+        multipv: info.multi_pv as u32,
+        score: Some(score(info.score)),
+        nodes: info.nodes,
+        nps: info.nps,
+        time_ms: info.time.as_millis() as u64,
+        pv: info.pv.iter().map(|mv| mv.to_string()).collect(),
+    }
+}
+
+fn best_move(result: &SearchResult) -> BestMove {
+    BestMove {
+        best_move: result.best_move.map(|mv| mv.to_string()).unwrap_or_default(),
+        ponder: result.ponder_move().map(|mv| mv.to_string()).unwrap_or_default(),
+        score: Some(score(result.score)),
+        depth: result.depth,
+        nodes: result.nodes,
+    }
+}
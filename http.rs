@@ -0,0 +1,251 @@
+// An HTTP server with a JSON API, for driving games and the engine from
+// any language, built with the `server` feature, which brings in
+// `tiny_http` as well. Games are kept in memory, shared by every client,
+// and numbered from 1; one nobody has asked about for `GAME_EXPIRY` is
+// forgotten, as is the longest untouched once there are `MAX_GAMES`.
+//
+//     POST /games                  {"fen": "<fen>"}, optional
+//                                  -> 201, the game (see `api::game`)
+//     GET  /games/<id>             -> the game: FEN, moves, legal moves
+//     GET  /games/<id>/legal_moves[?from=<square>]
+//                                  -> [{"uci", "san", "from", "to"}, ...]
+//     POST /games/<id>/moves       {"move": "e2e4"}, in UCI or SAN
+//                                  -> the game after it
+//     POST /games/<id>/bestmove    {"depth": 12, "movetime": 2000,
+//                                   "nodes": 1000000, "multipv": 3}, each
+//                                  optional -> {"move", "ponder", "score",
+//                                  "depth", "nodes", "lines": [{"score",
+//                                  "pv"}, ...]}
+//     GET  /games/<id>/pgn         -> the game as PGN
+//
+// Errors come back with a 4xx status and {"error": "..."}. Searches are
+// held to the limits in `api::search_limits`, so that one request can't
+// take the server over. Requests
+// are answered by `WORKERS` threads, and at most `SEARCHES` searches run at
+// once, each with a searcher, and its hash table, kept from one request to
+// the next; a bestmove request beyond that gets 503.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::api;
+use crate::eval::Parameters;
+use crate::options::EngineOptions;
+use crate::pgn::{self, PgnGame, Tags};
+use crate::search::Searcher;
+use crate::{Game, Square};
+
+pub const DEFAULT_PORT: u16 = 8080;
+// Bigger request bodies are refused unread.
+const MAX_BODY: u64 = 64 * 1024;
+const WORKERS: usize = 16;
+const SEARCHES: usize = 4;
+const MAX_GAMES: usize = 10_000;
+const GAME_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+struct State {
+    games: Mutex<Games>,
+    // The searchers not in use.
+    searchers: Mutex<Vec<Searcher>>,
+}
+
+struct Games {
+    games: HashMap<u64, StoredGame>,
+    next_id: u64,
+}
+
+struct StoredGame {
+    game: Game,
+    // When a request last asked about it.
+    touched: Instant,
+}
+
+impl Games {
+    // Makes room for another game.
+    fn prune(&mut self) {
+        self.games.retain(|_, stored| stored.touched.elapsed() < GAME_EXPIRY);
+        if self.games.len() >= MAX_GAMES {
+            let oldest = self.games.iter().min_by_key(|(_, stored)| stored.touched).map(|(&id, _)| id);
+            self.games.remove(&oldest.expect("there are games"));
+        }
+    }
+}
+
+// What a request gets back.
+enum Reply {
+    Json(u16, Value),
+    Pgn(String),
+}
+
+// A request that can't be answered, with its status.
+struct ApiError(u16, String);
+
+impl ApiError {
+    fn bad_request(message: impl ToString) -> ApiError {
+        ApiError(400, message.to_string())
+    }
+}
+
+// Answers requests on `port` for ever, with searchers set up by `options`.
+pub fn serve(port: u16, evaluation: Parameters, options: EngineOptions) -> io::Result<()> {
+    let server = Server::http(("0.0.0.0", port)).map_err(io::Error::other)?;
+    println!("Serving the HTTP API on port {}", port);
+    let evaluation = Arc::new(evaluation);
+    let searchers = (0..SEARCHES)
+        .map(|_| {
+            let mut searcher = options.searcher();
+            searcher.set_evaluation(Arc::clone(&evaluation));
+            searcher
+        })
+        .collect();
+    let games = Mutex::new(Games { games: HashMap::new(), next_id: 1 });
+    let state = State { games, searchers: Mutex::new(searchers) };
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    respond(&state, request);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(state: &State, mut request: Request) {
+    let reply = read_body(&mut request).and_then(|body| handle(state, request.method(), request.url(), &body));
+    let response = match reply {
+        Ok(Reply::Json(status, value)) => {
+            Response::from_string(value.to_string()).with_status_code(status).with_header(content_type("application/json"))
+        }
+        Ok(Reply::Pgn(text)) => Response::from_string(text).with_header(content_type("application/x-chess-pgn")),
+        Err(ApiError(status, message)) => Response::from_string(json!({"error": message}).to_string())
+            .with_status_code(status)
+            .with_header(content_type("application/json")),
+    };
+    if let Err(err) = request.respond(response) {
+        eprintln!("{}", err);
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("a valid header")
+}
+
+// The request's body as JSON; null if it has none.
+fn read_body(request: &mut Request) -> Result<Value, ApiError> {
+    if request.body_length().is_some_and(|length| length as u64 > MAX_BODY) {
+        return Err(ApiError(413, "request body too large".to_string()));
+    }
+    let mut body = String::new();
+    request.as_reader().take(MAX_BODY).read_to_string(&mut body).map_err(ApiError::bad_request)?;
+    if body.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&body).map_err(|err| ApiError::bad_request(format!("bad JSON: {}", err)))
+}
+
+fn handle(state: &State, method: &Method, url: &str, body: &Value) -> Result<Reply, ApiError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let not_found = || ApiError(404, format!("no such resource {}", path));
+    let id = match segments[..] {
+        ["games"] => {
+            if *method != Method::Post {
+                return Err(ApiError(405, "use POST to create a game".to_string()));
+            }
+            let game = match body["fen"].as_str() {
+                Some(fen) => Game::from_fen(fen).map_err(ApiError::bad_request)?,
+                None => Game::new(),
+            };
+            let mut games = state.games.lock().expect("no thread panics holding the games");
+            games.prune();
+            let id = games.next_id;
+            games.next_id += 1;
+            let reply = api::game(id, &game);
+            games.games.insert(id, StoredGame { game, touched: Instant::now() });
+            return Ok(Reply::Json(201, reply));
+        }
+        ["games", id, ..] => id.parse::<u64>().map_err(|_| not_found())?,
+        _ => return Err(not_found()),
+    };
+    // A copy, so that searches don't hold the lock.
+    let game = state.games.lock().expect("no thread panics holding the games").games.get_mut(&id).map(|stored| {
+        stored.touched = Instant::now();
+        stored.game.clone()
+    });
+    let game = game.ok_or_else(|| ApiError(404, format!("no game {}", id)))?;
+
+    match (method, &segments[2..]) {
+        (Method::Get, []) => Ok(Reply::Json(200, api::game(id, &game))),
+        (Method::Get, ["legal_moves"]) => {
+            let from = match query.split('&').find_map(|pair| pair.strip_prefix("from=")) {
+                Some(square) => Some(square.parse::<Square>().map_err(ApiError::bad_request)?),
+                None => None,
+            };
+            let moves: Vec<Value> = game
+                .legal_moves()
+                .iter()
+                .filter(|mv| from.is_none_or(|from| mv.from == from))
+                .map(|&mv| api::legal_move(&game, mv))
+                .collect();
+            Ok(Reply::Json(200, Value::Array(moves)))
+        }
+        (Method::Post, ["moves"]) => {
+            let text = body["move"].as_str().ok_or_else(|| ApiError::bad_request("no move"))?;
+            if game.outcome().is_some() {
+                return Err(ApiError(409, "the game is over".to_string()));
+            }
+            let mv = api::parse_move(&game, text).map_err(ApiError::bad_request)?;
+            let played = game.moves.len();
+            let mut games = state.games.lock().expect("no thread panics holding the games");
+            let game = &mut games.games.get_mut(&id).ok_or_else(|| ApiError(404, format!("no game {}", id)))?.game;
+            // Another client may have moved in the meantime.
+            if game.moves.len() != played {
+                return Err(ApiError(409, "the game has moved on".to_string()));
+            }
+            game.play_move(mv);
+            Ok(Reply::Json(200, api::game(id, game)))
+        }
+        (Method::Post, ["bestmove"]) => {
+            let number = |field: &str| body[field].as_u64();
+            let (limits, multi_pv) = api::search_limits(number("depth"), number("nodes"), number("movetime"), number("multipv"));
+            let searcher = state.searchers.lock().expect("no thread panics holding the searchers").pop();
+            let mut searcher = searcher.ok_or_else(|| ApiError(503, "every searcher is busy; try again shortly".to_string()))?;
+            searcher.multi_pv = multi_pv;
+            let result = searcher.search(&game, &limits);
+            state.searchers.lock().expect("no thread panics holding the searchers").push(searcher);
+            let lines: Vec<Value> = result
+                .lines
+                .iter()
+                .map(|line| json!({"score": api::score(line.score), "pv": line.moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>()}))
+                .collect();
+            Ok(Reply::Json(
+                200,
+                json!({
+                    "move": result.best_move.map(|mv| mv.to_string()),
+                    "ponder": result.ponder_move().map(|mv| mv.to_string()),
+                    "score": api::score(result.score),
+                    "depth": result.depth,
+                    "nodes": result.nodes,
+                    "lines": lines,
+                }),
+            ))
+        }
+        (Method::Get, ["pgn"]) => {
+            let result = game.outcome().map(|outcome| outcome.result);
+            let record = PgnGame::new(Tags::roster("?", "?", result), game, result);
+            let mut text = vec![];
+            pgn::write(&mut text, &record).expect("writing to memory doesn't fail");
+            Ok(Reply::Pgn(String::from_utf8(text).expect("PGN is written as UTF-8")))
+        }
+        (_, [] | ["legal_moves"] | ["moves"] | ["bestmove"] | ["pgn"]) => Err(ApiError(405, format!("{} not allowed on {}", method, path))),
+        _ => Err(not_found()),
+    }
+}
@@ -0,0 +1,236 @@
+// Playing on Lichess as a bot account through its Bot API, built with the
+// `online` feature like `online`.
+//
+// The bot follows the account's event stream: challenges to standard
+// chess, from the usual position or a set-up one, are accepted while it
+// has a game to spare and the rest declined; each game that starts is
+// played on a thread of its own. A game's stream gives the moves so far
+// and both clocks whenever a move is made, and the bot answers on its
+// turns with a search budgeted from its clock. It greets its opponent in
+// the chat when a game starts and thanks them when it ends.
+//
+// The API token must belong to a bot account and have the bot:play scope.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::eval::Parameters;
+use crate::options::EngineOptions;
+use crate::search::SearchLimits;
+use crate::timeman::TimeControl;
+use crate::{Color, Game};
+
+const LICHESS: &str = "https://lichess.org";
+// Lichess sends an empty line every few seconds to keep streams open, so
+// a read this long in coming means the connection is gone.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+// The most to think about a move, whatever the clock allows, and all the
+// time a move gets in a game without a clock.
+const MAX_MOVE_TIME: Duration = Duration::from_secs(30);
+const GREETING: &str = "Good luck, and have fun!";
+const FAREWELL: &str = "Thanks for the game!";
+
+#[derive(Debug, Clone)]
+pub struct BotSettings {
+    pub evaluation: Arc<Parameters>,
+    // Each game's searcher's; see options.rs.
+    pub options: EngineOptions,
+    // Games to play at once; challenges beyond that are declined.
+    pub max_games: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotError {
+    // The request failed, or Lichess turned it down.
+    Http(String),
+    // A reply that isn't what the API promises.
+    Protocol(String),
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BotError::Http(message) => write!(f, "{}", message),
+            BotError::Protocol(message) => write!(f, "unexpected reply from Lichess: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BotError {}
+
+impl From<ureq::Error> for BotError {
+    fn from(err: ureq::Error) -> BotError {
+        BotError::Http(err.to_string())
+    }
+}
+
+impl From<io::Error> for BotError {
+    fn from(err: io::Error) -> BotError {
+        BotError::Http(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(err: serde_json::Error) -> BotError {
+        BotError::Protocol(err.to_string())
+    }
+}
+
+// Makes the account's requests, with its token.
+#[derive(Clone)]
+struct Client {
+    agent: ureq::Agent,
+    authorization: String,
+}
+
+impl Client {
+    fn new(token: &str) -> Client {
+        let agent = ureq::AgentBuilder::new().timeout_read(READ_TIMEOUT).user_agent("ChessInRust").build();
+        Client { agent, authorization: format!("Bearer {}", token) }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        self.agent.request(method, &format!("{}{}", LICHESS, path)).set("Authorization", &self.authorization)
+    }
+
+    fn get(&self, path: &str) -> Result<Value, BotError> {
+        Ok(serde_json::from_str(&self.request("GET", path).call()?.into_string()?)?)
+    }
+
+    fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<(), BotError> {
+        self.request("POST", path).send_form(form)?;
+        Ok(())
+    }
+
+    // The events of a newline-delimited JSON stream, as they come, without
+    // the empty keep-alive lines.
+    fn stream(&self, path: &str) -> Result<impl Iterator<Item = Result<Value, BotError>>, BotError> {
+        let reader = BufReader::new(self.request("GET", path).call()?.into_reader());
+        Ok(reader
+            .lines()
+            .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?)))
+    }
+
+    fn chat(&self, game_id: &str, text: &str) {
+        if let Err(err) = self.post(&format!("/api/bot/game/{}/chat", game_id), &[("room", "player"), ("text", text)]) {
+            eprintln!("{}: chat: {}", game_id, err);
+        }
+    }
+}
+
+// Plays as the bot account whose API token is `token` until the event
+// stream ends.
+pub fn run(token: &str, settings: BotSettings) -> Result<(), BotError> {
+    let client = Client::new(token);
+    let account = client.get("/api/account")?;
+    let me = account["id"].as_str().ok_or_else(|| BotError::Protocol("no account id".to_string()))?.to_string();
+    println!("Playing on Lichess as {}", account["username"].as_str().unwrap_or(&me));
+
+    let playing = Arc::new(AtomicUsize::new(0));
+    for event in client.stream("/api/stream/event")? {
+        let event = event?;
+        match event["type"].as_str() {
+            Some("challenge") => {
+                let challenge = &event["challenge"];
+                let Some(id) = challenge["id"].as_str() else {
+                    continue;
+                };
+                // The bot's own challenges to others come through too.
+                if challenge["challenger"]["id"].as_str() == Some(me.as_str()) {
+                    continue;
+                }
+                let decline = if !matches!(challenge["variant"]["key"].as_str(), Some("standard" | "fromPosition")) {
+                    Some("variant")
+                } else if playing.load(Ordering::Relaxed) >= settings.max_games {
+                    Some("later")
+                } else {
+                    None
+                };
+                let answered = match decline {
+                    Some(reason) => client.post(&format!("/api/challenge/{}/decline", id), &[("reason", reason)]),
+                    None => client.post(&format!("/api/challenge/{}/accept", id), &[]),
+                };
+                // The challenger may have withdrawn it in the meantime.
+                if let Err(err) = answered {
+                    eprintln!("challenge {}: {}", id, err);
+                }
+            }
+            Some("gameStart") => {
+                let Some(id) = event["game"]["gameId"].as_str().or(event["game"]["id"].as_str()).map(str::to_string) else {
+                    continue;
+                };
+                let (client, me, settings, playing) = (client.clone(), me.clone(), settings.clone(), Arc::clone(&playing));
+                playing.fetch_add(1, Ordering::Relaxed);
+                thread::spawn(move || {
+                    println!("{}: game started", id);
+                    if let Err(err) = play(&client, &id, &me, &settings) {
+                        eprintln!("{}: {}", id, err);
+                    }
+                    println!("{}: game over", id);
+                    playing.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Plays the game `id` to its end.
+fn play(client: &Client, id: &str, me: &str, settings: &BotSettings) -> Result<(), BotError> {
+    let mut searcher = settings.options.searcher();
+    searcher.set_evaluation(Arc::clone(&settings.evaluation));
+    let mut start = Game::new();
+    let mut color = None;
+
+    for event in client.stream(&format!("/api/bot/game/stream/{}", id))? {
+        let event = event?;
+        let state = match event["type"].as_str() {
+            Some("gameFull") => {
+                color = Some(if event["white"]["id"].as_str() == Some(me) { Color::White } else { Color::Black });
+                if let Some(fen) = event["initialFen"].as_str().filter(|&fen| fen != "startpos") {
+                    start = Game::from_fen(fen).map_err(|err| BotError::Protocol(format!("initial FEN: {}", err)))?;
+                }
+                client.chat(id, GREETING);
+                event["state"].clone()
+            }
+            Some("gameState") => event,
+            // Chat lines and the opponent leaving or coming back.
+            _ => continue,
+        };
+        if state["status"].as_str() != Some("started") {
+            client.chat(id, FAREWELL);
+            return Ok(());
+        }
+
+        let mut game = start.clone();
+        for text in state["moves"].as_str().unwrap_or_default().split_whitespace() {
+            let mv = game.parse_uci(text).map_err(|err| BotError::Protocol(err.to_string()))?;
+            game.play_move(mv);
+        }
+        if Some(game.turn) != color {
+            continue;
+        }
+        let (time, increment) = match game.turn {
+            Color::White => (&state["wtime"], &state["winc"]),
+            Color::Black => (&state["btime"], &state["binc"]),
+        };
+        let clock = time.as_u64().map(|remaining| TimeControl {
+            remaining: Duration::from_millis(remaining),
+            increment: Duration::from_millis(increment.as_u64().unwrap_or(0)),
+            moves_to_go: None,
+        });
+        let result = searcher.search(&game, &SearchLimits { clock, move_time: Some(MAX_MOVE_TIME), ..SearchLimits::default() });
+        if let Some(mv) = result.best_move {
+            client.post(&format!("/api/bot/move/{}/{}", id, mv), &[])?;
+        }
+    }
+    Ok(())
+}
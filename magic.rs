@@ -0,0 +1,165 @@
+// Sliding piece attacks by magic bitboard lookup. For each square, the
+// occupied squares along a rook's (or bishop's) rays are multiplied by a
+// "magic" number whose top bits then index a table of precomputed attack
+// sets. The magics are searched for once at startup.
+
+use std::sync::OnceLock;
+
+use crate::bitboard::Bitboard;
+use crate::{Square, KING_OFFSETS};
+
+const ROOK_DIRECTIONS: &[(i8, i8)] = KING_OFFSETS.split_at(4).0;
+const BISHOP_DIRECTIONS: &[(i8, i8)] = KING_OFFSETS.split_at(4).1;
+
+struct Magic {
+    // The squares whose occupancy can change the attack set.
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    // Where this square's entries start in the shared attack table.
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied.0 & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Tables {
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    attacks: Vec<Bitboard>,
+}
+
+static TABLES: OnceLock<Tables> = OnceLock::new();
+
+// Builds the tables now rather than on the first lookup.
+pub fn init() {
+    tables();
+}
+
+fn tables() -> &'static Tables {
+    TABLES.get_or_init(|| {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut attacks = vec![];
+        let rook = Square::all().map(|square| find_magic(square, ROOK_DIRECTIONS, &mut rng, &mut attacks)).collect();
+        let bishop = Square::all().map(|square| find_magic(square, BISHOP_DIRECTIONS, &mut rng, &mut attacks)).collect();
+        Tables { rook, bishop, attacks }
+    })
+}
+
+pub fn rook_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.rook[square.0 as usize].index(occupied)]
+}
+
+pub fn bishop_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.bishop[square.0 as usize].index(occupied)]
+}
+
+pub fn queen_attacks(square: Square, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+// Attacks found by walking each ray until it hits a piece, only used to
+// fill the tables.
+fn slow_attacks(square: Square, directions: &[(i8, i8)], occupied: u64) -> u64 {
+    let mut attacks = 0;
+    for &(files, ranks) in directions {
+        let mut next = square.offset(files, ranks);
+        while let Some(to) = next {
+            attacks |= 1 << to.0;
+            if occupied & (1 << to.0) != 0 {
+                break;
+            }
+            next = to.offset(files, ranks);
+        }
+    }
+    attacks
+}
+
+// The ray squares short of the board edge: a piece on the last square of a
+// ray never blocks anything beyond it, so it doesn't need to be looked at.
+fn relevant_mask(square: Square, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+    for &(files, ranks) in directions {
+        let mut next = square.offset(files, ranks);
+        while let Some(to) = next {
+            next = to.offset(files, ranks);
+            if next.is_some() {
+                mask |= 1 << to.0;
+            }
+        }
+    }
+    mask
+}
+
+// Tries random sparse numbers until one maps every occupancy of the mask to
+// a table slot without two different attack sets colliding, then appends
+// that square's table to `attacks`.
+fn find_magic(square: Square, directions: &[(i8, i8)], rng: &mut Rng, attacks: &mut Vec<Bitboard>) -> Magic {
+    let mask = relevant_mask(square, directions);
+    let shift = 64 - mask.count_ones();
+
+    // Every subset of the mask, by the carry-rippler trick.
+    let mut occupancies = vec![];
+    let mut subset = 0u64;
+    loop {
+        occupancies.push((subset, slow_attacks(square, directions, subset)));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+
+    let size = 1 << mask.count_ones();
+    let mut table = vec![0u64; size];
+    // Which attempt last wrote each slot, so the table needn't be cleared.
+    let mut written = vec![0u32; size];
+    let mut attempt = 0;
+
+    loop {
+        let magic = rng.sparse();
+        // Magics that leave the top byte nearly empty rarely work.
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+        attempt += 1;
+
+        let works = occupancies.iter().all(|&(occupied, reference)| {
+            let index = (occupied.wrapping_mul(magic) >> shift) as usize;
+            if written[index] != attempt {
+                written[index] = attempt;
+                table[index] = reference;
+                true
+            } else {
+                table[index] == reference
+            }
+        });
+
+        if works {
+            let offset = attacks.len();
+            attacks.extend(table.iter().map(|&squares| Bitboard(squares)));
+            return Magic { mask, magic, shift, offset };
+        }
+    }
+}
+
+// xorshift64, seeded so that every run finds the same magics.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Numbers with few bits set make better magic candidates.
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
@@ -0,0 +1,468 @@
+// Matches between two UCI engines, this one or any other, such as
+// Stockfish, each run as a child process and spoken to over its standard
+// input and output. The engines play a number of games from the openings
+// given, each opening twice with the colours swapped, on a clock or with a
+// fixed time or depth a move, and the match keeps the score and the games.
+//
+// An engine that makes an illegal move, oversteps its clock, stops
+// answering or exits loses the game; one that stopped answering or exited
+// is started afresh for the next.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::epd::Epd;
+use crate::pgn::{self, PgnGame, Tags};
+use crate::timeman::TimeControl;
+use crate::{opponent, Color, Game, GameOutcome, GameResult, TerminationReason};
+
+// Allowed over the clock for a move to come through the pipes.
+const TIME_MARGIN: Duration = Duration::from_millis(50);
+// For an engine to start up or get ready, and for one on a fixed move time
+// to answer on top of it.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+// For an engine to exit once asked to before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct MatchSettings {
+    pub games: usize,
+    // Each side's starting time and increment.
+    pub clock: Option<(Duration, Duration)>,
+    pub move_time: Option<Duration>,
+    pub depth: Option<u32>,
+    // Played in turn, each for two games; the usual position if none.
+    pub openings: Vec<Game>,
+    // UCI options set on both engines, by name.
+    pub options: Vec<(String, String)>,
+    // A file the games are added to as PGN as they finish.
+    pub pgn: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(io::Error),
+    // The engine's output closed, most likely because it exited.
+    Exited,
+    // It didn't answer in time.
+    Timeout,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Io(err) => write!(f, "{}", err),
+            EngineError::Exited => write!(f, "the engine exited"),
+            EngineError::Timeout => write!(f, "the engine stopped answering"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<io::Error> for EngineError {
+    fn from(err: io::Error) -> EngineError {
+        EngineError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum MatchError {
+    // An engine that couldn't be started, by its command.
+    Engine(String, EngineError),
+    Pgn(io::Error),
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchError::Engine(command, err) => write!(f, "{}: {}", command, err),
+            MatchError::Pgn(err) => write!(f, "writing the games: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+// The first engine's score against the second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Score {
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // Points per game, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // The difference in Elo rating the score suggests, None when one side
+    // has every point (or there are no games), which no finite difference
+    // explains.
+    pub fn elo(&self) -> Option<f64> {
+        let fraction = self.fraction();
+        (fraction > 0.0 && fraction < 1.0).then(|| 400.0 * (fraction / (1.0 - fraction)).log10())
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {} - {} [{:.3}] {}", self.wins, self.losses, self.draws, self.fraction(), self.games())
+    }
+}
+
+// A UCI engine running as a child process.
+pub struct Engine {
+    // As it names itself, else its command.
+    pub name: String,
+    child: Child,
+    input: ChildStdin,
+    // Its output, a line at a time, read on a thread of its own so that
+    // waiting for it can time out.
+    output: Receiver<String>,
+}
+
+// An engine's last report on the move it chose.
+struct Thinking {
+    score: String,
+    depth: u32,
+}
+
+// How a game ended, and which engine, if either, has to be started again.
+struct Played {
+    game: Game,
+    result: GameResult,
+    reason: String,
+    // The PGN Termination tag.
+    termination: &'static str,
+    // By move, what the engine said about it.
+    comments: Vec<(usize, String)>,
+    failed: Option<usize>,
+}
+
+impl Engine {
+    // Starts the engine that `command` runs, a program and its arguments
+    // separated by spaces, or this engine for "self", and sets it up with
+    // `options`.
+    pub fn start(command: &str, options: &[(String, String)]) -> Result<Engine, EngineError> {
+        let mut process = if command == "self" {
+            let mut process = Command::new(std::env::current_exe()?);
+            process.arg("uci");
+            process
+        } else {
+            let mut words = command.split_whitespace();
+            let mut process = Command::new(words.next().ok_or_else(|| io::Error::other("no command"))?);
+            process.args(words);
+            process
+        };
+        let mut child = process.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+        let input = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine { name: command.to_string(), child, input, output };
+        engine.send("uci")?;
+        let mut name = None;
+        engine.wait_for("uciok", Some(ANSWER_TIMEOUT), |line| {
+            if let Some(id) = line.strip_prefix("id name ") {
+                name = Some(id.trim().to_string());
+            }
+        })?;
+        if let Some(name) = name {
+            engine.name = name;
+        }
+        for (name, value) in options {
+            engine.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        engine.ready()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), EngineError> {
+        writeln!(self.input, "{}", line)?;
+        self.input.flush()?;
+        Ok(())
+    }
+
+    // Reads lines until one starting with the word `word`, which it
+    // returns, handing the others to `each`. Waits for ever without a
+    // `timeout`, or with one too long to count.
+    fn wait_for(&mut self, word: &str, timeout: Option<Duration>, mut each: impl FnMut(&str)) -> Result<String, EngineError> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        loop {
+            let line = match deadline {
+                Some(deadline) => match self.output.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => return Err(EngineError::Timeout),
+                    Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Exited),
+                },
+                None => self.output.recv().map_err(|_| EngineError::Exited)?,
+            };
+            if line.split_whitespace().next() == Some(word) {
+                return Ok(line);
+            }
+            each(&line);
+        }
+    }
+
+    fn ready(&mut self) -> Result<(), EngineError> {
+        self.send("isready")?;
+        self.wait_for("readyok", Some(ANSWER_TIMEOUT), |_| {}).map(|_| ())
+    }
+
+    fn new_game(&mut self) -> Result<(), EngineError> {
+        self.send("ucinewgame")?;
+        self.ready()
+    }
+
+    // The move the engine chooses in `game`, as it gave it, for the `go`
+    // command `go`, and what it last said about it.
+    fn go(&mut self, game: &Game, go: &str, timeout: Option<Duration>) -> Result<(String, Option<Thinking>), EngineError> {
+        let moves: Vec<String> = game.moves.iter().map(|mv| mv.to_string()).collect();
+        let position = if game.start == Game::new().start { "startpos".to_string() } else { format!("fen {}", game.start) };
+        let position = if moves.is_empty() { position } else { format!("{} moves {}", position, moves.join(" ")) };
+        self.send(&format!("position {}", position))?;
+        self.send(go)?;
+        let mut thinking = None;
+        let line = self.wait_for("bestmove", timeout, |line| {
+            if let Some(info) = parse_info(line) {
+                thinking = Some(info);
+            }
+        })?;
+        Ok((line.split_whitespace().nth(1).unwrap_or("").to_string(), thinking))
+    }
+}
+
+impl Drop for Engine {
+    // Asks the engine to quit, and kills it if it doesn't.
+    fn drop(&mut self) {
+        self.send("quit").ok();
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+// The score and depth of an `info` line that has both.
+fn parse_info(line: &str) -> Option<Thinking> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.first() != Some(&"info") {
+        return None;
+    }
+    let after = |name: &str| words.iter().position(|&word| word == name).and_then(|i| words.get(i + 1));
+    let depth = after("depth")?.parse().ok()?;
+    let score = if let Some(cp) = after("cp") {
+        format!("{:+.2}", cp.parse::<i32>().ok()? as f64 / 100.0)
+    } else {
+        let mate = after("mate")?.parse::<i32>().ok()?;
+        if mate < 0 {
+            format!("-M{}", -mate)
+        } else {
+            format!("+M{}", mate)
+        }
+    };
+    Some(Thinking { score, depth })
+}
+
+// Plays the match between the engines `commands` start, printing each
+// game's result and the score as it goes, and returns the first engine's
+// score.
+pub fn run(commands: [&str; 2], settings: &MatchSettings) -> Result<Score, MatchError> {
+    let start = |command: &str| Engine::start(command, &settings.options).map_err(|err| MatchError::Engine(command.to_string(), err));
+    let mut engines = [start(commands[0])?, start(commands[1])?];
+    let mut pgn = match &settings.pgn {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path).map_err(MatchError::Pgn)?),
+        None => None,
+    };
+    let default_opening = [Game::new()];
+    let openings = if settings.openings.is_empty() { &default_opening[..] } else { &settings.openings[..] };
+    println!("{} vs {}", engines[0].name, engines[1].name);
+
+    let mut score = Score::default();
+    for round in 0..settings.games {
+        // Each opening twice, the first engine White in the first game.
+        let white = round % 2;
+        let opening = &openings[round / 2 % openings.len()];
+        let played = play_game(&mut engines, white, opening, settings);
+        let names = [engines[white].name.clone(), engines[1 - white].name.clone()];
+        println!("Game {} ({} vs {}): {} {{{}}}", round + 1, names[0], names[1], played.result, played.reason);
+
+        match (played.result, white) {
+            (GameResult::Draw, _) => score.draws += 1,
+            (GameResult::WhiteWins, 0) | (GameResult::BlackWins, 1) => score.wins += 1,
+            _ => score.losses += 1,
+        }
+        println!("Score of {} vs {}: {}", engines[0].name, engines[1].name, score);
+
+        if let Some(out) = pgn.as_mut() {
+            write_game(out, &played, &names, round + 1, settings).map_err(MatchError::Pgn)?;
+        }
+        if let Some(failed) = played.failed {
+            engines[failed] = start(commands[failed])?;
+        }
+    }
+
+    match score.elo() {
+        Some(elo) => println!("Elo difference: {:+.1}", elo),
+        None if score.games() > 0 => println!("Elo difference: {}", if score.fraction() == 1.0 { "+inf" } else { "-inf" }),
+        None => {}
+    }
+    Ok(score)
+}
+
+// One game from `opening`, `engines[white]` playing White.
+fn play_game(engines: &mut [Engine; 2], white: usize, opening: &Game, settings: &MatchSettings) -> Played {
+    let mut game = opening.clone();
+    let engine_for = |color: Color| if color == Color::White { white } else { 1 - white };
+    let mut comments = vec![];
+    // A side that can't be got ready loses before the game begins.
+    for color in [Color::White, Color::Black] {
+        if let Err(err) = engines[engine_for(color)].new_game() {
+            return forfeit(game, comments, color, &err, engine_for(color));
+        }
+    }
+    let mut clocks = settings.clock.map(|(initial, _)| [initial; 2]);
+    let increment = settings.clock.map_or(Duration::ZERO, |(_, increment)| increment);
+
+    loop {
+        if let Some(outcome) = game.outcome() {
+            let reason = describe(&game, outcome);
+            return Played { game, result: outcome.result, reason, termination: "normal", comments, failed: None };
+        }
+        let color = game.turn;
+        let engine = engine_for(color);
+
+        let mut go = "go".to_string();
+        if let Some(clocks) = clocks {
+            let (wtime, btime, inc) = (clocks[0].as_millis(), clocks[1].as_millis(), increment.as_millis());
+            go += &format!(" wtime {} btime {} winc {} binc {}", wtime, btime, inc, inc);
+        }
+        if let Some(move_time) = settings.move_time {
+            go += &format!(" movetime {}", move_time.as_millis());
+        }
+        if let Some(depth) = settings.depth {
+            go += &format!(" depth {}", depth);
+        }
+        let timeout = match (clocks, settings.move_time) {
+            (Some(clocks), _) => Some(clocks[color.index()].saturating_add(TIME_MARGIN)),
+            (None, Some(move_time)) => Some(move_time + ANSWER_TIMEOUT),
+            (None, None) => None,
+        };
+
+        let move_start = Instant::now();
+        let answer = engines[engine].go(&game, &go, timeout);
+        let elapsed = move_start.elapsed();
+        let (text, thinking) = match answer {
+            Ok(answer) => answer,
+            Err(EngineError::Timeout) if clocks.is_some() => return lost_on_time(game, comments, color, Some(engine)),
+            Err(err) => return forfeit(game, comments, color, &err, engine),
+        };
+        if let Some(clocks) = clocks.as_mut() {
+            let clock = &mut clocks[color.index()];
+            if elapsed > clock.saturating_add(TIME_MARGIN) {
+                return lost_on_time(game, comments, color, None);
+            }
+            *clock = clock.saturating_sub(elapsed).saturating_add(increment);
+        }
+        let Ok(mv) = game.parse_uci(&text) else {
+            let reason = format!("{:?} makes an illegal move: {}", color, text);
+            let result = GameResult::win_for(opponent(color));
+            return Played { game, result, reason, termination: "rules infraction", comments, failed: None };
+        };
+        game.play_move(mv);
+        if let Some(thinking) = thinking {
+            // Score/depth and time, as other match runners note them.
+            comments.push((game.moves.len() - 1, format!("{}/{} {:.3}s", thinking.score, thinking.depth, elapsed.as_secs_f64())));
+        }
+    }
+}
+
+fn lost_on_time(game: Game, comments: Vec<(usize, String)>, color: Color, failed: Option<usize>) -> Played {
+    let reason = format!("{:?} loses on time", color);
+    Played { game, result: GameResult::win_for(opponent(color)), reason, termination: "time forfeit", comments, failed }
+}
+
+// The engine playing `color` broke down and loses.
+fn forfeit(game: Game, comments: Vec<(usize, String)>, color: Color, err: &EngineError, engine: usize) -> Played {
+    let reason = format!("{:?} forfeits: {}", color, err);
+    Played { game, result: GameResult::win_for(opponent(color)), reason, termination: "abandoned", comments, failed: Some(engine) }
+}
+
+fn describe(game: &Game, outcome: GameOutcome) -> String {
+    match outcome.reason {
+        TerminationReason::Checkmate => format!("{:?} mates", opponent(game.turn)),
+        TerminationReason::Stalemate => "Draw by stalemate".to_string(),
+        TerminationReason::InsufficientMaterial => "Draw by insufficient mating material".to_string(),
+        TerminationReason::ThreefoldRepetition => "Draw by threefold repetition".to_string(),
+        TerminationReason::FiftyMoveRule => "Draw by the fifty-move rule".to_string(),
+        reason => format!("{:?}", reason),
+    }
+}
+
+fn write_game(out: &mut File, played: &Played, names: &[String; 2], round: usize, settings: &MatchSettings) -> io::Result<()> {
+    let mut tags = Tags::roster(&names[0], &names[1], Some(played.result));
+    tags.set("Event", "Engine match");
+    tags.set("Round", &round.to_string());
+    if let Some((initial, increment)) = settings.clock {
+        tags.set_time_control(&TimeControl { remaining: initial, increment, moves_to_go: None });
+    }
+    tags.set("Termination", played.termination);
+    let mut record = PgnGame::new(tags, played.game.clone(), Some(played.result));
+    for (index, comment) in &played.comments {
+        record.annotation_mut(*index).comments.push(comment.clone());
+    }
+    match played.game.moves.len().checked_sub(1) {
+        Some(last) => record.annotation_mut(last).comments.push(played.reason.clone()),
+        None => record.comments.push(played.reason.clone()),
+    }
+    pgn::write(out, &record)?;
+    out.flush()
+}
+
+// The openings in `path`: the positions games in a PGN file (named .pgn)
+// reach, moves and all, or else positions one to a line, as FEN or EPD.
+pub fn read_openings(path: &str) -> Result<Vec<Game>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    if path.ends_with(".pgn") {
+        let mut reader = pgn::Reader::new(BufReader::new(file));
+        let mut openings = vec![];
+        while let Some(record) = reader.next() {
+            let record = record.map_err(|err| format!("line {}: {}", reader.start_line(), err))?;
+            openings.push(record.game);
+        }
+        return Ok(openings);
+    }
+    let mut openings = vec![];
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let game = Game::from_fen(line.trim()).or_else(|_| Epd::parse(&line).map(|record| record.game));
+        openings.push(game.map_err(|err| format!("line {}: {}", number + 1, err))?);
+    }
+    Ok(openings)
+}
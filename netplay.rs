@@ -0,0 +1,327 @@
+// Two players on different machines playing each other over TCP, each
+// side a person at the terminal or the engine. One side hosts and chooses
+// the colours, the clock and the starting position; the other connects.
+//
+// Every message is a 4-byte big-endian length and then that many bytes of
+// text, a word naming the message and its fields:
+//
+//     hello 1 <your colour> <ms>|- <increment ms>|- <fen>
+//                           the host's opening message, version 1
+//     move <uci> <ms>|-     a move and the time left on the mover's clock
+//     draw                  a draw offer, on the sender's turn
+//     accept, decline       the answer to an offer
+//     resign
+//     flag <colour>         that side's time has run out
+//
+// Both ends check every move against the rules, their own and their
+// opponent's, and each keeps its own clock and trusts the other's report of
+// theirs. A side that lets the clock run out waiting for its opponent's
+// move calls the flag itself.
+
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::timeman::TimeControl;
+use crate::{opponent, Action, AiSettings, BoardStyle, Color, Game, GameOutcome, GameResult, TerminationReason};
+
+const VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7878;
+// Longer than any message this protocol sends.
+const MAX_MESSAGE: usize = 1024;
+// Allowed on top of the opponent's clock for their move to arrive before
+// they are called out of time.
+const LATENCY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    // `color` is the side the receiver plays.
+    Hello { color: Color, clock: Option<(Duration, Duration)>, fen: String },
+    Move { mv: String, clock: Option<Duration> },
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Resign,
+    Flag(Color),
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    // A message that isn't in the protocol, or not where it came.
+    Protocol(String),
+    // The opponent sent a move the rules don't allow.
+    IllegalMove(String),
+    // The opponent went away mid-game.
+    Disconnected,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "{}", err),
+            NetError::Protocol(message) => write!(f, "protocol error: {}", message),
+            NetError::IllegalMove(mv) => write!(f, "the opponent played an illegal move: {}", mv),
+            NetError::Disconnected => write!(f, "the opponent disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> NetError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe => NetError::Disconnected,
+            _ => NetError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = |time: Option<Duration>| time.map_or("-".to_string(), |time| time.as_millis().to_string());
+        match self {
+            Message::Hello { color, clock, fen } => {
+                let (initial, increment) = (clock.map(|(initial, _)| initial), clock.map(|(_, increment)| increment));
+                write!(f, "hello {} {} {} {} {}", VERSION, color_name(*color), millis(initial), millis(increment), fen)
+            }
+            Message::Move { mv, clock } => write!(f, "move {} {}", mv, millis(*clock)),
+            Message::OfferDraw => write!(f, "draw"),
+            Message::AcceptDraw => write!(f, "accept"),
+            Message::DeclineDraw => write!(f, "decline"),
+            Message::Resign => write!(f, "resign"),
+            Message::Flag(color) => write!(f, "flag {}", color_name(*color)),
+        }
+    }
+}
+
+impl Message {
+    pub fn parse(text: &str) -> Result<Message, NetError> {
+        let bad = || NetError::Protocol(format!("bad message {:?}", text));
+        let words: Vec<&str> = text.split(' ').collect();
+        let millis = |word: &str| match word {
+            "-" => Ok(None),
+            _ => word.parse().map(|ms| Some(Duration::from_millis(ms))).map_err(|_| bad()),
+        };
+        let color = |word: &str| match word {
+            "white" => Ok(Color::White),
+            "black" => Ok(Color::Black),
+            _ => Err(bad()),
+        };
+        match words[..] {
+            ["hello", version, side, initial, increment, ref fen @ ..] if !fen.is_empty() => {
+                if version != VERSION.to_string() {
+                    return Err(NetError::Protocol(format!("the host speaks version {}, not {}", version, VERSION)));
+                }
+                let clock = millis(initial)?.zip(millis(increment)?);
+                Ok(Message::Hello { color: color(side)?, clock, fen: fen.join(" ") })
+            }
+            ["move", mv, clock] => Ok(Message::Move { mv: mv.to_string(), clock: millis(clock)? }),
+            ["draw"] => Ok(Message::OfferDraw),
+            ["accept"] => Ok(Message::AcceptDraw),
+            ["decline"] => Ok(Message::DeclineDraw),
+            ["resign"] => Ok(Message::Resign),
+            ["flag", side] => Ok(Message::Flag(color(side)?)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+pub fn send(stream: &mut impl Write, message: &Message) -> Result<(), NetError> {
+    let text = message.to_string();
+    stream.write_all(&(text.len() as u32).to_be_bytes())?;
+    stream.write_all(text.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+pub fn receive(stream: &mut impl Read) -> Result<Message, NetError> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE {
+        return Err(NetError::Protocol(format!("a message of {} bytes", length)));
+    }
+    let mut text = vec![0; length];
+    stream.read_exact(&mut text)?;
+    let text = String::from_utf8(text).map_err(|_| NetError::Protocol("a message that isn't UTF-8".to_string()))?;
+    Message::parse(&text)
+}
+
+// Waits on `port` for an opponent, who plays the other side from `color`.
+// The clock is the one in `ai`.
+pub fn host(port: u16, game: &mut Game, color: Color, human: bool, ai: AiSettings) -> Result<GameOutcome, NetError> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Waiting for an opponent on port {}...", port);
+    let (mut stream, address) = listener.accept()?;
+    println!("{} connected.", address);
+    send(&mut stream, &Message::Hello { color: opponent(color), clock: ai.clock, fen: game.to_fen() })?;
+    play(&mut stream, game, color, human, ai)
+}
+
+// Joins the game hosted at `address`, in the colour, on the clock and from
+// the position the host chose.
+pub fn connect(address: impl ToSocketAddrs, human: bool, mut ai: AiSettings) -> Result<(Game, GameOutcome), NetError> {
+    let mut stream = TcpStream::connect(address)?;
+    let Message::Hello { color, clock, fen } = receive(&mut stream)? else {
+        return Err(NetError::Protocol("expected hello".to_string()));
+    };
+    let mut game = Game::from_fen(&fen).map_err(|err| NetError::Protocol(format!("the host's position: {}", err)))?;
+    println!("Playing {:?}.", color);
+    ai.clock = clock;
+    let outcome = play(&mut stream, &mut game, color, human, ai)?;
+    Ok((game, outcome))
+}
+
+// Plays `color` against the other end of `stream` until the game is over.
+// `human` plays from the terminal; otherwise the engine plays with `ai`.
+fn play(stream: &mut TcpStream, game: &mut Game, color: Color, human: bool, ai: AiSettings) -> Result<GameOutcome, NetError> {
+    let mut clocks = ai.clock.map(|(initial, _)| [initial; 2]);
+    let increment = ai.clock.map_or(Duration::ZERO, |(_, increment)| increment);
+    let timeout = |loser: Color| {
+        println!("Game over! {:?} ran out of time.", loser);
+        GameOutcome { result: GameResult::win_for(opponent(loser)), reason: TerminationReason::Timeout }
+    };
+
+    let outcome = loop {
+        println!("{}", game.render(BoardStyle::default()));
+        if let Some(clocks) = clocks {
+            println!("White {}  Black {}", format_clock(clocks[0]), format_clock(clocks[1]));
+        }
+        if let Some(outcome) = game.outcome() {
+            game.announce(outcome);
+            break outcome;
+        }
+
+        if game.turn == color {
+            let move_start = Instant::now();
+            let action = if human {
+                game.read_human_action(&ai.evaluation)
+            } else {
+                let clock = clocks.map(|clocks| TimeControl { remaining: clocks[color.index()], increment, moves_to_go: None });
+                game.get_ai_move(&ai, clock).map(Action::Move)
+            };
+            if let Some(clocks) = clocks.as_mut() {
+                let clock = &mut clocks[color.index()];
+                match clock.checked_sub(move_start.elapsed()) {
+                    Some(left) => *clock = left + increment,
+                    None => {
+                        send(stream, &Message::Flag(color))?;
+                        break timeout(color);
+                    }
+                }
+            }
+            match action {
+                Some(Action::Move(mv)) => {
+                    send(stream, &Message::Move { mv: mv.to_string(), clock: clocks.map(|clocks| clocks[color.index()]) })?;
+                    println!("{:?} plays {}", color, game.numbered_san(mv));
+                    game.play_move(mv);
+                }
+                Some(Action::OfferDraw) => {
+                    send(stream, &Message::OfferDraw)?;
+                    println!("Waiting for an answer to the draw offer...");
+                    match receive(stream)? {
+                        Message::AcceptDraw => {
+                            println!("{:?} accepts the draw offer.", opponent(color));
+                            game.offer_draw(color);
+                            game.accept_draw(opponent(color));
+                        }
+                        Message::DeclineDraw => println!("{:?} declines the draw offer.", opponent(color)),
+                        message => {
+                            return Err(NetError::Protocol(format!("expected an answer to the draw offer, not {:?}", message.to_string())))
+                        }
+                    }
+                }
+                // Closing the input gives the game up, as at the board.
+                Some(Action::Resign) | None => {
+                    send(stream, &Message::Resign)?;
+                    println!("Game over! {:?} resigns.", color);
+                    game.resign(color);
+                }
+            }
+        } else {
+            let them = opponent(color);
+            println!("Waiting for {:?} to move...", them);
+            stream.set_read_timeout(clocks.map(|clocks| clocks[them.index()] + LATENCY))?;
+            let message = match receive(stream) {
+                Err(NetError::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    send(stream, &Message::Flag(them))?;
+                    break timeout(them);
+                }
+                message => message?,
+            };
+            match message {
+                Message::Move { mv, clock } => {
+                    let mv = game.parse_uci(&mv).map_err(|_| NetError::IllegalMove(mv))?;
+                    if let (Some(clocks), Some(left)) = (clocks.as_mut(), clock) {
+                        clocks[them.index()] = left;
+                    }
+                    println!("{:?} plays {}", them, game.numbered_san(mv));
+                    game.play_move(mv);
+                }
+                Message::OfferDraw => {
+                    game.offer_draw(them);
+                    let accept =
+                        if human { ask(&format!("{:?} offers a draw. Accept? (y/n): ", them)) } else { game.ai_accepts_draw(color) };
+                    if accept && game.accept_draw(color) {
+                        send(stream, &Message::AcceptDraw)?;
+                        println!("{:?} accepts the draw offer.", color);
+                    } else {
+                        send(stream, &Message::DeclineDraw)?;
+                        println!("{:?} declines the draw offer.", color);
+                        game.decline_draw();
+                    }
+                }
+                Message::Resign => {
+                    println!("Game over! {:?} resigns.", them);
+                    game.resign(them);
+                }
+                Message::Flag(loser) => break timeout(loser),
+                message => return Err(NetError::Protocol(format!("unexpected {:?}", message.to_string()))),
+            }
+        }
+
+        if let Some(outcome) = game.declared_outcome {
+            break outcome;
+        }
+    };
+
+    println!("Result: {}", outcome.result);
+    Ok(outcome)
+}
+
+// A yes or no from the terminal; no once the input is closed.
+fn ask(question: &str) -> bool {
+    loop {
+        print!("{}", question);
+        io::stdout().flush().ok();
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+            return false;
+        }
+        match answer.trim() {
+            "y" | "yes" => return true,
+            "n" | "no" => return false,
+            _ => {}
+        }
+    }
+}
+
+// "4:05", or "0:09.5" under ten seconds.
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds < 10 {
+        format!("0:0{:.1}", time.as_secs_f64())
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
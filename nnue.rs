@@ -0,0 +1,173 @@
+// An efficiently updatable neural network (NNUE) evaluation, which can
+// stand in for the hand-crafted one in `eval` when a network file is
+// loaded with `--nnue <file>`.
+//
+// The network has one input per piece type, color and square, seen from
+// each side's point of view in turn: "our knight on f3" for White is "our
+// knight on f6" for Black. The piece types go pawn, knight, bishop,
+// rook, queen, king, as trainers lay them out, not in Piece::ALL order.
+// The inputs feed a hidden layer, one copy per side, whose values (the
+// accumulator) are kept up to date as pieces come and go: a move only
+// adds and takes away the weights of the two or three inputs it changes,
+// rather than running the whole layer again. The side to move's half and
+// the other side's, clipped to 0..=QA, then make up the output neuron's
+// inputs. Its output is kept short of the mate scores.
+//
+// Network files are little-endian: the magic bytes "CNUE", the hidden
+// layer size as a u32, then i16 weights in the order the network uses
+// them. Those are the input weights input by input, the hidden biases,
+// the output weights for the side to move's half and the other side's,
+// and the output bias. The input weights are quantised by QA, the output
+// weights by QB and the output bias by both, as trainers such as bullet
+// write them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::search::MATE_BOUND;
+use crate::{opponent, ChessPiece, Color, Piece, Square};
+
+const MAGIC: &[u8; 4] = b"CNUE";
+const INPUTS: usize = 768;
+// Quantisation of the input and output layers, and the centipawns one
+// unit of the output is worth.
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+pub struct Network {
+    hidden: usize,
+    // INPUTS rows of `hidden` weights.
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    // The side to move's half, then the other side's.
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+impl Network {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Network> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Network> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("not a network file: {}", message));
+        let (magic, rest) = bytes.split_at_checked(4).ok_or_else(|| invalid("too short"))?;
+        if magic != MAGIC {
+            return Err(invalid("bad magic bytes"));
+        }
+        let (size, rest) = rest.split_at_checked(4).ok_or_else(|| invalid("too short"))?;
+        let hidden = u32::from_le_bytes(size.try_into().unwrap()) as usize;
+        let expected = INPUTS * hidden + hidden + 2 * hidden + 1;
+        if hidden == 0 || rest.len() != expected * 2 {
+            return Err(invalid("wrong size for its hidden layer"));
+        }
+
+        let mut values = rest.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<i16>>();
+        let input_weights = take(INPUTS * hidden);
+        let hidden_biases = take(hidden);
+        let output_weights = take(2 * hidden);
+        let output_bias = take(1)[0];
+        Ok(Network { hidden, input_weights, hidden_biases, output_weights, output_bias })
+    }
+
+    fn weights(&self, input: usize) -> &[i16] {
+        &self.input_weights[input * self.hidden..(input + 1) * self.hidden]
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Network({} hidden)", self.hidden)
+    }
+}
+
+// The hidden layer for a position, from each side's point of view.
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    // By Color::index.
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    // An accumulator for an empty board, for the pieces to be added to.
+    pub fn new(network: Arc<Network>) -> Accumulator {
+        let values = [network.hidden_biases.clone(), network.hidden_biases.clone()];
+        Accumulator { network, values }
+    }
+
+    pub fn add(&mut self, piece: ChessPiece, square: Square) {
+        self.update(piece, square, |value, weight| value.wrapping_add(weight));
+    }
+
+    pub fn remove(&mut self, piece: ChessPiece, square: Square) {
+        self.update(piece, square, |value, weight| value.wrapping_sub(weight));
+    }
+
+    fn update(&mut self, piece: ChessPiece, square: Square, apply: impl Fn(i16, i16) -> i16) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = self.network.weights(input(perspective, piece, square));
+            for (value, &weight) in self.values[perspective.index()].iter_mut().zip(weights) {
+                *value = apply(*value, weight);
+            }
+        }
+    }
+
+    // The network's score in centipawns for the side to move, `turn`.
+    pub fn evaluate(&self, turn: Color) -> i32 {
+        let network = &self.network;
+        let (ours, theirs) = network.output_weights.split_at(network.hidden);
+        // A wide layer of large weights overflows an i32.
+        let half = |values: &[i16], weights: &[i16]| -> i64 {
+            values.iter().zip(weights).map(|(&value, &weight)| (value as i32).clamp(0, QA) as i64 * weight as i64).sum()
+        };
+        let output = half(&self.values[turn.index()], ours)
+            + half(&self.values[opponent(turn).index()], theirs)
+            + network.output_bias as i64;
+        let bound = MATE_BOUND as i64 - 1;
+        (output * SCALE as i64 / (QA * QB) as i64).clamp(-bound, bound) as i32
+    }
+}
+
+// The input for `piece` on `square` from `perspective`'s point of view:
+// its own pieces first, and the board turned round for Black.
+fn input(perspective: Color, piece: ChessPiece, square: Square) -> usize {
+    let side = if piece.color == perspective { 0 } else { 1 };
+    let square = match perspective {
+        Color::White => square.0,
+        Color::Black => square.0 ^ 56,
+    };
+    let kind = match piece.piece {
+        Piece::Pawn => 0,
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        Piece::King => 5,
+    };
+    (side * 6 + kind) * 64 + square as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_stays_short_of_mate_scores() {
+        for (weight, expected) in [(i16::MAX, MATE_BOUND - 1), (i16::MIN, 1 - MATE_BOUND), (64, 400)] {
+            let network = Network {
+                hidden: 1,
+                input_weights: vec![0; INPUTS],
+                hidden_biases: vec![QA as i16],
+                output_weights: vec![weight, 0],
+                output_bias: 0,
+            };
+            assert_eq!(Accumulator::new(Arc::new(network)).evaluate(Color::White), expected);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// A game's moves as a list in one of the notations other tools expect:
+// SAN as in PGN ("Nf3", "exd5"), long algebraic with both squares
+// ("Ng1-f3", "e4xd5") as in older books and some logs, or the coordinates
+// UCI engines speak ("g1f3", "e4d5"). Move numbers are optional: "1." before
+// White's moves, and "1..." before a list that starts with Black's.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{opponent, san, Color, Game, Move, MoveKind, Piece};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    San,
+    Long,
+    Uci,
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Notation::San => "san",
+            Notation::Long => "long",
+            Notation::Uci => "uci",
+        })
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Notation, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "san" => Ok(Notation::San),
+            "long" | "lan" => Ok(Notation::Long),
+            "uci" => Ok(Notation::Uci),
+            _ => Err(format!("unknown notation {:?}: expected san, long or uci", s)),
+        }
+    }
+}
+
+// `mv`, a legal move in `game`, in `notation`.
+pub fn format(game: &Game, mv: Move, notation: Notation) -> String {
+    match notation {
+        Notation::San => san::format(game, mv),
+        Notation::Long => long(game, mv),
+        Notation::Uci => mv.to_string(),
+    }
+}
+
+// Long algebraic: the piece's letter, the square it leaves, "-" or "x", the
+// square it goes to, then any promotion and check mark as in SAN.
+fn long(game: &Game, mv: Move) -> String {
+    if mv.kind == MoveKind::Castle {
+        return san::format(game, mv);
+    }
+    let letter = match mv.piece {
+        Piece::Pawn => String::new(),
+        piece => san::letter(piece).to_string(),
+    };
+    let separator = if mv.is_capture() { 'x' } else { '-' };
+    let promotion = mv.promotion.map_or(String::new(), |piece| format!("={}", san::letter(piece)));
+    let check = san::format(game, mv).chars().last().filter(|&c| c == '+' || c == '#').map_or(String::new(), String::from);
+    format!("{}{}{}{}{}{}", letter, mv.from, separator, mv.to, promotion, check)
+}
+
+// Every move of `game` since its start, in `notation`.
+pub fn moves(game: &Game, notation: Notation) -> Vec<String> {
+    let mut replay = Game::from_fen(&game.start).expect("a game's start is a valid FEN");
+    game.moves
+        .iter()
+        .map(|&mv| {
+            let text = format(&replay, mv, notation);
+            replay.play_move(mv);
+            text
+        })
+        .collect()
+}
+
+// The moves of `game` on one line, separated by spaces, with move numbers
+// if `numbered`.
+pub fn write(game: &Game, notation: Notation, numbered: bool) -> String {
+    let start = Game::from_fen(&game.start).expect("a game's start is a valid FEN");
+    let mut parts = vec![];
+    let mut number = start.fullmove_number;
+    let mut turn = start.turn;
+    for (i, text) in moves(game, notation).into_iter().enumerate() {
+        if numbered {
+            match turn {
+                Color::White => parts.push(format!("{}.", number)),
+                Color::Black if i == 0 => parts.push(format!("{}...", number)),
+                Color::Black => {}
+            }
+        }
+        parts.push(text);
+        if turn == Color::Black {
+            number += 1;
+        }
+        turn = opponent(turn);
+    }
+    parts.join(" ")
+}
@@ -0,0 +1,138 @@
+// Fetching games from Lichess and Chess.com through their public APIs,
+// built only with the `online` feature, which brings in `ureq` for HTTPS
+// and `serde_json` for Chess.com's replies.
+//
+// A Lichess game is named by its eight-character ID, or any URL of it
+// ("https://lichess.org/abcdefgh", with "/black" or the four extra
+// characters of a player's link, or the export URL); the export API hands
+// it over as PGN. Chess.com has no API for a single game, only a player's
+// games month by month, so a Chess.com game URL
+// ("https://www.chess.com/game/live/123456789") needs one of its players
+// too, whose archives are searched from the latest month back.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::pgn::{self, PgnError, PgnGame};
+
+const LICHESS_EXPORT: &str = "https://lichess.org/game/export";
+const CHESS_COM_PLAYER: &str = "https://api.chess.com/pub/player";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Where a game is to be found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Lichess(String),
+    ChessCom(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    // Neither a Lichess ID nor a game URL of either site.
+    Unrecognized(String),
+    // A Chess.com game, without a player to look it up by.
+    NeedsPlayer,
+    // The site doesn't have the game, or the player's archives don't.
+    NotFound(String),
+    // The request failed or the reply wasn't what the API promises.
+    Http(String),
+    Pgn(PgnError),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Unrecognized(text) => write!(f, "{:?} is not a Lichess or Chess.com game", text),
+            FetchError::NeedsPlayer => write!(f, "finding a Chess.com game needs the name of one of its players"),
+            FetchError::NotFound(game) => write!(f, "game {} not found", game),
+            FetchError::Http(message) => write!(f, "{}", message),
+            FetchError::Pgn(err) => write!(f, "the game's PGN: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<PgnError> for FetchError {
+    fn from(err: PgnError) -> FetchError {
+        FetchError::Pgn(err)
+    }
+}
+
+impl Source {
+    // The game a URL or bare Lichess ID names.
+    pub fn parse(text: &str) -> Result<Source, FetchError> {
+        let unrecognized = || FetchError::Unrecognized(text.to_string());
+        let text = text.trim();
+        let rest = text.strip_prefix("https://").or_else(|| text.strip_prefix("http://")).unwrap_or(text);
+        let (host, path) = rest.split_once('/').unwrap_or(("", rest));
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        match host.trim_start_matches("www.") {
+            "" | "lichess.org" => {
+                let segment = match segments[..] {
+                    ["game", "export", id, ..] => id,
+                    [id, ..] => id,
+                    [] => return Err(unrecognized()),
+                };
+                let id = segment.strip_suffix(".pgn").unwrap_or(segment);
+                let alphanumeric = id.chars().all(|c| c.is_ascii_alphanumeric());
+                match id.len() {
+                    8 | 12 if alphanumeric => Ok(Source::Lichess(id[..8].to_string())),
+                    _ => Err(unrecognized()),
+                }
+            }
+            "chess.com" => {
+                // "/game/live/<id>", "/game/daily/<id>", "/live/game/<id>"
+                // and the like: the ID is the last number.
+                let id = segments.iter().rev().find(|segment| segment.chars().all(|c| c.is_ascii_digit())).ok_or_else(unrecognized)?;
+                Ok(Source::ChessCom(id.to_string()))
+            }
+            _ => Err(unrecognized()),
+        }
+    }
+}
+
+// Downloads the game `text` names, a URL or Lichess ID, and reads it.
+// `player` is one of the players, which a Chess.com game needs.
+pub fn fetch(text: &str, player: Option<&str>) -> Result<PgnGame, FetchError> {
+    let pgn = fetch_pgn(&Source::parse(text)?, player)?;
+    Ok(pgn::read(&pgn)?)
+}
+
+// The game's PGN as the site exports it.
+pub fn fetch_pgn(source: &Source, player: Option<&str>) -> Result<String, FetchError> {
+    match source {
+        Source::Lichess(id) => get(&format!("{}/{}?clocks=false&evals=false", LICHESS_EXPORT, id), "application/x-chess-pgn", id),
+        Source::ChessCom(id) => {
+            let player = player.ok_or(FetchError::NeedsPlayer)?.to_ascii_lowercase();
+            let archives = json(&get(&format!("{}/{}/games/archives", CHESS_COM_PLAYER, player), "application/json", &player)?)?;
+            let months = archives["archives"].as_array().ok_or_else(|| FetchError::Http("no archives in Chess.com's reply".to_string()))?;
+            for month in months.iter().rev().filter_map(|month| month.as_str()) {
+                let games = json(&get(month, "application/json", id)?)?;
+                for game in games["games"].as_array().into_iter().flatten() {
+                    let url = game["url"].as_str().unwrap_or_default();
+                    if url.rsplit('/').next() == Some(id.as_str()) {
+                        return game["pgn"].as_str().map(str::to_string).ok_or_else(|| FetchError::NotFound(id.clone()));
+                    }
+                }
+            }
+            Err(FetchError::NotFound(id.clone()))
+        }
+    }
+}
+
+// The body of a GET request for `url`; `what` names the thing asked for
+// when the site says there is no such thing.
+fn get(url: &str, accept: &str, what: &str) -> Result<String, FetchError> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).user_agent("ChessInRust").build();
+    match agent.get(url).set("Accept", accept).call() {
+        Ok(response) => response.into_string().map_err(|err| FetchError::Http(format!("{}: {}", url, err))),
+        Err(ureq::Error::Status(404, _)) => Err(FetchError::NotFound(what.to_string())),
+        Err(err) => Err(FetchError::Http(format!("{}: {}", url, err))),
+    }
+}
+
+fn json(text: &str) -> Result<serde_json::Value, FetchError> {
+    serde_json::from_str(text).map_err(|err| FetchError::Http(format!("bad JSON from Chess.com: {}", err)))
+}
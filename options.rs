@@ -0,0 +1,207 @@
+// The engine's settings, declared once and set the same way from anywhere:
+// a GUI sets them as UCI options ("setoption name Hash value 64") and the
+// command line as flags ("--hash 64"). Each is a whole number in a range,
+// bar EvalFile, which names a file to load:
+//
+//     Hash         --hash <mb>         transposition table size
+//     Threads      --threads <n>       search threads
+//     MultiPV      --multipv <n>       best lines to find
+//     Contempt     --contempt <cp>     how much worse than nothing a draw is
+//     Skill Level  --skill <0-20>      20 for full strength
+//     EvalFile     --nnue <file>       an NNUE network to evaluate with
+//                                      instead of the hand-crafted terms;
+//                                      see nnue.rs
+//
+// Below full strength the engine plays weaker moves on purpose: it looks
+// one ply ahead, and one more for every two levels, and plays any move up
+// to 20 centipawns a level below the top worse than its best, picked at
+// random.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::nnue::Network;
+use crate::search::Searcher;
+use crate::tt::TranspositionTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineOption {
+    // As UCI names it, matched without regard to case.
+    pub name: &'static str,
+    pub flag: &'static str,
+    pub kind: OptionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    // A whole number in a range.
+    Spin { default: i64, min: i64, max: i64 },
+    // A path, empty by default.
+    File,
+}
+
+const MAX_SKILL: u32 = 20;
+// The variety each skill level below the top adds.
+const VARIETY_PER_LEVEL: i32 = 20;
+
+// Their places in `OPTIONS`.
+const HASH: usize = 0;
+const THREADS: usize = 1;
+const MULTI_PV: usize = 2;
+const CONTEMPT: usize = 3;
+const SKILL: usize = 4;
+
+const fn spin(name: &'static str, flag: &'static str, default: i64, min: i64, max: i64) -> EngineOption {
+    EngineOption { name, flag, kind: OptionKind::Spin { default, min, max } }
+}
+
+pub const OPTIONS: [EngineOption; 6] = [
+    spin("Hash", "--hash", TranspositionTable::DEFAULT_SIZE_MB as i64, 1, 65536),
+    spin("Threads", "--threads", 1, 1, 256),
+    spin("MultiPV", "--multipv", 1, 1, 256),
+    spin("Contempt", "--contempt", 0, -100, 100),
+    spin("Skill Level", "--skill", MAX_SKILL as i64, 0, MAX_SKILL as i64),
+    EngineOption { name: "EvalFile", flag: "--nnue", kind: OptionKind::File },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Unknown(String),
+    // Not a number, or out of the option's range.
+    BadValue(&'static EngineOption, String),
+    // A file that couldn't be loaded, and why.
+    BadFile(&'static EngineOption, String, String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionError::Unknown(name) => write!(f, "no option named {}", name),
+            OptionError::BadValue(option, value) => match option.kind {
+                OptionKind::Spin { min, max, .. } => write!(f, "{} takes a number from {} to {}, not {:?}", option.name, min, max, value),
+                OptionKind::File => write!(f, "{} takes a path, not {:?}", option.name, value),
+            },
+            OptionError::BadFile(option, path, err) => write!(f, "{} {}: {}", option.name, path, err),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+impl EngineOption {
+    // Its declaration in answer to `uci`.
+    pub fn uci(&self) -> String {
+        match self.kind {
+            OptionKind::Spin { default, min, max } => format!("option name {} type spin default {} min {} max {}", self.name, default, min, max),
+            OptionKind::File => format!("option name {} type string default <empty>", self.name),
+        }
+    }
+}
+
+// A value for every option.
+#[derive(Debug, Clone)]
+pub struct EngineOptions {
+    // The file options' places are unused.
+    values: [i64; OPTIONS.len()],
+    network: Option<Arc<Network>>,
+}
+
+impl Default for EngineOptions {
+    fn default() -> EngineOptions {
+        let values = OPTIONS.map(|option| match option.kind {
+            OptionKind::Spin { default, .. } => default,
+            OptionKind::File => 0,
+        });
+        EngineOptions { values, network: None }
+    }
+}
+
+impl EngineOptions {
+    // The defaults, with any flags for options in `args` applied.
+    pub fn from_args(args: &[String]) -> Result<EngineOptions, OptionError> {
+        let mut options = EngineOptions::default();
+        for option in &OPTIONS {
+            if let Some(i) = args.iter().position(|a| a == option.flag) {
+                let value = args.get(i + 1).ok_or_else(|| OptionError::BadValue(option, String::new()))?;
+                options.set(option.name, value)?;
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), OptionError> {
+        let index = OPTIONS.iter().position(|option| option.name.eq_ignore_ascii_case(name.trim()));
+        let index = index.ok_or_else(|| OptionError::Unknown(name.to_string()))?;
+        let option = &OPTIONS[index];
+        match option.kind {
+            OptionKind::Spin { min, max, .. } => match value.trim().parse::<i64>() {
+                Ok(number) if (min..=max).contains(&number) => {
+                    self.values[index] = number;
+                    Ok(())
+                }
+                _ => Err(OptionError::BadValue(option, value.to_string())),
+            },
+            // EvalFile is the only file option. GUIs send "<empty>" to
+            // clear a path.
+            OptionKind::File => {
+                self.network = match value.trim() {
+                    "" | "<empty>" => None,
+                    path => Some(Arc::new(Network::load(path).map_err(|err| OptionError::BadFile(option, path.to_string(), err.to_string()))?)),
+                };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn hash_mb(&self) -> usize {
+        self.values[HASH] as usize
+    }
+
+    pub fn threads(&self) -> usize {
+        self.values[THREADS] as usize
+    }
+
+    pub fn multi_pv(&self) -> usize {
+        self.values[MULTI_PV] as usize
+    }
+
+    pub fn contempt(&self) -> i32 {
+        self.values[CONTEMPT] as i32
+    }
+
+    pub fn skill(&self) -> u32 {
+        self.values[SKILL] as u32
+    }
+
+    // The network from EvalFile, if one is loaded.
+    pub fn network(&self) -> Option<Arc<Network>> {
+        self.network.clone()
+    }
+
+    // The deepest the skill level lets the engine look, if it is below
+    // full strength.
+    pub fn max_depth(&self) -> Option<u32> {
+        (self.skill() < MAX_SKILL).then(|| 1 + self.skill() / 2)
+    }
+
+    // A searcher set up with these options.
+    pub fn searcher(&self) -> Searcher {
+        let mut searcher = Searcher::with_hash_size(self.hash_mb());
+        self.configure(&mut searcher);
+        searcher
+    }
+
+    // Sets up `searcher` with every option but Hash, which takes a new
+    // table; see `Searcher::set_hash_size`.
+    pub fn configure(&self, searcher: &mut Searcher) {
+        searcher.threads = self.threads();
+        searcher.multi_pv = self.multi_pv();
+        searcher.contempt = self.contempt();
+        searcher.network = self.network();
+        searcher.variety = (MAX_SKILL - self.skill()) as i32 * VARIETY_PER_LEVEL;
+        if searcher.variety > 0 {
+            searcher.seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        }
+    }
+}
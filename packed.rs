@@ -0,0 +1,86 @@
+// Moves squeezed into 16 bits for the engine's tables: the from square in
+// the low 6 bits, the to square in the next 6 and a 4-bit flag on top. The
+// moving and captured pieces aren't stored; they are read back off the board
+// the move was made on.
+
+use crate::{Game, Move, MoveKind, Piece, Square};
+
+const QUIET: u16 = 0;
+const DOUBLE_PUSH: u16 = 1;
+const EN_PASSANT: u16 = 2;
+const CASTLE: u16 = 3;
+// Promotions take flags 4 to 7, in the order of `PROMOTIONS`.
+const PROMOTION: u16 = 4;
+const PROMOTIONS: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    // a1a1, which no move can be, for empty table slots.
+    pub const NONE: PackedMove = PackedMove(0);
+
+    pub fn origin(self) -> Square {
+        Square((self.0 & 0x3f) as u8)
+    }
+
+    pub fn target(self) -> Square {
+        Square((self.0 >> 6 & 0x3f) as u8)
+    }
+
+    fn flag(self) -> u16 {
+        self.0 >> 12
+    }
+
+    pub fn promotion(self) -> Option<Piece> {
+        let flag = self.flag();
+        (flag >= PROMOTION).then(|| PROMOTIONS[(flag - PROMOTION) as usize])
+    }
+
+    pub fn is_none(self) -> bool {
+        self == PackedMove::NONE
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u16) -> PackedMove {
+        PackedMove(bits)
+    }
+
+    // The full move in `game`, which must be the position the move was
+    // packed in (or one where it means the same thing). Returns None if
+    // there's no piece on the from square, as with a stale table entry.
+    pub fn unpack(self, game: &Game) -> Option<Move> {
+        if self.is_none() {
+            return None;
+        }
+        let (from, to) = (self.origin(), self.target());
+        let piece = game.piece_at(from)?.piece;
+        let kind = match self.flag() {
+            DOUBLE_PUSH => MoveKind::DoublePush,
+            EN_PASSANT => MoveKind::EnPassant,
+            CASTLE => MoveKind::Castle,
+            _ => MoveKind::Normal,
+        };
+        let captured = match kind {
+            MoveKind::EnPassant => Some(Piece::Pawn),
+            _ => game.piece_at(to).map(|captured| captured.piece),
+        };
+        Some(Move { from, to, piece, captured, promotion: self.promotion(), kind })
+    }
+}
+
+impl From<Move> for PackedMove {
+    fn from(mv: Move) -> PackedMove {
+        let flag = match (mv.kind, mv.promotion) {
+            (_, Some(piece)) => PROMOTION + PROMOTIONS.iter().position(|&p| p == piece).expect("promotion to a king or pawn") as u16,
+            (MoveKind::Normal, None) => QUIET,
+            (MoveKind::DoublePush, None) => DOUBLE_PUSH,
+            (MoveKind::EnPassant, None) => EN_PASSANT,
+            (MoveKind::Castle, None) => CASTLE,
+        };
+        PackedMove(mv.from.0 as u16 | (mv.to.0 as u16) << 6 | flag << 12)
+    }
+}
@@ -0,0 +1,188 @@
+// Pawn structure evaluation, cached by a hash of the pawns alone: pawns move
+// far less often than pieces, so most positions in a search share their
+// pawn structure with many others.
+//
+// Doubled pawns get in each other's way, and isolated ones (no friendly
+// pawn on a neighbouring file) and backward ones (left behind by their
+// neighbours, with the square in front controlled by an enemy pawn) need
+// pieces to defend them. A passed pawn, with no enemy pawn left to stop
+// it, is worth more the further it has advanced. In the endgame it is
+// also worth more the closer its own king is to the square in front of
+// it, and the further the enemy king; that part depends on the kings and
+// so is worked out afresh rather than cached.
+
+use crate::attacks;
+use crate::bitboard::{Bitboard, Bitboards};
+use crate::eval::{Parameters, Score, Terms, Totals, Weight};
+use crate::{opponent, Color, File, Piece, Rank, Square};
+
+pub const DOUBLED: Score = Score::new(-10, -20);
+pub const ISOLATED: Score = Score::new(-10, -15);
+pub const BACKWARD: Score = Score::new(-8, -10);
+// By how far the pawn has advanced, from its own side's point of view.
+pub const PASSED: [Score; 8] = [
+    Score::ZERO,
+    Score::new(5, 10),
+    Score::new(10, 20),
+    Score::new(15, 35),
+    Score::new(25, 60),
+    Score::new(40, 100),
+    Score::new(60, 150),
+    Score::ZERO,
+];
+// Per square of distance between each king and a passed pawn's stop
+// square, times how far the pawn has advanced by PASSED_KING_WEIGHT: the
+// race to the promotion square only matters for pawns that are close,
+// and only in the endgame.
+pub const PASSED_OWN_KING: Score = Score::new(0, -2);
+pub const PASSED_ENEMY_KING: Score = Score::new(0, 5);
+pub const PASSED_KING_WEIGHT: [i32; 8] = [0, 0, 0, 1, 2, 3, 5, 0];
+
+// A pawn structure's score and the pawns in it that are passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PawnStructure {
+    // Positive when it favours White.
+    pub score: Score,
+    pub passed: Bitboard,
+}
+
+pub fn evaluate(bitboards: &Bitboards, params: &Parameters) -> PawnStructure {
+    let mut totals = Totals::new(params);
+    let passed = collect(bitboards, &mut totals);
+    PawnStructure { score: totals.white(), passed }
+}
+
+// Hands both sides' pawn structure terms to `terms`, returning the passed
+// pawns.
+pub fn collect(bitboards: &Bitboards, terms: &mut impl Terms) -> Bitboard {
+    side(bitboards, Color::White, terms) | side(bitboards, Color::Black, terms)
+}
+
+fn side(bitboards: &Bitboards, color: Color, terms: &mut impl Terms) -> Bitboard {
+    let own = bitboards.pieces(Piece::Pawn, color);
+    let enemy = bitboards.pieces(Piece::Pawn, opponent(color));
+    let mut passed = Bitboard::EMPTY;
+
+    for file in 0..8 {
+        let count = (own & Bitboard::file(File(file))).count() as i32;
+        if count > 1 {
+            terms.add(color, Weight::Doubled, count - 1);
+        }
+    }
+
+    for square in own.squares() {
+        let neighbours = own & adjacent_files(square.file());
+        if neighbours.is_empty() {
+            terms.add(color, Weight::Isolated, 1);
+        } else if (neighbours & !front_span(square, color)).is_empty() && stop_square_attacked(square, color, enemy) {
+            terms.add(color, Weight::Backward, 1);
+        }
+        if (enemy & front_span(square, color)).is_empty() {
+            terms.add(color, Weight::Passed(advanced(square, color)), 1);
+            passed |= Bitboard::from_square(square);
+        }
+    }
+
+    passed
+}
+
+// The kings' part in the passed pawns' worth.
+pub fn passed_pawn_kings(bitboards: &Bitboards, passed: Bitboard, terms: &mut impl Terms) {
+    for square in passed.squares() {
+        let Some(color) = bitboards.piece_at(square).map(|piece| piece.color) else {
+            continue;
+        };
+        let (Some(own_king), Some(enemy_king)) =
+            (bitboards.pieces(Piece::King, color).first(), bitboards.pieces(Piece::King, opponent(color)).first())
+        else {
+            continue;
+        };
+        let Some(stop) = square.offset(0, forward(color)) else {
+            continue;
+        };
+        let weight = terms.params().passed_king_weight[advanced(square, color)];
+        terms.add(color, Weight::PassedOwnKing, own_king.distance(stop) as i32 * weight);
+        terms.add(color, Weight::PassedEnemyKing, enemy_king.distance(stop) as i32 * weight);
+    }
+}
+
+// How many ranks a pawn has come from its own side's back rank.
+fn advanced(square: Square, color: Color) -> usize {
+    match color {
+        Color::White => square.rank().0 as usize,
+        Color::Black => 7 - square.rank().0 as usize,
+    }
+}
+
+// The rank step of a `color` pawn's advance.
+pub fn forward(color: Color) -> i8 {
+    match color {
+        Color::White => 1,
+        Color::Black => -1,
+    }
+}
+
+// An enemy pawn guards the square the pawn would advance to.
+fn stop_square_attacked(square: Square, color: Color, enemy: Bitboard) -> bool {
+    square.offset(0, forward(color)).is_some_and(|stop| !(attacks::pawn_attacks(color, stop) & enemy).is_empty())
+}
+
+fn adjacent_files(file: File) -> Bitboard {
+    let left = if file.0 > 0 { Bitboard::file(File(file.0 - 1)) } else { Bitboard::EMPTY };
+    let right = if file.0 < 7 { Bitboard::file(File(file.0 + 1)) } else { Bitboard::EMPTY };
+    left | right
+}
+
+// The squares ahead of a pawn on its own and the neighbouring files, which
+// an enemy pawn must stand on to stop it or take it on its way.
+fn front_span(square: Square, color: Color) -> Bitboard {
+    let files = Bitboard::file(square.file()) | adjacent_files(square.file());
+    let ahead = (0..8)
+        .map(Rank)
+        .filter(|rank| match color {
+            Color::White => rank.0 > square.rank().0,
+            Color::Black => rank.0 < square.rank().0,
+        })
+        .fold(Bitboard::EMPTY, |ranks, rank| ranks | Bitboard::rank(rank));
+    files & ahead
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    key: u64,
+    structure: PawnStructure,
+}
+
+pub struct PawnTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl PawnTable {
+    pub const DEFAULT_SIZE_MB: usize = 1;
+
+    pub fn new(size_mb: usize) -> PawnTable {
+        let slots = (size_mb * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+        PawnTable { entries: vec![None; slots] }
+    }
+
+    // The pawn structure for the pawns hashing to `pawn_key`, from the
+    // table when it has been seen before. The table keeps whatever
+    // `params` it was first used with.
+    pub fn probe(&mut self, pawn_key: u64, bitboards: &Bitboards, params: &Parameters) -> PawnStructure {
+        let slot = (pawn_key % self.entries.len() as u64) as usize;
+        match self.entries[slot] {
+            Some(entry) if entry.key == pawn_key => entry.structure,
+            _ => {
+                let structure = evaluate(bitboards, params);
+                self.entries[slot] = Some(Entry { key: pawn_key, structure });
+                structure
+            }
+        }
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> PawnTable {
+        PawnTable::new(PawnTable::DEFAULT_SIZE_MB)
+    }
+}
@@ -0,0 +1,87 @@
+// Perft: counting the leaf nodes of the legal move tree to a given depth,
+// the standard check of a move generator against published totals.
+
+use std::thread;
+
+use crate::{Game, Move};
+
+pub fn perft(game: &mut Game, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mover = game.turn;
+    let moves = if game.is_in_check(mover) { game.evasion_moves() } else { game.pseudo_legal_moves() };
+
+    let mut nodes = 0;
+    for mv in moves {
+        let undo = game.make_move(mv);
+        if !game.is_in_check(mover) {
+            nodes += perft(game, depth - 1);
+        }
+        game.unmake_move(undo);
+    }
+    nodes
+}
+
+// The node count below each legal root move, found by handing the root
+// moves round-robin to `threads` workers, each on its own copy of the game.
+// The counts come back in move generation order.
+pub fn divide(game: &Game, depth: u32, threads: usize) -> Vec<(Move, u64)> {
+    let moves = game.legal_moves();
+    let threads = threads.clamp(1, moves.len().max(1));
+
+    let mut counts: Vec<(usize, Move, u64)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let mut game = game.clone();
+                let moves = &moves;
+                scope.spawn(move || {
+                    let mut counts = vec![];
+                    for (i, &mv) in moves.iter().enumerate().skip(worker).step_by(threads) {
+                        let undo = game.make_move(mv);
+                        counts.push((i, mv, perft(&mut game, depth.saturating_sub(1))));
+                        game.unmake_move(undo);
+                    }
+                    counts
+                })
+            })
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("perft worker panicked")).collect()
+    });
+
+    counts.sort_by_key(|&(i, _, _)| i);
+    counts.into_iter().map(|(_, mv, nodes)| (mv, nodes)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The standard positions, from the Chess Programming Wiki's perft
+    // results, at depths a debug build gets through quickly.
+    const POSITIONS: [(&str, u32, u64); 6] = [
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4, 197_281),
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3, 97_862),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43_238),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 3, 9_467),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3, 62_379),
+        ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", 3, 89_890),
+    ];
+
+    #[test]
+    fn standard_positions() {
+        for (fen, depth, nodes) in POSITIONS {
+            let mut game = Game::from_fen(fen).unwrap();
+            assert_eq!(perft(&mut game, depth), nodes, "{}", fen);
+            assert_eq!(game.to_fen(), fen, "perft left the position changed");
+        }
+    }
+
+    #[test]
+    fn divide_adds_up() {
+        let (fen, depth, nodes) = POSITIONS[1];
+        let counts = divide(&Game::from_fen(fen).unwrap(), depth, 4);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>(), nodes);
+    }
+}
@@ -0,0 +1,741 @@
+// Portable Game Notation (PGN), the usual file format for chess games: a
+// list of tag pairs such as `[White "Kasparov"]`, then the moves in SAN
+// (see `san`), numbered, and the result.
+//
+// Moves may carry comments, `{like this}`, numeric annotation glyphs
+// (NAGs) such as `$1` for a good move, and variations in brackets: other
+// moves that could have been played instead, `1. e4 (1. d4 d5) e5`, which
+// may have comments and variations of their own. `PgnGame` keeps them
+// alongside the moves they follow.
+//
+// Games are read leniently, as other software writes them, one at a time
+// from files of many (`Reader`), and written in the export format: the
+// Seven Tag Roster (Event, Site, Date, Round, White, Black and Result)
+// first and in that order, `SetUp` and `FEN` tags for a game that didn't
+// start from the usual position, and the movetext wrapped to lines of at
+// most 80 characters.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::san::{self, SanError};
+use crate::timeman::TimeControl;
+use crate::{Color, FenError, Game, GameResult, Move};
+
+// The longest line of movetext written.
+const LINE_LENGTH: usize = 80;
+
+// The tags every PGN game has, in the order they are written.
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// A game's tag pairs, in order, each name once. Besides the Seven Tag
+// Roster any tags at all may be there, such as WhiteElo or TimeControl;
+// they are kept as they are and written back out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags(Vec<(String, String)>);
+
+impl Tags {
+    pub fn new() -> Tags {
+        Tags(vec![])
+    }
+
+    // The Seven Tag Roster for a game played today between `white` and
+    // `black`. Values nobody knows are "?", as the standard asks.
+    pub fn roster(white: &str, black: &str, result: Option<GameResult>) -> Tags {
+        let mut tags = Tags::new();
+        tags.set("Event", "Casual game");
+        tags.set("Site", "?");
+        tags.set("Date", &today());
+        tags.set("Round", "-");
+        tags.set("White", white);
+        tags.set("Black", black);
+        tags.set_result(result);
+        tags
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    // Sets the tag, in its old place if it was there already and
+    // otherwise last.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.0.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.0.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let i = self.0.iter().position(|(tag, _)| tag == name)?;
+        Some(self.0.remove(i).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    // The Result tag; None for "*" or no tag at all.
+    pub fn result(&self) -> Option<GameResult> {
+        match self.get("Result")? {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+
+    pub fn set_result(&mut self, result: Option<GameResult>) {
+        self.set("Result", &result.map_or("*".to_string(), |result| result.to_string()));
+    }
+
+    // The starting position, when the game didn't start from the usual
+    // one.
+    pub fn fen(&self) -> Option<&str> {
+        self.get("FEN")
+    }
+}
+
+// The tags other software most often reads, typed. Values that don't
+// parse give None, as if the tag weren't there.
+#[cfg_attr(not(test), allow(dead_code))]
+impl Tags {
+    pub fn white(&self) -> Option<&str> {
+        self.get("White")
+    }
+
+    pub fn black(&self) -> Option<&str> {
+        self.get("Black")
+    }
+
+    pub fn event(&self) -> Option<&str> {
+        self.get("Event")
+    }
+
+    // The year, month and day of the Date tag, "YYYY.MM.DD", each None
+    // where it has question marks.
+    pub fn date(&self) -> Option<(Option<u32>, Option<u32>, Option<u32>)> {
+        let mut parts = self.get("Date")?.split('.').map(|part| part.parse().ok());
+        let date = (parts.next()?, parts.next()?, parts.next()?);
+        parts.next().is_none().then_some(date)
+    }
+
+    pub fn white_elo(&self) -> Option<u32> {
+        self.get("WhiteElo")?.parse().ok()
+    }
+
+    pub fn set_white_elo(&mut self, elo: u32) {
+        self.set("WhiteElo", &elo.to_string());
+    }
+
+    pub fn black_elo(&self) -> Option<u32> {
+        self.get("BlackElo")?.parse().ok()
+    }
+
+    pub fn set_black_elo(&mut self, elo: u32) {
+        self.set("BlackElo", &elo.to_string());
+    }
+
+    // The first period of the TimeControl tag: "seconds",
+    // "seconds+increment" or "moves/seconds". Controls with no fixed time,
+    // "-" for none and "*" for sandclocks, give None.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        let period = self.get("TimeControl")?.split(':').next()?;
+        let (moves_to_go, period) = match period.split_once('/') {
+            Some((moves, period)) => (Some(moves.parse().ok()?), period),
+            None => (None, period),
+        };
+        let (base, increment) = period.split_once('+').unwrap_or((period, "0"));
+        let seconds = |text: &str| Duration::try_from_secs_f64(text.parse().ok()?).ok();
+        Some(TimeControl { remaining: seconds(base)?, increment: seconds(increment)?, moves_to_go })
+    }
+
+    // Writes `clock` as a TimeControl tag, in the form `time_control`
+    // reads.
+    pub fn set_time_control(&mut self, clock: &TimeControl) {
+        let mut value = clock.moves_to_go.map_or(String::new(), |moves| format!("{}/", moves));
+        value.push_str(&clock.remaining.as_secs_f64().to_string());
+        if !clock.increment.is_zero() {
+            value.push_str(&format!("+{}", clock.increment.as_secs_f64()));
+        }
+        self.set("TimeControl", &value);
+    }
+}
+
+// Writes `record`'s game, from its starting position through every move
+// played, with its annotations and under its tags. Its result goes in the
+// Result tag and at the end of the movetext, "*" while it is unknown.
+pub fn write(out: &mut impl Write, record: &PgnGame) -> io::Result<()> {
+    let game = &record.game;
+    let mut tags = record.tags.clone();
+    tags.set_result(record.result);
+    tags.remove("SetUp");
+    tags.remove("FEN");
+    let setup = (game.start != Game::new().start).then_some([("SetUp", "1"), ("FEN", game.start.as_str())]);
+    // The Seven Tag Roster first, "?" where it is missing, then the
+    // starting position, then the rest in their order.
+    let roster = SEVEN_TAG_ROSTER.iter().map(|&name| (name, tags.get(name).unwrap_or("?")));
+    let others = tags.iter().filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(name));
+    for (name, value) in roster.chain(setup.into_iter().flatten()).chain(others) {
+        writeln!(out, "[{} \"{}\"]", name, escape(value))?;
+    }
+    writeln!(out)?;
+
+    let start = Game::from_fen(&game.start).expect("the starting position was legal when the game began");
+    let mut tokens = vec![];
+    push_line(&mut tokens, &start, &game.moves, &record.comments, &record.annotations);
+    tokens.push(tags.get("Result").unwrap_or("*").to_string());
+    for line in wrap(&tokens, LINE_LENGTH) {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out)
+}
+
+// `variation` as movetext on one line, "1. d4 d5 2. c4", where `before`
+// is the position before the move it replaces.
+pub fn variation_text(before: &Game, variation: &Variation) -> String {
+    let mut tokens = vec![];
+    push_line(&mut tokens, before, &variation.moves, &variation.comments, &variation.annotations);
+    wrap(&tokens, usize::MAX).join(" ")
+}
+
+// Adds the movetext of `moves`, played from `start`, with their
+// annotations and any variations within them.
+fn push_line(tokens: &mut Vec<String>, start: &Game, moves: &[Move], comments: &[String], annotations: &[Annotation]) {
+    let mut replay = start.clone();
+    push_comments(tokens, comments);
+    // Black's move needs its number too when something comes between it
+    // and White's.
+    let mut numbered = false;
+    for (i, &mv) in moves.iter().enumerate() {
+        if replay.turn == Color::White {
+            tokens.push(format!("{}.", replay.fullmove_number));
+        } else if !numbered {
+            tokens.push(format!("{}...", replay.fullmove_number));
+        }
+        tokens.push(san::format(&replay, mv));
+        let annotation = annotations.get(i).cloned().unwrap_or_default();
+        let before = (!annotation.variations.is_empty()).then(|| replay.clone());
+        replay.make_move(mv);
+        tokens.extend(annotation.nags.iter().map(|nag| format!("${}", nag)));
+        push_comments(tokens, &annotation.comments);
+        if let Some(before) = before {
+            for variation in &annotation.variations {
+                tokens.push("(".to_string());
+                push_line(tokens, &before, &variation.moves, &variation.comments, &variation.annotations);
+                tokens.push(")".to_string());
+            }
+        }
+        numbered = replay.turn == Color::Black && annotation.comments.is_empty() && annotation.variations.is_empty();
+    }
+}
+
+// The tokens joined into lines of at most `width` characters, where a
+// token fits. Brackets hug what they enclose.
+fn wrap(tokens: &[String], width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() && !line.ends_with('(') && token != ")" {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    lines.push(line);
+    lines
+}
+
+// Adds each comment in braces, word by word so that long ones wrap. A
+// closing brace would end the comment early, so it can't be written.
+fn push_comments(tokens: &mut Vec<String>, comments: &[String]) {
+    for comment in comments {
+        let words: Vec<&str> = comment.split_whitespace().collect();
+        for (i, word) in words.iter().enumerate() {
+            let open = if i == 0 { "{" } else { "" };
+            let close = if i + 1 == words.len() { "}" } else { "" };
+            tokens.push(format!("{}{}{}", open, word.replace('}', ")"), close));
+        }
+        if words.is_empty() {
+            tokens.push("{}".to_string());
+        }
+    }
+}
+
+// A tag value with its quotes and backslashes escaped.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Today's date in UTC, as "YYYY.MM.DD".
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() / 86_400) as i64;
+    // Howard Hinnant's days-to-civil algorithm, counting in 400-year eras
+    // of 146,097 days from 1 March 0000.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+// A game as PGN records it: its tags, in order, the game itself after its
+// last move, which keeps the moves for replaying them, and what the
+// annotator had to say about them.
+#[derive(Clone)]
+pub struct PgnGame {
+    pub tags: Tags,
+    pub game: Game,
+    // None for a game still going on or of unknown result ("*").
+    pub result: Option<GameResult>,
+    // Comments before the first move, on the game as a whole.
+    pub comments: Vec<String>,
+    // By move, in step with `game.moves`; moves past the end have none.
+    pub annotations: Vec<Annotation>,
+}
+
+// What a PGN says about a move besides the move itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    // Numeric annotation glyphs, such as 1 for "!" and 4 for "??".
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    // Lines that could have been played instead of the move, each from the
+    // position before it.
+    pub variations: Vec<Variation>,
+}
+
+// A sideline: moves in place of one in the line around it, such as an
+// engine's analysis, annotated like the main line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variation {
+    // Each legal in turn from the position before the move replaced.
+    pub moves: Vec<Move>,
+    // Comments before the first move.
+    pub comments: Vec<String>,
+    // By move, in step with `moves`.
+    pub annotations: Vec<Annotation>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Variation {
+    pub fn new(moves: Vec<Move>) -> Variation {
+        Variation { moves, comments: vec![], annotations: vec![] }
+    }
+
+    // The annotation of the move at `index` in `moves`, to add to.
+    pub fn annotation_mut(&mut self, index: usize) -> &mut Annotation {
+        annotation_at(&mut self.annotations, index)
+    }
+}
+
+// The annotation at `index`, adding empty ones up to it as needed.
+fn annotation_at(annotations: &mut Vec<Annotation>, index: usize) -> &mut Annotation {
+    if annotations.len() <= index {
+        annotations.resize(index + 1, Annotation::default());
+    }
+    &mut annotations[index]
+}
+
+impl PgnGame {
+    // `game` under `tags`, without annotations.
+    pub fn new(tags: Tags, game: Game, result: Option<GameResult>) -> PgnGame {
+        PgnGame { tags, game, result, comments: vec![], annotations: vec![] }
+    }
+
+    // The annotation of the move at `index` in `game.moves`, to add to.
+    pub fn annotation_mut(&mut self, index: usize) -> &mut Annotation {
+        annotation_at(&mut self.annotations, index)
+    }
+
+    // Stores `variation` as a sideline to the move at `index` in
+    // `game.moves`, after any it has already.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn add_variation(&mut self, index: usize, variation: Variation) {
+        self.annotation_mut(index).variations.push(variation);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    // A tag pair that isn't `[Name "value"]`.
+    Tag(String),
+    Fen(FenError),
+    // A move that couldn't be played, by its number and side.
+    Move(u32, Color, SanError),
+    // Movetext after the result, a comment or variation left open, or a
+    // bracket out of place.
+    Movetext(String),
+    // Reading the file failed; `Reader` stops after this one.
+    Io(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Tag(line) => write!(f, "bad tag pair: {}", line),
+            PgnError::Fen(err) => write!(f, "bad FEN tag: {}", err),
+            PgnError::Move(number, Color::White, err) => write!(f, "move {}.: {}", number, err),
+            PgnError::Move(number, Color::Black, err) => write!(f, "move {}...: {}", number, err),
+            PgnError::Movetext(message) => write!(f, "{}", message),
+            PgnError::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// Reads a file of any number of games one at a time, as an iterator, so
+// that even a database of millions of games needn't fit in memory. A game
+// that can't be read comes out as an error, and the reader goes on with
+// the next one.
+pub struct Reader<R: BufRead> {
+    lines: io::Lines<R>,
+    // The first line of the next game, read while looking for the end of
+    // the one before.
+    next: Option<String>,
+    // Lines read so far, and the line the last game began on.
+    read: usize,
+    start: usize,
+    failed: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader { lines: input.lines(), next: None, read: 0, start: 0, failed: false }
+    }
+
+    // The line, counting from 1, that the game last returned began on.
+    pub fn start_line(&self) -> usize {
+        self.start
+    }
+
+    // The text of the next game: its tags, then its movetext up to the
+    // next game's tags.
+    fn next_text(&mut self) -> io::Result<Option<String>> {
+        let mut text = String::new();
+        let mut in_movetext = false;
+        loop {
+            let line = match self.next.take() {
+                Some(line) => line,
+                None => match self.lines.next() {
+                    Some(line) => {
+                        self.read += 1;
+                        line?
+                    }
+                    None => break,
+                },
+            };
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && in_movetext {
+                self.next = Some(line);
+                break;
+            }
+            if text.trim().is_empty() {
+                self.start = self.read;
+            }
+            in_movetext |= !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%');
+            text.push_str(&line);
+            text.push('\n');
+        }
+        Ok((!text.trim().is_empty()).then_some(text))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_text() {
+            Ok(text) => text.map(|text| read(&text)),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(PgnError::Io(err.to_string())))
+            }
+        }
+    }
+}
+
+// Reads one game: tag pairs, one to a line, then the movetext. Move
+// numbers may be there or not. Comments, in braces or after a semicolon,
+// and NAGs go with the move before them, as do variations in brackets,
+// and the traditional suffixes such as "!?" on a move become NAGs too.
+// Without a FEN tag the game starts from the usual position.
+pub fn read(text: &str) -> Result<PgnGame, PgnError> {
+    let mut tags = Tags::new();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if movetext.trim().is_empty() && trimmed.starts_with('[') {
+            let (name, value) = parse_tag(trimmed).ok_or_else(|| PgnError::Tag(trimmed.to_string()))?;
+            tags.set(&name, &value);
+        } else if !trimmed.starts_with('%') {
+            // Lines starting with % are escaped, for other software to read.
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let game = match tags.fen() {
+        Some(fen) => Game::from_fen(fen).map_err(PgnError::Fen)?,
+        None => Game::new(),
+    };
+    let result = tags.result();
+    let mut record = PgnGame::new(tags, game, result);
+    let mut tokens = movetext_tokens(&movetext)?.into_iter();
+    if let Some(result) = read_line(&mut tokens, &mut record.game, &mut record.comments, &mut record.annotations, false)? {
+        record.result = result;
+    }
+    Ok(record)
+}
+
+// Plays the moves of a line, the main line or a variation, from `tokens`
+// on `game`, until the variation's closing bracket or the end of the
+// movetext, keeping the comments before its first move and the
+// annotations of each. Returns the main line's result, if it has one.
+fn read_line(
+    tokens: &mut impl Iterator<Item = Token>,
+    game: &mut Game,
+    comments: &mut Vec<String>,
+    annotations: &mut Vec<Annotation>,
+    nested: bool,
+) -> Result<Option<Option<GameResult>>, PgnError> {
+    let first = game.moves.len();
+    let mut ended = None;
+    while let Some(token) = tokens.next() {
+        let played = game.moves.len() - first;
+        match token {
+            Token::Comment(comment) if played == 0 => comments.push(comment),
+            Token::Comment(comment) => annotation_at(annotations, played - 1).comments.push(comment),
+            Token::Nag(nag) if played > 0 => annotation_at(annotations, played - 1).nags.push(nag),
+            Token::Nag(_) => {}
+            Token::Close if nested => return Ok(None),
+            Token::Close => return Err(PgnError::Movetext("\")\" without a variation to close".to_string())),
+            _ if ended.is_some() => return Err(PgnError::Movetext("movetext after the result".to_string())),
+            Token::Result(_) if nested => return Err(PgnError::Movetext("result inside a variation".to_string())),
+            Token::Result(result) => ended = Some(result),
+            Token::Open if played == 0 => return Err(PgnError::Movetext("variation before the first move".to_string())),
+            Token::Open => {
+                // The variation replaces the last move, so it starts from
+                // the position before it.
+                let mut branch = Game::from_fen(&game.start).expect("the game was read from this position");
+                for &mv in &game.moves[..game.moves.len() - 1] {
+                    branch.play_move(mv);
+                }
+                let mut variation = Variation::default();
+                read_line(tokens, &mut branch, &mut variation.comments, &mut variation.annotations, true)?;
+                variation.moves = branch.moves[game.moves.len() - 1..].to_vec();
+                annotation_at(annotations, played - 1).variations.push(variation);
+            }
+            Token::Move(san) => {
+                let mv = san::parse(game, &san).map_err(|err| PgnError::Move(game.fullmove_number, game.turn, err))?;
+                game.play_move(mv);
+                let suffix = &san[san.trim_end_matches(['!', '?']).len()..];
+                if let Some(nag) = SUFFIXES.iter().position(|&s| s == suffix) {
+                    annotation_at(annotations, played).nags.push(nag as u8 + 1);
+                }
+            }
+        }
+    }
+    if nested {
+        return Err(PgnError::Movetext("variation left open".to_string()));
+    }
+    Ok(ended)
+}
+
+// The move suffixes that stand for NAGs $1 to $6.
+const SUFFIXES: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+// `[Name "value"]` as its name and unescaped value.
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some((name.to_string(), unescaped))
+}
+
+enum Token {
+    Move(String),
+    Nag(u8),
+    Comment(String),
+    // The brackets around a variation.
+    Open,
+    Close,
+    // None for "*".
+    Result(Option<GameResult>),
+}
+
+// The movetext's moves, annotations, variations and result, without the
+// move numbers.
+fn movetext_tokens(movetext: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = vec![];
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::Movetext("comment left open".to_string())),
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek().filter(|&&c| !c.is_whitespace() && !"{;()".contains(c)) {
+                    token.push(c);
+                    chars.next();
+                }
+                // "12." and "12..." number the moves, and may run into
+                // the move itself ("12.e4").
+                let token = match token.trim_start_matches(|c: char| c.is_ascii_digit()).strip_prefix('.') {
+                    Some(rest) => rest.trim_start_matches('.'),
+                    None => &token,
+                };
+                if token.is_empty() {
+                    continue;
+                }
+                tokens.push(match token {
+                    "1-0" => Token::Result(Some(GameResult::WhiteWins)),
+                    "0-1" => Token::Result(Some(GameResult::BlackWins)),
+                    "1/2-1/2" => Token::Result(Some(GameResult::Draw)),
+                    "*" => Token::Result(None),
+                    _ => match token.strip_prefix('$') {
+                        Some(nag) => Token::Nag(nag.parse().map_err(|_| PgnError::Movetext(format!("bad NAG {}", token)))?),
+                        None => Token::Move(token.to_string()),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `uci`'s moves played in turn from `game`.
+    fn moves(game: &Game, uci: &[&str]) -> Vec<Move> {
+        let mut game = game.clone();
+        uci.iter()
+            .map(|text| {
+                let mv = game.parse_uci(text).unwrap();
+                game.play_move(mv);
+                mv
+            })
+            .collect()
+    }
+
+    #[test]
+    fn variations_round_trip() {
+        let mut game = Game::new();
+        for mv in moves(&game, &["e2e4", "e7e5"]) {
+            game.play_move(mv);
+        }
+        let mut record = PgnGame::new(Tags::new(), game, None);
+        let mut variation = Variation::new(moves(&Game::new(), &["d2d4", "d7d5"]));
+        variation.annotation_mut(1).comments.push("symmetrical".to_string());
+        record.add_variation(0, variation.clone());
+
+        let mut text = vec![];
+        write(&mut text, &record).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.ends_with("\n1. e4 (1. d4 d5 {symmetrical}) 1... e5 *\n\n"), "{}", text);
+        let read = read(&text).unwrap();
+        assert_eq!(read.annotations[0].variations, vec![variation]);
+        assert_eq!(read.game.moves, record.game.moves);
+    }
+
+    #[test]
+    fn game_round_trip() {
+        // An en passant capture, a promotion by capture and castling.
+        let uci = ["e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "e7e6", "f6g7", "e8f7", "g7h8q", "d8e7", "g1f3", "c8d7", "f1c4", "b8c6", "e1g1"];
+        let mut game = Game::new();
+        for mv in moves(&game, &uci) {
+            game.play_move(mv);
+        }
+        let mut record = PgnGame::new(Tags::roster("White", "Black", None), game, None);
+        record.annotation_mut(4).nags.push(1);
+        let mut text = vec![];
+        write(&mut text, &record).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("\n1. e4 d5 2. e5 f5 3. exf6 $1 e6 4. fxg7 Kf7 5. gxh8=Q Qe7 6. Nf3 Bd7 7. Bc4 Nc6\n8. O-O *\n"), "{}", text);
+
+        let read = read(&text).unwrap();
+        assert_eq!(read.game.moves, record.game.moves);
+        assert_eq!(read.tags, record.tags);
+        assert_eq!(read.annotations[4].nags, vec![1]);
+        let mut again = vec![];
+        write(&mut again, &read).unwrap();
+        assert_eq!(String::from_utf8(again).unwrap(), text);
+    }
+
+    #[test]
+    fn typed_tags() {
+        let mut tags = Tags::roster("Carlsen", "Caruana", None);
+        tags.set("Date", "2018.11.??");
+        tags.set_white_elo(2835);
+        tags.set_black_elo(2832);
+        assert_eq!(tags.black_elo(), Some(2832));
+        tags.set("BlackElo", "-");
+        let clock = TimeControl { remaining: Duration::from_secs(300), increment: Duration::from_secs(2), moves_to_go: None };
+        tags.set_time_control(&clock);
+        assert_eq!(tags.get("TimeControl"), Some("300+2"));
+        assert_eq!((tags.white(), tags.black(), tags.event()), (Some("Carlsen"), Some("Caruana"), Some("Casual game")));
+        assert_eq!(tags.date(), Some((Some(2018), Some(11), None)));
+        assert_eq!((tags.white_elo(), tags.black_elo()), (Some(2835), None));
+        assert_eq!(tags.time_control(), Some(clock));
+
+        tags.set("TimeControl", "40/7200:3600");
+        let clock = TimeControl { remaining: Duration::from_secs(7200), increment: Duration::ZERO, moves_to_go: Some(40) };
+        assert_eq!(tags.time_control(), Some(clock));
+        tags.set_time_control(&clock);
+        assert_eq!(tags.get("TimeControl"), Some("40/7200"));
+        tags.set("TimeControl", "-");
+        assert_eq!(tags.time_control(), None);
+    }
+
+    #[test]
+    fn import_export_round_trip() {
+        let text = "[Event \"Training\"]\n[Site \"?\"]\n[Date \"2024.01.02\"]\n[Round \"-\"]\n\
+                    [White \"A\"]\n[Black \"B\"]\n[Result \"1-0\"]\n[SetUp \"1\"]\n\
+                    [FEN \"4k3/8/8/8/8/8/8/R3K3 w Q - 0 1\"]\n[WhiteElo \"2100\"]\n\
+                    [Annotator \"Someone \\\"quoted\\\"\"]\n[TimeControl \"60+1\"]\n\n\
+                    1. Ra7 {cutting the king off} 1... Kd8 2. Kd2 $1 1-0\n\n";
+        let record = read(text).unwrap();
+        assert_eq!(record.tags.fen(), Some("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"));
+        assert_eq!(record.tags.white_elo(), Some(2100));
+        assert_eq!(record.tags.get("Annotator"), Some("Someone \"quoted\""));
+        assert_eq!(record.result, Some(GameResult::WhiteWins));
+        let mut written = vec![];
+        write(&mut written, &record).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), text);
+    }
+}
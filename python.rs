@@ -0,0 +1,272 @@
+// Python bindings, built with the `python` feature through PyO3 as the
+// extension module `chess_in_rust` (for instance with maturin), for
+// generating and analysing games from Python at the engine's speed:
+//
+//     from chess_in_rust import Game
+//     game = Game()                    # or Game("<fen>")
+//     game.play("e4")                  # SAN, UCI or a Move
+//     move = game.best_move(depth=10)  # or movetime=500, in ms
+//     game.analyse(depth=12, multipv=3)
+//                                      # [{"cp": 31, "mate": None, "pv": [Move, ...]}, ...]
+//     game.board.piece_at("e4")        # "P"
+//     game.outcome                     # None, or ("1-0", "Checkmate")
+//     game.pgn()
+//
+// The search lets go of the GIL while it thinks, so other Python threads
+// carry on, and each `Game` keeps its searcher's table between searches.
+
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::pgn::{self, PgnGame, Tags};
+use crate::san;
+use crate::search::{mate_in, SearchLimits, Searcher};
+use crate::{BoardStyle, Color, Game, Move, ParseMoveError, Square};
+
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+
+#[pyclass(name = "Move", module = "chess_in_rust", frozen, eq)]
+#[derive(Clone, PartialEq)]
+pub struct PyMove {
+    mv: Move,
+}
+
+#[pymethods]
+impl PyMove {
+    // In UCI coordinates, "e2e4" or "e7e8q".
+    #[getter]
+    fn uci(&self) -> String {
+        self.mv.to_string()
+    }
+
+    #[getter(from_square)]
+    fn origin(&self) -> String {
+        self.mv.from.to_string()
+    }
+
+    #[getter(to_square)]
+    fn destination(&self) -> String {
+        self.mv.to.to_string()
+    }
+
+    // The piece a pawn promotes to, as a lower-case letter.
+    #[getter]
+    fn promotion(&self) -> Option<char> {
+        self.mv.promotion.map(|piece| san::letter(piece).to_ascii_lowercase())
+    }
+
+    fn __str__(&self) -> String {
+        self.mv.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Move('{}')", self.mv)
+    }
+}
+
+// A position, apart from the game that led to it.
+#[pyclass(name = "Board", module = "chess_in_rust", frozen)]
+pub struct PyBoard {
+    game: Game,
+}
+
+#[pymethods]
+impl PyBoard {
+    #[getter]
+    fn fen(&self) -> String {
+        self.game.to_fen()
+    }
+
+    // "white" or "black".
+    #[getter]
+    fn turn(&self) -> &'static str {
+        color_name(self.game.turn)
+    }
+
+    // The piece on `square` ("e4") as its FEN letter, "P" for a white pawn
+    // and "p" for a black one, or None.
+    fn piece_at(&self, square: &str) -> PyResult<Option<char>> {
+        let square = square.parse::<Square>().map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(self.game.piece_at(square).map(|piece| piece.to_char()))
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_in_check(self.game.turn)
+    }
+
+    fn __str__(&self) -> String {
+        self.game.render(BoardStyle { coordinates: true, ..BoardStyle::default() })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Board('{}')", self.game.to_fen())
+    }
+}
+
+#[pyclass(name = "Game", module = "chess_in_rust")]
+pub struct PyGame {
+    game: Game,
+    searcher: Searcher,
+}
+
+// A move as Python may give it.
+#[derive(FromPyObject)]
+enum MoveArg {
+    Move(PyMove),
+    Text(String),
+}
+
+#[pymethods]
+impl PyGame {
+    #[new]
+    #[pyo3(signature = (fen = None))]
+    fn new(fen: Option<&str>) -> PyResult<PyGame> {
+        let game = match fen {
+            Some(fen) => Game::from_fen(fen).map_err(|err| PyValueError::new_err(err.to_string()))?,
+            None => Game::new(),
+        };
+        Ok(PyGame { game, searcher: Searcher::new() })
+    }
+
+    #[getter]
+    fn fen(&self) -> String {
+        self.game.to_fen()
+    }
+
+    #[getter]
+    fn turn(&self) -> &'static str {
+        color_name(self.game.turn)
+    }
+
+    #[getter]
+    fn board(&self) -> PyBoard {
+        PyBoard { game: self.game.clone() }
+    }
+
+    // Every move played, from the starting position.
+    #[getter]
+    fn moves(&self) -> Vec<PyMove> {
+        self.game.moves.iter().map(|&mv| PyMove { mv }).collect()
+    }
+
+    fn legal_moves(&self) -> Vec<PyMove> {
+        self.game.legal_moves().iter().map(|&mv| PyMove { mv }).collect()
+    }
+
+    // Plays `mv`, a Move or a string in SAN or UCI, and returns it as a
+    // Move. Raises ValueError if it isn't legal.
+    fn play(&mut self, mv: MoveArg) -> PyResult<PyMove> {
+        let mv = match mv {
+            MoveArg::Move(PyMove { mv }) if self.game.legal_moves().contains(&mv) => mv,
+            MoveArg::Move(PyMove { mv }) => return Err(PyValueError::new_err(format!("illegal move {}", mv))),
+            MoveArg::Text(text) => parse_move(&self.game, &text)?,
+        };
+        self.game.play_move(mv);
+        Ok(PyMove { mv })
+    }
+
+    // `mv` in SAN, for the position now.
+    fn san(&self, mv: PyMove) -> PyResult<String> {
+        if !self.game.legal_moves().contains(&mv.mv) {
+            return Err(PyValueError::new_err(format!("illegal move {}", mv.mv)));
+        }
+        Ok(san::format(&self.game, mv.mv))
+    }
+
+    // None while the game goes on, else its result and how it ended, such
+    // as ("1-0", "Checkmate").
+    #[getter]
+    fn outcome(&self) -> Option<(String, String)> {
+        self.game.outcome().map(|outcome| (outcome.result.to_string(), format!("{:?}", outcome.reason)))
+    }
+
+    // The engine's move, searching to `depth` plies or for `movetime`
+    // milliseconds, whichever comes first, or for a second if neither is
+    // given. None once the game is over.
+    #[pyo3(signature = (depth = None, movetime = None))]
+    fn best_move(&mut self, py: Python<'_>, depth: Option<u32>, movetime: Option<u64>) -> Option<PyMove> {
+        let limits = limits(depth, movetime);
+        let PyGame { game, searcher } = self;
+        searcher.multi_pv = 1;
+        py.allow_threads(|| searcher.search(game, &limits)).best_move.map(|mv| PyMove { mv })
+    }
+
+    // The `multipv` best lines, best first, each a dict of its score, in
+    // centipawns for the side to move ("cp") or as moves to mate ("mate",
+    // negative when it is being mated), and its moves ("pv").
+    #[pyo3(signature = (depth = None, movetime = None, multipv = 1))]
+    fn analyse<'py>(
+        &mut self,
+        py: Python<'py>,
+        depth: Option<u32>,
+        movetime: Option<u64>,
+        multipv: usize,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let limits = limits(depth, movetime);
+        let PyGame { game, searcher } = self;
+        searcher.multi_pv = multipv.max(1);
+        let result = py.allow_threads(|| searcher.search(game, &limits));
+        result
+            .lines
+            .iter()
+            .map(|line| {
+                let dict = PyDict::new_bound(py);
+                let mate = mate_in(line.score);
+                dict.set_item("cp", if mate.is_none() { Some(line.score) } else { None })?;
+                dict.set_item("mate", mate)?;
+                dict.set_item("pv", line.moves.iter().map(|&mv| PyMove { mv }).collect::<Vec<_>>())?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    // The game as PGN, with `white` and `black` for the players' names.
+    #[pyo3(signature = (white = "?", black = "?"))]
+    fn pgn(&self, white: &str, black: &str) -> String {
+        let result = self.game.outcome().map(|outcome| outcome.result);
+        let record = PgnGame::new(Tags::roster(white, black, result), self.game.clone(), result);
+        let mut text = vec![];
+        pgn::write(&mut text, &record).expect("writing to memory doesn't fail");
+        String::from_utf8(text).expect("PGN is written as UTF-8")
+    }
+
+    fn __str__(&self) -> String {
+        self.board().__str__()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Game('{}')", self.game.to_fen())
+    }
+}
+
+#[pymodule]
+fn chess_in_rust(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyGame>()?;
+    module.add_class::<PyBoard>()?;
+    module.add_class::<PyMove>()?;
+    Ok(())
+}
+
+fn limits(depth: Option<u32>, movetime: Option<u64>) -> SearchLimits {
+    let move_time = movetime.map(Duration::from_millis).or(if depth.is_none() { Some(DEFAULT_MOVE_TIME) } else { None });
+    SearchLimits { depth, move_time, ..SearchLimits::default() }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+// A move in UCI coordinates or, failing that, SAN.
+fn parse_move(game: &Game, text: &str) -> PyResult<Move> {
+    match game.parse_uci(text) {
+        Ok(mv) => Ok(mv),
+        Err(ParseMoveError::Invalid(_)) => san::parse(game, text).map_err(|err| PyValueError::new_err(err.to_string())),
+        Err(err) => Err(PyValueError::new_err(err.to_string())),
+    }
+}
@@ -0,0 +1,220 @@
+// Standard Algebraic Notation (SAN), the way PGN and most chess books
+// record moves: the piece's letter (none for a pawn), as much of the
+// square it comes from as tells it apart from other pieces of the same
+// kind that could go to the same square, "x" for a capture, the square it
+// goes to, "=Q" and the like for a promotion, and "+" for check or "#" for
+// mate. A pawn capture names the pawn's file ("exd5"); castling is "O-O"
+// on the king's side and "O-O-O" on the queen's. `format` writes a move
+// down and `parse` reads one back.
+
+use std::fmt;
+
+use crate::{File, Game, Move, MoveKind, Piece, Rank, Square};
+
+// The SAN of `mv`, a legal move in `game`.
+pub fn format(game: &Game, mv: Move) -> String {
+    let mut san = String::new();
+    if mv.kind == MoveKind::Castle {
+        san.push_str(if mv.to.file() > mv.from.file() { "O-O" } else { "O-O-O" });
+    } else {
+        if mv.piece == Piece::Pawn {
+            if mv.is_capture() {
+                san.push_str(&mv.from.file().to_string());
+            }
+        } else {
+            san.push(letter(mv.piece));
+            san.push_str(&disambiguation(game, mv));
+        }
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(letter(promotion));
+        }
+    }
+
+    let mut after = game.clone();
+    after.make_move(mv);
+    if after.is_in_check(after.turn) {
+        san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+    }
+    san
+}
+
+// The piece's letter in English SAN.
+pub fn letter(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Rook => 'R',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+// The file, the rank or both of the from square, whichever is the first
+// to single `mv` out among the moves of its kind of piece to its square.
+fn disambiguation(game: &Game, mv: Move) -> String {
+    let rivals: Vec<Move> =
+        game.legal_moves().iter().copied().filter(|other| other.piece == mv.piece && other.to == mv.to && other.from != mv.from).collect();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|other| other.from.file() != mv.from.file()) {
+        mv.from.file().to_string()
+    } else if rivals.iter().all(|other| other.from.rank() != mv.from.rank()) {
+        mv.from.rank().to_string()
+    } else {
+        mv.from.to_string()
+    }
+}
+
+// Why a string can't be read as a move in the position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    // Not SAN at all.
+    Invalid(String),
+    // SAN for a move the position doesn't allow.
+    Illegal(String),
+    // Fits more than one legal move, for want of disambiguation.
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "{:?} is not a move in SAN", san),
+            SanError::Illegal(san) => write!(f, "{} is not a legal move here", san),
+            SanError::Ambiguous(san) => write!(f, "{} could be more than one move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+// The legal move in `game` that `san` describes. Check and mate marks and
+// annotations such as "!?" may be there or not, whether or not they are
+// right; castling may be written with zeros ("0-0") as well, and a
+// capture without its "x".
+pub fn parse(game: &Game, san: &str) -> Result<Move, SanError> {
+    let invalid = || SanError::Invalid(san.to_string());
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = game.legal_moves();
+
+    let castle = match text {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    let candidates: Vec<Move> = if let Some(kingside) = castle {
+        legal.iter().copied().filter(|mv| mv.kind == MoveKind::Castle && (mv.to.file() > mv.from.file()) == kingside).collect()
+    } else {
+        let (piece, rest) = match text.chars().next().and_then(piece_for) {
+            Some(piece) => (piece, &text[1..]),
+            None => (Piece::Pawn, text),
+        };
+        // Some software leaves out the "=" of a promotion ("e8Q").
+        let (rest, promotion) = match rest.split_once('=') {
+            Some((rest, letter)) => {
+                let mut letters = letter.chars();
+                match (letters.next().and_then(piece_for), letters.next()) {
+                    (Some(promotion), None) => (rest, Some(promotion)),
+                    _ => return Err(invalid()),
+                }
+            }
+            None => match rest.chars().last().and_then(piece_for) {
+                Some(promotion) if piece == Piece::Pawn => (&rest[..rest.len() - 1], Some(promotion)),
+                _ => (rest, None),
+            },
+        };
+        let to: Square = rest.get(rest.len().saturating_sub(2)..).and_then(|to| to.parse().ok()).ok_or_else(invalid)?;
+        // What is left before the square says which piece moves, and
+        // whether it captures.
+        let hint = rest[..rest.len() - 2].replacen('x', "", 1);
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in hint.chars() {
+            match (File::from_char(c), Rank::from_char(c)) {
+                (Some(file), _) if from_file.is_none() && from_rank.is_none() => from_file = Some(file),
+                (_, Some(rank)) if from_rank.is_none() => from_rank = Some(rank),
+                _ => return Err(invalid()),
+            }
+        }
+        legal
+            .iter()
+            .copied()
+            .filter(|mv| {
+                mv.piece == piece
+                    && mv.to == to
+                    && mv.promotion == promotion
+                    && from_file.is_none_or(|file| mv.from.file() == file)
+                    && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+            })
+            .collect()
+    };
+
+    match candidates[..] {
+        [mv] => Ok(mv),
+        [] => Err(SanError::Illegal(san.to_string())),
+        _ => Err(SanError::Ambiguous(san.to_string())),
+    }
+}
+
+// The piece with this upper-case SAN letter, pawns aside.
+fn piece_for(c: char) -> Option<Piece> {
+    match c {
+        'R' => Some(Piece::Rook),
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_legal_move_round_trips() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            // Three queens that can go to e4, for each kind of disambiguation.
+            "1k6/8/8/8/Q6Q/8/8/K6Q w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            for mv in game.legal_moves() {
+                let san = format(&game, mv);
+                assert_eq!(parse(&game, &san), Ok(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let game = Game::from_fen("1k6/8/8/8/Q6Q/8/8/K6Q w - - 0 1").unwrap();
+        let san = |uci: &str| format(&game, game.parse_uci(uci).unwrap());
+        assert_eq!(san("h4e4"), "Qh4e4");
+        assert_eq!(san("a4e4"), "Qae4");
+        assert_eq!(san("h1e4"), "Q1e4");
+        assert_eq!(san("h4h8"), "Qh8+");
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let san = |uci: &str| format(&game, game.parse_uci(uci).unwrap());
+        assert_eq!((san("e1g1"), san("e1c1")), ("O-O".to_string(), "O-O-O".to_string()));
+        assert_eq!(san("d5e6"), "dxe6");
+        assert_eq!(san("c3b1"), "Nb1");
+        assert_eq!(san("e5f7"), "Nxf7");
+        let game = Game::from_fen("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let san = |uci: &str| format(&game, game.parse_uci(uci).unwrap());
+        assert_eq!(san("b7a8q"), "bxa8=Q+");
+        assert_eq!(san("b7b8n"), "b8=N");
+        let game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(format(&game, game.parse_uci("f7g7").unwrap()), "Qg7#");
+    }
+}
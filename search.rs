@@ -0,0 +1,1213 @@
+// Choosing a move by looking ahead: every line of play is followed to a
+// fixed depth and the positions at the end are scored by the static
+// evaluation, each side picking the move that is best for it (minimax,
+// written in negamax form so both sides maximise their own score).
+//
+// Alpha-beta pruning skips the lines that can't matter: alpha is the score
+// the side to move is already sure of elsewhere, beta the most the opponent
+// will allow. Once a move reaches beta the opponent won't play into this
+// position, so its remaining moves needn't be looked at. Scores are
+// fail-soft: a node that fails high or low returns the best score it saw,
+// which may lie outside the window.
+//
+// The search deepens iteratively: depth 1, then 2, and so on, until one of
+// the `SearchLimits` is reached. Each iteration starts from the previous
+// one's best move. When one is cut short, by the clock or by a
+// `SearchHandle` from another thread, only the root moves it finished
+// searching count: the first of them is the previous best move searched
+// deeper, and any that beat it did so in a full search, so there is
+// always a fully searched move to play.
+//
+// At the end of each line a quiescence search plays out the captures still
+// on the board, so that a position isn't scored in the middle of an
+// exchange with a piece left hanging just beyond the horizon.
+//
+// Moves are tried best-first: the move the transposition table remembers
+// from an earlier visit, then captures that don't lose material by static
+// exchange evaluation, then the "killer" quiet moves that caused a cutoff
+// elsewhere at the same ply, then the remaining quiet moves by how often
+// the same move has caused cutoffs anywhere in the tree (the history
+// heuristic), and losing captures last.
+//
+// Good ordering means the moves that come late rarely turn out best, so
+// late quiet moves are searched less deeply (late move reductions), and
+// searched again at full depth only if they unexpectedly beat alpha.
+//
+// Close to the horizon, a position whose static evaluation is far enough
+// above beta is taken to fail high without a search (reverse futility
+// pruning), and quiet moves that would need to gain more than a margin to
+// reach alpha are skipped (futility pruning).
+//
+// Checks are searched one ply deeper than other moves, so that forcing
+// lines and mates are seen through. The extensions stop once a line is
+// twice as long as the iteration's depth, or perpetual checks would keep
+// the search going for ever.
+//
+// A transposition table move that beats every alternative by a margin is
+// singular: the position hangs on it, so it is extended by a ply as well.
+// That is found by a reduced search of the same node without the move,
+// against a bound just below the move's remembered score; if nothing else
+// reaches that bound, the move is singular.
+//
+// For the same reason every move after the first is searched with a null
+// window around alpha (principal variation search), which only proves it
+// no better than the first and is much cheaper than finding its score;
+// the few that fail high are searched again with the full window.
+//
+// In MultiPV mode each iteration searches the root several times, leaving
+// out the moves already chosen, to find the best few moves rather than
+// just the best one. Their principal variations are read back from the
+// transposition table.
+//
+// With more than one thread the search is "lazy SMP": helper threads run
+// the same iterative deepening on their own, sharing only the
+// transposition table, and speed the main thread up by filling it. Every
+// other helper searches one ply deeper so the threads don't all search
+// the same nodes in step. The main thread's result is the one returned.
+//
+// Pondering searches the position after the reply the engine expects while
+// the opponent thinks. Until the opponent plays that move (a "ponder hit")
+// the clock is ignored; then the same search carries on as the real one,
+// its time limit counted from the hit.
+//
+// Embedders can follow a search's progress through a callback, called on
+// the main thread with a `SearchInfo` whenever an iteration (or a MultiPV
+// line of one) is complete.
+//
+// A position that repeats one earlier in the game or in the line being
+// searched scores as a draw, as does one where the fifty-move rule has run
+// out: the side that is better would otherwise take a repetition for
+// progress, and the side that is worse wouldn't see its way out. A single
+// repetition is enough, since whatever was possible the first time will
+// be possible again.
+// Contempt shifts a draw's score from nothing to a little below it for
+// the side the search is for, and above it for the opponent.
+//
+// With endgame tablebases (see syzygy.rs) a position with few enough
+// pieces is looked up rather than searched. At the root the tables pick
+// the move outright; inside the tree a position reached by a capture or
+// pawn move, which resets the fifty-move count as the win/draw/loss tables
+// assume, scores as a win or loss just short of a mate, or as a draw.
+//
+// For variety in casual and self-play games the searcher can be told to
+// play any root move scoring within a margin of the best one, picked at
+// random from a seed. The candidates are found as MultiPV lines, so their
+// scores are exact.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+use std::time::Instant;
+// The browser has no `std::time::Instant`.
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+use web_time::Instant;
+
+use crate::movelist::MoveList;
+use crate::nnue::Network;
+use crate::packed::PackedMove;
+use crate::pawns::PawnTable;
+use crate::timeman::{self, TimeBudget, TimeControl};
+use crate::tt::{Bound, TranspositionTable};
+use crate::eval::{self, Parameters};
+use crate::{CastlingRights, Color, Game, Move, Piece};
+
+// Worse than any evaluation. Being checkmated `ply` plies from the root
+// scores -(MATE - ply), so that quicker mates score better for the winner
+// and slower ones better for the loser.
+pub const MATE: i32 = 30_000;
+// Beyond any score, for the initial window.
+const INFINITY: i32 = MATE + 1;
+// Scores past this are mates rather than evaluations, which no margin
+// should be applied to.
+pub const MATE_BOUND: i32 = MATE - 1000;
+// How much a capture may gain beyond the captured piece's value, through
+// positional changes, before quiescence stops considering it.
+const DELTA_MARGIN: i32 = 200;
+// Deeper than any search goes, for the per-ply tables.
+const MAX_PLY: usize = 128;
+// The deepest iteration, leaving room below MAX_PLY for extensions and
+// quiescence.
+const MAX_DEPTH: u32 = 64;
+// History scores are halved when one passes this, keeping them below the
+// killer moves' place in the ordering.
+const HISTORY_LIMIT: i32 = 50_000;
+// Root moves searched as candidates when playing with variety.
+const VARIETY_CANDIDATES: usize = 4;
+// A tablebase win; less than any mate, and more than any evaluation even
+// counting down a ply at a time from it.
+const TB_WIN: i32 = MATE_BOUND - 1 - MAX_PLY as i32;
+
+// A tablebase's verdict on a position for the side to move. A cursed win
+// would be a win but for the fifty-move rule, which saves the other side
+// from a blessed loss. Only the `syzygy` build has tables to give one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "syzygy"), allow(dead_code))]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+// Endgame tablebases the search can look positions up in.
+pub trait Tablebase: Send + Sync {
+    // The most pieces, kings included, of any position the tables have.
+    fn max_pieces(&self) -> u32;
+    // The result with best play in `game`, taking the fifty-move count as
+    // just reset, or None if the tables don't have it.
+    fn probe_wdl(&self, game: &Game) -> Option<Wdl>;
+    // The move that keeps the best result and heads for it fastest, and
+    // that result, or None if the tables don't have the position.
+    fn best_move(&self, game: &Game) -> Option<(Move, Wdl)>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    // None when the side to move has no legal moves.
+    pub best_move: Option<Move>,
+    // From the side to move's point of view, in centipawns.
+    pub score: i32,
+    // The best root moves found, best first, one per MultiPV line.
+    pub lines: Vec<PvLine>,
+    // The deepest iteration that finished.
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+impl SearchResult {
+    // The reply the search expects, to ponder on.
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.lines.first().and_then(|line| line.moves.get(1)).copied()
+    }
+}
+
+// Progress report for one completed line of an iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    // The deepest ply any line reached, quiescence included.
+    pub seldepth: u32,
+    // Across all threads.
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: Duration,
+    // Transposition table use in permille.
+    pub hashfull: u32,
+    // 1 for the best line, 2 for the second best and so on.
+    pub multi_pv: usize,
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    // Moves to mate, if the score is a mate score; see `mate_in`.
+    pub fn mate(&self) -> Option<i32> {
+        mate_in(self.score)
+    }
+}
+
+// For a mate score, the number of moves (not plies) to mate: positive
+// when the side to move mates, negative when it is mated. None for an
+// ordinary evaluation.
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE_BOUND {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+
+// Mate scores count plies from the root, but a table entry may be read at
+// another ply than it was stored at, so it holds them counted from the
+// position itself.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+type InfoCallback = Box<dyn FnMut(&SearchInfo) + Send>;
+
+// State shared by every thread of a search and by its handles.
+struct Control {
+    // Nodes searched by all threads, counted in batches.
+    nodes: AtomicU64,
+    // Set once time is up, the main thread finishes or a handle stops the
+    // search, unwinding it on every thread.
+    stop: AtomicBool,
+    // Set while pondering, when there is no time limit yet.
+    pondering: AtomicBool,
+    // When the clock started: at the start of the search, or at the ponder
+    // hit.
+    clock: Mutex<Instant>,
+}
+
+impl Control {
+    fn elapsed(&self) -> Duration {
+        self.clock.lock().map_or(Duration::ZERO, |clock| clock.elapsed())
+    }
+
+    fn restart_clock(&self) {
+        if let Ok(mut clock) = self.clock.lock() {
+            *clock = Instant::now();
+        }
+    }
+}
+
+// Lets another thread steer a search running in a `Searcher`.
+#[derive(Clone)]
+pub struct SearchHandle {
+    control: Arc<Control>,
+}
+
+impl SearchHandle {
+    // The opponent played the move being pondered on: the search becomes
+    // the real one, with its time limit starting now.
+    pub fn ponderhit(&self) {
+        self.control.restart_clock();
+        self.control.pondering.store(false, Ordering::Relaxed);
+    }
+
+    // Ends the search, which returns the best move found so far. This is
+    // also how a ponder search is abandoned when the opponent plays
+    // something else.
+    pub fn stop(&self) {
+        self.control.pondering.store(false, Ordering::Relaxed);
+        self.control.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// When a search should stop. Every limit that is set applies, whichever is
+// reached first; with none set the search goes on to its greatest depth.
+// The first iteration always finishes, so there is a move to play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchLimits {
+    // Iterations to complete at most, in plies.
+    pub depth: Option<u32>,
+    // Nodes to search at most, across all threads.
+    pub nodes: Option<u64>,
+    // Time to think for exactly, give or take the iteration in progress.
+    pub move_time: Option<Duration>,
+    // Stop once a mate in this many moves or fewer is found.
+    pub mate: Option<u32>,
+    // The side to move's clock, for the time manager to budget from.
+    pub clock: Option<TimeControl>,
+    // Search until stopped through a `SearchHandle`, ignoring the other
+    // limits.
+    pub infinite: bool,
+}
+
+// A root move's score and the line of play the search expects after it,
+// starting with the move itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: i32,
+    pub moves: Vec<Move>,
+}
+
+// Knobs for tuning the search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchParams {
+    // Late move reductions apply from this remaining depth...
+    pub lmr_min_depth: u32,
+    // ...to moves after this many have been searched at the node,
+    pub lmr_min_moves: usize,
+    // reducing by lmr_base + ln(depth) * ln(moves) / lmr_divisor plies.
+    pub lmr_base: f64,
+    pub lmr_divisor: f64,
+    // Futility pruning applies up to this remaining depth, skipping quiet
+    // moves when the static evaluation plus futility_margin per ply of
+    // depth is still no better than alpha.
+    pub futility_max_depth: u32,
+    pub futility_margin: i32,
+    // Reverse futility pruning applies up to this depth, failing high when
+    // the static evaluation minus reverse_futility_margin per ply is still
+    // at least beta.
+    pub reverse_futility_max_depth: u32,
+    pub reverse_futility_margin: i32,
+    // Singular extensions apply from this remaining depth, to a move whose
+    // table score beats every other move's by singular_margin per ply.
+    pub singular_min_depth: u32,
+    pub singular_margin: i32,
+}
+
+impl Default for SearchParams {
+    fn default() -> SearchParams {
+        SearchParams {
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            lmr_base: 0.75,
+            lmr_divisor: 2.25,
+            futility_max_depth: 2,
+            futility_margin: 150,
+            reverse_futility_max_depth: 3,
+            reverse_futility_margin: 120,
+            singular_min_depth: 6,
+            singular_margin: 2,
+        }
+    }
+}
+
+impl SearchParams {
+    // How many plies less to search the `moves`th move at `depth`.
+    fn reduction(&self, depth: u32, moves: usize) -> u32 {
+        if depth < self.lmr_min_depth || moves < self.lmr_min_moves {
+            return 0;
+        }
+        let reduction = self.lmr_base + (depth as f64).ln() * (moves as f64).ln() / self.lmr_divisor;
+        // Always leave at least one ply to search.
+        (reduction.max(0.0) as u32).min(depth.saturating_sub(2))
+    }
+}
+
+pub struct Searcher {
+    pub params: SearchParams,
+    // How many of the best root moves to find; 1 for normal play.
+    pub multi_pv: usize,
+    // Search threads, counting this one.
+    pub threads: usize,
+    // Plays a random root move scoring within this many centipawns of the
+    // best one, chosen by `seed` and the position; 0 always plays the best.
+    pub variety: i32,
+    pub seed: u64,
+    // Centipawns a draw scores below nothing for the side the search is
+    // for, so that it plays on against weaker opposition; negative makes
+    // it seek draws instead.
+    pub contempt: i32,
+    pub tablebase: Option<Arc<dyn Tablebase>>,
+    // Evaluates with this network, rather than any the game has, when set.
+    pub network: Option<Arc<Network>>,
+    on_info: Option<InfoCallback>,
+    tt: Arc<TranspositionTable>,
+    // Two quiet moves per ply that recently caused a beta cutoff there.
+    killers: [[PackedMove; 2]; MAX_PLY],
+    // The move left out at each ply by a singular extension's verification
+    // search, or NONE.
+    excluded: [PackedMove; MAX_PLY],
+    // Cutoffs caused by each quiet move, by color, from and to square,
+    // weighted towards those found deeper in the tree.
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    // The hand-crafted evaluation's weights, shared between the threads.
+    evaluation: Arc<Parameters>,
+    // Each thread has its own, as they are written to on every lookup.
+    pawns: PawnTable,
+    nodes: u64,
+    // The side to move at the root, whom contempt is for.
+    root_turn: Color,
+    // Depth of the iteration in progress.
+    root_depth: u32,
+    seldepth: usize,
+    // Only set on the main thread: helpers go on until it stops them.
+    limits: SearchLimits,
+    // Whether `limits` apply yet, which they don't until the first
+    // iteration is done.
+    limits_active: bool,
+    // Hashes of the positions from the start of the game down to the node
+    // being searched, for spotting repetitions.
+    path: Vec<u64>,
+    started: Instant,
+    // The time manager's share of `limits.clock`; see `TimeBudget`.
+    budget: Option<TimeBudget>,
+    control: Arc<Control>,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::with_hash_size(TranspositionTable::DEFAULT_SIZE_MB)
+    }
+
+    pub fn with_hash_size(size_mb: usize) -> Searcher {
+        Searcher {
+            params: SearchParams::default(),
+            multi_pv: 1,
+            threads: 1,
+            variety: 0,
+            seed: 0,
+            contempt: 0,
+            tablebase: None,
+            network: None,
+            on_info: None,
+            tt: Arc::new(TranspositionTable::new(size_mb)),
+            killers: [[PackedMove::NONE; 2]; MAX_PLY],
+            excluded: [PackedMove::NONE; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            evaluation: Arc::new(Parameters::default()),
+            pawns: PawnTable::default(),
+            nodes: 0,
+            root_turn: Color::White,
+            root_depth: 0,
+            seldepth: 0,
+            limits: SearchLimits::default(),
+            limits_active: false,
+            path: vec![],
+            started: Instant::now(),
+            budget: None,
+            control: Arc::new(Control {
+                nodes: AtomicU64::new(0),
+                stop: AtomicBool::new(false),
+                pondering: AtomicBool::new(false),
+                clock: Mutex::new(Instant::now()),
+            }),
+        }
+    }
+
+    // Calls `callback` with progress reports from now on. The callback can
+    // just as well forward them to a channel.
+    pub fn on_info(&mut self, callback: impl FnMut(&SearchInfo) + Send + 'static) {
+        self.on_info = Some(Box::new(callback));
+    }
+
+    // Evaluates with `params` from now on. The cached pawn structures were
+    // scored with the old ones, so they go.
+    pub fn set_evaluation(&mut self, params: Arc<Parameters>) {
+        self.evaluation = params;
+        self.pawns = PawnTable::default();
+    }
+
+    // Replaces the transposition table with an empty one of about
+    // `size_mb` megabytes.
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(size_mb));
+    }
+
+    // A handle for steering the next search from another thread, to be
+    // taken on the thread handing the search off before it does: that
+    // search starts with the flags set here, so a stop or ponder hit sent
+    // before it gets going still counts. `pondering` for one that is to go
+    // to `ponder`.
+    pub fn prepare(&self, pondering: bool) -> SearchHandle {
+        self.control.stop.store(false, Ordering::Relaxed);
+        self.control.pondering.store(pondering, Ordering::Relaxed);
+        SearchHandle { control: Arc::clone(&self.control) }
+    }
+
+    // A searcher for a helper thread, sharing this one's table and stop
+    // flag.
+    fn helper(&self) -> Searcher {
+        Searcher {
+            params: self.params,
+            multi_pv: 1,
+            threads: 1,
+            variety: 0,
+            seed: 0,
+            contempt: self.contempt,
+            tablebase: self.tablebase.clone(),
+            network: self.network.clone(),
+            on_info: None,
+            tt: Arc::clone(&self.tt),
+            killers: [[PackedMove::NONE; 2]; MAX_PLY],
+            excluded: [PackedMove::NONE; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            evaluation: Arc::clone(&self.evaluation),
+            pawns: PawnTable::default(),
+            nodes: 0,
+            root_turn: Color::White,
+            root_depth: 0,
+            seldepth: 0,
+            limits: SearchLimits::default(),
+            limits_active: false,
+            path: vec![],
+            started: Instant::now(),
+            budget: None,
+            control: Arc::clone(&self.control),
+        }
+    }
+
+    // How full the transposition table is, in permille.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn hashfull(&self) -> u32 {
+        self.tt.hashfull()
+    }
+
+    // Forgets what earlier searches learned, for a new game: the
+    // transposition table and the move ordering statistics.
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.killers = [[PackedMove::NONE; 2]; MAX_PLY];
+        *self.history = [[[0; 64]; 64]; 2];
+    }
+
+    // Searches until one of `limits` is reached. Depth 1 is always
+    // completed, so there is a move to return whenever one exists.
+    pub fn search(&mut self, game: &Game, limits: &SearchLimits) -> SearchResult {
+        self.run(game, limits)
+    }
+
+    // The flags in `control` are only ever cleared between searches, when
+    // this one is over or by `prepare`, never as a search starts.
+    fn run(&mut self, game: &Game, limits: &SearchLimits) -> SearchResult {
+        let result = self.run_once(game, limits);
+        self.control.stop.store(false, Ordering::Relaxed);
+        self.control.pondering.store(false, Ordering::Relaxed);
+        result
+    }
+
+    fn run_once(&mut self, game: &Game, limits: &SearchLimits) -> SearchResult {
+        self.limits = *limits;
+        self.budget = limits.clock.as_ref().map(timeman::allocate);
+        let max_depth = if limits.infinite { MAX_DEPTH } else { limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH) };
+        self.started = Instant::now();
+        self.control.nodes.store(0, Ordering::Relaxed);
+        self.control.restart_clock();
+        self.tt.new_search();
+
+        if let Some(result) = self.probe_root(game) {
+            self.hold();
+            return result;
+        }
+
+        let helpers: Vec<Searcher> = (1..self.threads.max(1)).map(|_| self.helper()).collect();
+        thread::scope(|scope| {
+            let helpers: Vec<_> = helpers
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut helper)| {
+                    scope.spawn(move || {
+                        helper.deepen(game, max_depth, 1 + (i as u32 + 1) % 2);
+                        helper.nodes
+                    })
+                })
+                .collect();
+
+            let mut result = self.deepen(game, max_depth, 1);
+            if self.variety > 0 {
+                self.vary(game, &mut result);
+            }
+            self.hold();
+            result.nodes += helpers.into_iter().map(|helper| helper.join().unwrap_or(0)).sum::<u64>();
+            result
+        })
+    }
+
+    // Waits until a ponder search's opponent has moved, or an infinite
+    // search is stopped, as neither may answer before then, and then stops
+    // any helpers.
+    fn hold(&self) {
+        while (self.limits.infinite || self.control.pondering.load(Ordering::Relaxed)) && !self.stopped() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        self.control.stop.store(true, Ordering::Relaxed);
+    }
+
+    // The tables' move at the root, if they have the position, as a search
+    // result with that one line.
+    fn probe_root(&mut self, game: &Game) -> Option<SearchResult> {
+        let (mv, wdl) = self.tablebase_for(game)?.best_move(game)?;
+        self.root_turn = game.turn;
+        let line = PvLine { score: self.tablebase_score(wdl, game.turn, 0), moves: vec![mv] };
+        self.report(1, 1, &line);
+        Some(SearchResult { best_move: Some(mv), score: line.score, lines: vec![line], depth: 1, nodes: 0 })
+    }
+
+    // The tables, if there are any and they may have `game`: it has few
+    // enough pieces and, as the tables never do, no castling rights.
+    fn tablebase_for(&self, game: &Game) -> Option<Arc<dyn Tablebase>> {
+        let tablebase = self.tablebase.as_ref()?;
+        let fits = game.castling == CastlingRights::NONE && game.bitboards.occupied().count() <= tablebase.max_pieces();
+        fits.then(|| Arc::clone(tablebase))
+    }
+
+    // A tablebase result as a score for `turn` at `ply`: a win just short
+    // of any mate, and sooner the nearer the root, or a draw, as the
+    // fifty-move rule makes cursed wins and blessed losses.
+    fn tablebase_score(&self, wdl: Wdl, turn: Color, ply: usize) -> i32 {
+        match wdl {
+            Wdl::Win => TB_WIN - ply as i32,
+            Wdl::Loss => -TB_WIN + ply as i32,
+            Wdl::BlessedLoss | Wdl::Draw | Wdl::CursedWin => self.draw_score(turn),
+        }
+    }
+
+    // Searches the position after `predicted`, the opponent's expected
+    // reply, until the handle from `prepare(true)` reports a ponder hit or
+    // is stopped. After a hit the search goes on as `search` would with the
+    // same limits, its clock starting at the hit.
+    pub fn ponder(&mut self, game: &Game, predicted: Move, limits: &SearchLimits) -> SearchResult {
+        let mut game = game.clone();
+        game.play_move(predicted);
+        self.run(&game, limits)
+    }
+
+    // Iterative deepening from `first_depth` to `max_depth` plies, or until
+    // one of the limits is reached once the first iteration is done.
+    fn deepen(&mut self, game: &Game, max_depth: u32, first_depth: u32) -> SearchResult {
+        let mut game = game.clone();
+        if self.network.is_some() {
+            game.set_network(self.network.clone());
+        }
+        self.nodes = 0;
+        self.root_turn = game.turn;
+        self.seldepth = 0;
+        self.path = game.history.clone();
+        self.limits_active = false;
+        self.killers = [[PackedMove::NONE; 2]; MAX_PLY];
+        // What was learned last time still helps, but less.
+        self.age_history();
+
+        let mut moves = game.legal_moves();
+        if moves.is_empty() {
+            return SearchResult { best_move: None, score: self.no_moves(&game, 0), lines: vec![], depth: 0, nodes: 0 };
+        }
+
+        let mut result = SearchResult {
+            best_move: Some(moves[0]),
+            score: 0,
+            lines: vec![PvLine { score: 0, moves: vec![moves[0]] }],
+            depth: 0,
+            nodes: 0,
+        };
+        // Iterations in a row that have kept the same best move.
+        let mut stable = 0;
+        'deepening: for depth in first_depth..=max_depth.max(first_depth) {
+            let mut lines: Vec<PvLine> = vec![];
+            let wanted = if self.variety > 0 { self.multi_pv.max(VARIETY_CANDIDATES) } else { self.multi_pv.max(1) };
+            while lines.len() < wanted {
+                let rest: MoveList = moves.iter().copied().filter(|mv| lines.iter().all(|line| line.moves[0] != *mv)).collect();
+                if rest.is_empty() {
+                    break;
+                }
+                let Some((mv, score)) = self.search_root(&mut game, &rest, depth) else {
+                    break 'deepening;
+                };
+                if self.stopped() {
+                    if lines.is_empty() {
+                        let moves = self.principal_variation(&mut game, mv, depth);
+                        result.best_move = Some(mv);
+                        result.score = score;
+                        result.lines.retain(|line| line.moves[0] != mv);
+                        result.lines.insert(0, PvLine { score, moves });
+                        result.lines.truncate(self.multi_pv.max(1));
+                    }
+                    break 'deepening;
+                }
+                if lines.is_empty() {
+                    self.tt.store(game.hash, mv.pack(), score, depth as u8, Bound::Exact);
+                }
+                lines.push(PvLine { score, moves: self.principal_variation(&mut game, mv, depth) });
+                if lines.len() <= self.multi_pv.max(1) {
+                    self.report(depth, lines.len(), &lines[lines.len() - 1]);
+                }
+            }
+            if result.depth > 0 && result.best_move == Some(lines[0].moves[0]) {
+                stable += 1;
+            } else {
+                stable = 0;
+            }
+            result = SearchResult { best_move: Some(lines[0].moves[0]), score: lines[0].score, lines, depth, nodes: self.nodes };
+
+            // Searching the best moves first lets the next iteration prune
+            // the rest against their scores straight away.
+            for (i, line) in result.lines.iter().enumerate() {
+                let at = moves.iter().position(|&mv| mv == line.moves[0]).unwrap_or(i);
+                moves[i..=at].rotate_right(1);
+            }
+
+            // Only after the first iteration, so there is always a move.
+            self.limits_active = true;
+            if self.past_limits() {
+                break;
+            }
+            if self.limits.infinite {
+                continue;
+            }
+            if self.limits.mate.is_some_and(|moves| mate_in(result.score).is_some_and(|mate| 0 < mate && mate <= moves as i32)) {
+                break;
+            }
+            // Another iteration likely wouldn't finish in time, or change
+            // the answer.
+            let pondering = self.control.pondering.load(Ordering::Relaxed);
+            if self.budget.is_some_and(|budget| !pondering && self.control.elapsed() >= budget.soft_limit(stable)) {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    // Swaps the best line for a random one within the variety margin, then
+    // drops the candidates beyond the MultiPV lines asked for. A mate is
+    // never given up for variety.
+    fn vary(&self, game: &Game, result: &mut SearchResult) {
+        if let Some(best) = result.lines.first().map(|line| line.score).filter(|score| score.abs() < MATE_BOUND) {
+            let candidates = result.lines.iter().take_while(|line| line.score >= best - self.variety).count();
+            let chosen = (random(self.seed ^ game.hash) % candidates as u64) as usize;
+            result.lines[..=chosen].rotate_right(1);
+            result.best_move = Some(result.lines[0].moves[0]);
+            result.score = result.lines[0].score;
+        }
+        result.lines.truncate(self.multi_pv.max(1));
+    }
+
+    fn report(&mut self, depth: u32, multi_pv: usize, line: &PvLine) {
+        let Some(on_info) = self.on_info.as_mut() else {
+            return;
+        };
+        let time = self.started.elapsed();
+        let nodes = self.control.nodes.load(Ordering::Relaxed) + (self.nodes & 1023);
+        on_info(&SearchInfo {
+            depth,
+            seldepth: self.seldepth as u32,
+            nodes,
+            nps: (nodes as f64 / time.as_secs_f64().max(1e-3)) as u64,
+            time,
+            hashfull: self.tt.hashfull(),
+            multi_pv,
+            score: line.score,
+            pv: line.moves.clone(),
+        });
+    }
+
+    // Counts a node, looking at the limits every so often (every node would
+    // cost more than it saves). True if the search has to stop.
+    fn count_node(&mut self, ply: usize) -> bool {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if self.nodes & 1023 == 0 {
+            self.control.nodes.fetch_add(1024, Ordering::Relaxed);
+            return self.past_limits();
+        }
+        false
+    }
+
+    // One iteration over the root moves, returning the best. If the search
+    // is stopped partway, that is the best of the moves searched in full,
+    // or None if there weren't any.
+    fn search_root(&mut self, game: &mut Game, moves: &[Move], depth: u32) -> Option<(Move, i32)> {
+        self.root_depth = depth;
+        let mut best: Option<(Move, i32)> = None;
+        let mut alpha = -INFINITY;
+        for &mv in moves {
+            let undo = game.make_move(mv);
+            self.path.push(game.hash);
+            let score = if best.is_none() {
+                -self.alpha_beta(game, depth - 1, -INFINITY, -alpha, 1)
+            } else {
+                let score = -self.alpha_beta(game, depth - 1, -alpha - 1, -alpha, 1);
+                if score > alpha {
+                    -self.alpha_beta(game, depth - 1, -INFINITY, -alpha, 1)
+                } else {
+                    score
+                }
+            };
+            self.path.pop();
+            game.unmake_move(undo);
+            if self.stopped() {
+                break;
+            }
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((mv, score));
+                alpha = alpha.max(score);
+            }
+        }
+        best
+    }
+
+    // `first` and the moves the transposition table holds as best after
+    // it, up to `depth` moves in all.
+    fn principal_variation(&self, game: &mut Game, first: Move, depth: u32) -> Vec<Move> {
+        let mut pv = vec![first];
+        let mut undos = vec![game.make_move(first)];
+        while pv.len() < depth as usize {
+            // The entry may belong to another position sharing the slot, so
+            // its move is only trusted if it is legal here.
+            let Some(mv) = self.tt.probe(game.hash).and_then(|entry| entry.best_move.unpack(game)) else {
+                break;
+            };
+            if !game.legal_moves().contains(&mv) {
+                break;
+            }
+            pv.push(mv);
+            undos.push(game.make_move(mv));
+        }
+        for undo in undos.into_iter().rev() {
+            game.unmake_move(undo);
+        }
+        pv
+    }
+
+    // Stops the search on every thread if it has used up its time or
+    // nodes. Neither counts while pondering, and an infinite search only
+    // stops when told to.
+    fn past_limits(&self) -> bool {
+        if self.limits_active && !self.limits.infinite && !self.control.pondering.load(Ordering::Relaxed) {
+            let time_limit = self.limits.move_time.into_iter().chain(self.budget.map(|budget| budget.hard)).min();
+            let out_of_time = time_limit.is_some_and(|limit| self.control.elapsed() >= limit);
+            let out_of_nodes = self.limits.nodes.is_some_and(|limit| self.control.nodes.load(Ordering::Relaxed) >= limit);
+            if out_of_time || out_of_nodes {
+                self.control.stop.store(true, Ordering::Relaxed);
+            }
+        }
+        self.stopped()
+    }
+
+    fn stopped(&self) -> bool {
+        self.control.stop.load(Ordering::Relaxed)
+    }
+
+    fn alpha_beta(&mut self, game: &mut Game, depth: u32, mut alpha: i32, mut beta: i32, ply: usize) -> i32 {
+        if self.is_draw(game) {
+            return self.draw_score(game.turn);
+        }
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(game, alpha, beta, ply);
+        }
+        if self.count_node(ply) {
+            return 0;
+        }
+
+        // Mate distance pruning: no line from here can do better than
+        // mating on the next move, or worse than being mated right now, so
+        // a window outside those bounds is already decided.
+        alpha = alpha.max(-MATE + ply as i32);
+        beta = beta.min(MATE - ply as i32 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+
+        // An earlier search of this position to at least this depth may
+        // already settle it, unless this is a search without its best move.
+        let excluded = self.excluded[ply];
+        let entry = self.tt.probe(game.hash);
+        if let Some(entry) = entry.filter(|entry| excluded.is_none() && entry.depth as u32 >= depth) {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+        let tt_move = entry.map_or(PackedMove::NONE, |entry| entry.best_move);
+
+        // The win/draw/loss tables assume the fifty-move count was just
+        // reset, so only a capture or pawn move leads to a position they
+        // settle.
+        if ply > 0 && excluded.is_none() && game.halfmove_clock == 0 {
+            if let Some(wdl) = self.tablebase_for(game).and_then(|tablebase| tablebase.probe_wdl(game)) {
+                let score = self.tablebase_score(wdl, game.turn, ply);
+                self.tt.store(game.hash, PackedMove::NONE, score_to_tt(score, ply), MAX_DEPTH as u8, Bound::Exact);
+                return score;
+            }
+        }
+
+        // The table move is singular if a reduced search without it can't
+        // come near its score. Only an entry that is a lower bound (or
+        // exact) from nearly this depth says enough about the move.
+        let singular = entry.is_some_and(|entry| {
+            depth >= self.params.singular_min_depth
+                && excluded.is_none()
+                && !tt_move.is_none()
+                && entry.bound != Bound::Upper
+                && entry.depth as u32 + 3 >= depth
+                && entry.score.abs() < MATE_BOUND
+                && {
+                    let singular_beta = score_from_tt(entry.score, ply) - self.params.singular_margin * depth as i32;
+                    self.excluded[ply] = tt_move;
+                    let score = self.alpha_beta(game, (depth - 1) / 2, singular_beta - 1, singular_beta, ply);
+                    self.excluded[ply] = PackedMove::NONE;
+                    score < singular_beta
+                }
+        });
+        if self.stopped() {
+            return 0;
+        }
+
+        let original_alpha = alpha;
+        let mover = game.turn;
+        let in_check = game.is_in_check(mover);
+
+        // Only outside the principal variation, where a null window says
+        // an exact score isn't needed.
+        let null_window = beta - alpha == 1;
+        let static_eval = eval::evaluate_with(game, &self.evaluation, &mut self.pawns);
+        if null_window
+            && !in_check
+            && depth <= self.params.reverse_futility_max_depth
+            && beta.abs() < MATE_BOUND
+            && static_eval - self.params.reverse_futility_margin * depth as i32 >= beta
+        {
+            return static_eval;
+        }
+        let futile = !in_check
+            && depth <= self.params.futility_max_depth
+            && alpha.abs() < MATE_BOUND
+            && static_eval + self.params.futility_margin * depth as i32 <= alpha;
+
+        let mut moves = if in_check { game.evasion_moves() } else { game.pseudo_legal_moves() };
+        self.order_moves(game, &mut moves, tt_move, ply);
+        let mut best = None;
+        let mut best_move = PackedMove::NONE;
+        let mut searched = 0;
+        for mv in moves {
+            if mv.pack() == excluded {
+                continue;
+            }
+            let undo = game.make_move(mv);
+            if game.is_in_check(mover) {
+                game.unmake_move(undo);
+                continue;
+            }
+            searched += 1;
+
+            let quiet = !mv.is_capture() && mv.promotion.is_none();
+            let gives_check = game.is_in_check(game.turn);
+            // The first move is always searched, so a node where every move
+            // is futile still has a real score.
+            if futile && quiet && searched > 1 && !gives_check {
+                game.unmake_move(undo);
+                continue;
+            }
+
+            let extends = gives_check || (singular && mv.pack() == tt_move);
+            let extension = if extends && ply < 2 * self.root_depth as usize { 1 } else { 0 };
+            let new_depth = depth - 1 + extension;
+            let reduction = if quiet && !in_check && !gives_check {
+                self.params.reduction(depth, searched - 1)
+            } else {
+                0
+            };
+            self.path.push(game.hash);
+            let score = if searched == 1 {
+                -self.alpha_beta(game, new_depth, -beta, -alpha, ply + 1)
+            } else {
+                let mut score = -self.alpha_beta(game, new_depth - reduction, -alpha - 1, -alpha, ply + 1);
+                if reduction > 0 && score > alpha {
+                    score = -self.alpha_beta(game, new_depth, -alpha - 1, -alpha, ply + 1);
+                }
+                if score > alpha && score < beta {
+                    score = -self.alpha_beta(game, new_depth, -beta, -alpha, ply + 1);
+                }
+                score
+            };
+            self.path.pop();
+            game.unmake_move(undo);
+            if self.stopped() {
+                return 0;
+            }
+
+            if best.is_none_or(|best| score > best) {
+                best = Some(score);
+                best_move = mv.pack();
+            }
+            if score >= beta {
+                if !mv.is_capture() && mv.promotion.is_none() {
+                    self.add_killer(mv.pack(), ply);
+                    self.add_history(game, mv, depth);
+                }
+                break;
+            }
+            alpha = alpha.max(score);
+        }
+
+        let Some(best) = best else {
+            // Without the excluded move there may be nothing left, which
+            // says nothing about the position itself.
+            if !excluded.is_none() {
+                return alpha;
+            }
+            return self.no_moves(game, ply);
+        };
+        if !excluded.is_none() {
+            return best;
+        }
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(game.hash, best_move, score_to_tt(best, ply), depth as u8, bound);
+        best
+    }
+
+    // A draw's score for `turn`, after contempt.
+    fn draw_score(&self, turn: Color) -> i32 {
+        if turn == self.root_turn {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    // Drawn by repetition or the fifty-move rule, unless checkmate came
+    // first. Positions from before the last capture or pawn move can't
+    // repeat, nor can those with the other side to move.
+    fn is_draw(&self, game: &Game) -> bool {
+        if game.halfmove_clock >= 100 {
+            return !game.is_checkmate();
+        }
+        let Some((_, earlier)) = self.path.split_last() else {
+            return false;
+        };
+        earlier.iter().rev().take(game.halfmove_clock as usize).skip(1).step_by(2).any(|&key| key == game.hash)
+    }
+
+    fn order_moves(&self, game: &Game, moves: &mut MoveList, tt_move: PackedMove, ply: usize) {
+        let killers = self.killers[ply];
+        let history = &self.history[game.turn.index()];
+        moves.sort_by_key(|mv| {
+            let packed = mv.pack();
+            let score = if packed == tt_move {
+                1_000_000
+            } else if mv.is_capture() || mv.promotion.is_some() {
+                if game.see(*mv) >= 0 {
+                    100_000 + mvv_lva(mv)
+                } else {
+                    -100_000 + mvv_lva(mv)
+                }
+            } else if packed == killers[0] {
+                90_000
+            } else if packed == killers[1] {
+                80_000
+            } else {
+                history[mv.from.0 as usize][mv.to.0 as usize]
+            };
+            std::cmp::Reverse(score)
+        });
+    }
+
+    fn add_history(&mut self, game: &Game, mv: Move, depth: u32) {
+        let entry = &mut self.history[game.turn.index()][mv.from.0 as usize][mv.to.0 as usize];
+        *entry += (depth * depth) as i32;
+        if *entry > HISTORY_LIMIT {
+            self.age_history();
+        }
+    }
+
+    fn age_history(&mut self) {
+        self.history.iter_mut().flatten().flatten().for_each(|entry| *entry /= 2);
+    }
+
+    fn add_killer(&mut self, mv: PackedMove, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != mv {
+            killers[1] = killers[0];
+            killers[0] = mv;
+        }
+    }
+
+    // Searches captures and promotions only. The side to move may also
+    // "stand pat" on the static evaluation, since it needn't capture;
+    // in check it must answer the check instead, by any move.
+    fn quiescence(&mut self, game: &mut Game, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        if self.count_node(ply) {
+            return 0;
+        }
+        // Evasions can go on for as long as there are checks to answer.
+        if ply >= MAX_PLY {
+            return eval::evaluate_with(game, &self.evaluation, &mut self.pawns);
+        }
+
+        let mover = game.turn;
+        let in_check = game.is_in_check(mover);
+        let stand_pat = if in_check { None } else { Some(eval::evaluate_with(game, &self.evaluation, &mut self.pawns)) };
+        if let Some(stand_pat) = stand_pat {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut moves = if in_check { game.evasion_moves() } else { game.pseudo_legal_moves() };
+        if let Some(stand_pat) = stand_pat {
+            // Delta pruning: a capture that can't lift the score to alpha
+            // even with a margin to spare isn't worth searching, nor is one
+            // that loses material in the exchange that follows.
+            moves.retain(|mv| {
+                let gain = mv.captured.map_or(0, eval::value) + mv.promotion.map_or(0, |piece| eval::value(piece) - eval::value(Piece::Pawn));
+                (mv.is_capture() || mv.promotion.is_some()) && stand_pat + gain + DELTA_MARGIN > alpha && game.see(*mv) >= 0
+            });
+        }
+        order_captures(&mut moves);
+
+        let mut best = stand_pat;
+        for mv in moves {
+            let undo = game.make_move(mv);
+            if game.is_in_check(mover) {
+                game.unmake_move(undo);
+                continue;
+            }
+            let score = -self.quiescence(game, -beta, -alpha, ply + 1);
+            game.unmake_move(undo);
+            if self.stopped() {
+                return 0;
+            }
+
+            let best_score = best.map_or(score, |best: i32| best.max(score));
+            best = Some(best_score);
+            if best_score >= beta {
+                break;
+            }
+            alpha = alpha.max(best_score);
+        }
+        best.unwrap_or_else(|| self.no_moves(game, ply))
+    }
+
+    // The score of a position without legal moves `ply` plies from the
+    // root: mated if in check, otherwise stalemate.
+    fn no_moves(&self, game: &Game, ply: usize) -> i32 {
+        if game.is_in_check(game.turn) {
+            -MATE + ply as i32
+        } else {
+            0
+        }
+    }
+}
+
+// Puts the moves most likely to cause a cutoff first: captures of the most
+// valuable victim by the least valuable attacker (MVV-LVA), with
+// promotions counted as winning the promoted piece. Quiet moves keep their
+// generation order after them.
+fn order_captures(moves: &mut MoveList) {
+    moves.sort_by_key(|mv| std::cmp::Reverse(mvv_lva(mv)));
+}
+
+// splitmix64, mixing a seed into a well-spread number.
+fn random(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn mvv_lva(mv: &Move) -> i32 {
+    // A king captures last, as anything it takes may turn out defended.
+    let attacker = if mv.piece == Piece::King { 1000 } else { eval::value(mv.piece) };
+    let victim = mv.captured.map_or(0, |victim| eval::value(victim) * 10 - attacker / 10);
+    victim + mv.promotion.map_or(0, |piece| eval::value(piece) * 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashfull_counts_this_search() {
+        let mut searcher = Searcher::with_hash_size(1);
+        assert_eq!(searcher.hashfull(), 0);
+        searcher.search(&Game::new(), &SearchLimits { depth: Some(5), ..SearchLimits::default() });
+        assert!(searcher.hashfull() > 0);
+        searcher.new_game();
+        assert_eq!(searcher.hashfull(), 0);
+    }
+}
@@ -0,0 +1,173 @@
+// Diagrams of a position as SVG, for web pages and analysis reports: the
+// board in two colours, the pieces drawn with the Unicode chess glyphs,
+// and optionally the squares of the last move tinted and arrows from
+// square to square, such as for an engine's best moves.
+
+use crate::{ChessPiece, Color, File, Game, Move, Rank, Square};
+
+// Text attributes that centre a label or glyph on its point.
+const CENTRED: &str = r#"text-anchor="middle" dominant-baseline="central""#;
+
+// How to draw a diagram. The default is a 360-pixel board, White at the
+// bottom, with coordinates and in the usual brown.
+#[derive(Debug, Clone)]
+pub struct Diagram {
+    // Width and height of a square, in pixels.
+    pub square_size: u32,
+    // Colours in any form SVG takes, such as "#f0d9b5" or "tan".
+    pub light: String,
+    pub dark: String,
+    // Black at the bottom.
+    pub flipped: bool,
+    // File letters and rank numbers in a margin around the board.
+    pub coordinates: bool,
+    // The move to tint the squares of, usually the last one played.
+    pub last_move: Option<Move>,
+    pub highlight: String,
+    pub arrows: Vec<Arrow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arrow {
+    pub from: Square,
+    pub to: Square,
+    pub color: String,
+}
+
+impl Arrow {
+    pub fn new(from: Square, to: Square, color: &str) -> Arrow {
+        Arrow { from, to, color: color.to_string() }
+    }
+}
+
+impl Default for Diagram {
+    fn default() -> Diagram {
+        Diagram {
+            square_size: 45,
+            light: "#f0d9b5".to_string(),
+            dark: "#b58863".to_string(),
+            flipped: false,
+            coordinates: true,
+            last_move: None,
+            highlight: "#cdd26a".to_string(),
+            arrows: vec![],
+        }
+    }
+}
+
+impl Diagram {
+    // The position in `game` as a standalone SVG document.
+    pub fn render(&self, game: &Game) -> String {
+        let size = self.square_size as f64;
+        let margin = if self.coordinates { size / 2.0 } else { 0.0 };
+        let width = 8.0 * size + 2.0 * margin;
+        let mut lines = vec![];
+        lines.push(format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#, width));
+        if self.coordinates {
+            lines.push(format!(r##"<rect width="{0}" height="{0}" fill="#404040"/>"##, width));
+        }
+
+        let moved = self.last_move.map(|mv| [mv.from, mv.to]);
+        for square in Square::all() {
+            let (x, y) = self.corner(square, margin);
+            let color = if moved.is_some_and(|squares| squares.contains(&square)) {
+                &self.highlight
+            } else if (square.file().0 + square.rank().0) % 2 == 0 {
+                &self.dark
+            } else {
+                &self.light
+            };
+            lines.push(format!(r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, x, y, size, size, color));
+        }
+
+        if self.coordinates {
+            let font = size * 0.3;
+            for i in 0..8u8 {
+                let (file, _) = self.corner(Square::new(File(i), Rank(0)), margin);
+                let (_, rank) = self.corner(Square::new(File(0), Rank(i)), margin);
+                for (x, y, label) in [
+                    (file + size / 2.0, margin / 2.0, File(i).to_string()),
+                    (file + size / 2.0, width - margin / 2.0, File(i).to_string()),
+                    (margin / 2.0, rank + size / 2.0, Rank(i).to_string()),
+                    (width - margin / 2.0, rank + size / 2.0, Rank(i).to_string()),
+                ] {
+                    let style = format!(r##"font-size="{:.1}" font-family="sans-serif" fill="#e0e0e0" {}"##, font, CENTRED);
+                    lines.push(format!(r#"<text x="{:.1}" y="{:.1}" {}>{}</text>"#, x, y, style, label));
+                }
+            }
+        }
+
+        // The solid glyphs for both sides, filled white or black, read
+        // better than the outlined ones for White.
+        for square in Square::all() {
+            let Some(piece) = game.piece_at(square) else {
+                continue;
+            };
+            let (x, y) = self.corner(square, margin);
+            let glyph = ChessPiece { piece: piece.piece, color: Color::Black }.to_symbol();
+            let (fill, stroke) = match piece.color {
+                Color::White => ("#ffffff", "#000000"),
+                Color::Black => ("#000000", "#000000"),
+            };
+            let style = format!(
+                r#"font-size="{:.1}" fill="{}" stroke="{}" stroke-width="{:.1}" {}"#,
+                size * 0.8,
+                fill,
+                stroke,
+                size / 45.0,
+                CENTRED
+            );
+            lines.push(format!(r#"<text x="{:.1}" y="{:.1}" {}>{}</text>"#, x + size / 2.0, y + size / 2.0, style, glyph));
+        }
+
+        for arrow in &self.arrows {
+            lines.push(self.arrow(arrow, margin));
+        }
+        lines.push("</svg>\n".to_string());
+        lines.join("\n")
+    }
+
+    // The top left corner of `square`.
+    fn corner(&self, square: Square, margin: f64) -> (f64, f64) {
+        let size = self.square_size as f64;
+        let (column, row) = if self.flipped { (7 - square.file().0, square.rank().0) } else { (square.file().0, 7 - square.rank().0) };
+        (margin + column as f64 * size, margin + row as f64 * size)
+    }
+
+    // A shaft from the middle of one square to a head on the middle of the
+    // other, half transparent so the pieces show through.
+    fn arrow(&self, arrow: &Arrow, margin: f64) -> String {
+        let size = self.square_size as f64;
+        let centre = |square| {
+            let (x, y) = self.corner(square, margin);
+            (x + size / 2.0, y + size / 2.0)
+        };
+        let (x1, y1) = centre(arrow.from);
+        let (x2, y2) = centre(arrow.to);
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt().max(1.0);
+        // Along the arrow, and across it.
+        let (ux, uy) = ((x2 - x1) / length, (y2 - y1) / length);
+        let (nx, ny) = (-uy, ux);
+        let head = size * 0.4;
+        let (bx, by) = (x2 - ux * head, y2 - uy * head);
+        format!(
+            concat!(
+                r#"<g opacity="0.7"><line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{:.1}" stroke-linecap="round"/>"#,
+                r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="{}"/></g>"#
+            ),
+            x1,
+            y1,
+            bx,
+            by,
+            arrow.color,
+            size * 0.15,
+            x2,
+            y2,
+            bx + nx * head * 0.6,
+            by + ny * head * 0.6,
+            bx - nx * head * 0.6,
+            by - ny * head * 0.6,
+            arrow.color
+        )
+    }
+}
@@ -0,0 +1,78 @@
+// Syzygy endgame tablebases, built with the `syzygy` feature through
+// `shakmaty-syzygy`, which reads the .rtbw (win/draw/loss) and .rtbz
+// (distance to zeroing) files. The search looks positions up in them as
+// described in search.rs; the files are found in the directories on a
+// path given as `--syzygy <path>` or the SyzygyPath UCI option, separated
+// by ':' (';' on Windows) as in Stockfish.
+//
+// The probing code has a board of its own, so each position crosses over
+// as FEN and each move comes back in UCI coordinates.
+
+use std::io;
+use std::path::Path;
+
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::Dtz;
+
+use crate::search::{Tablebase, Wdl};
+use crate::{Game, Move};
+
+// The fifty-move rule, in plies.
+const FIFTY_MOVES: i32 = 100;
+
+pub struct Syzygy {
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+impl Syzygy {
+    // The tables in every directory on `path`. Directories without any
+    // are fine, but not ones that can't be read.
+    pub fn open(path: &str) -> io::Result<Syzygy> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        for directory in path.split(separator).filter(|directory| !directory.trim().is_empty()) {
+            tables.add_directory(Path::new(directory.trim()))?;
+        }
+        Ok(Syzygy { tables })
+    }
+}
+
+impl Tablebase for Syzygy {
+    fn max_pieces(&self) -> u32 {
+        self.tables.max_pieces() as u32
+    }
+
+    fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        let wdl = self.tables.probe_wdl_after_zeroing(&position(game)?).ok()?;
+        Some(match wdl {
+            shakmaty_syzygy::Wdl::Loss => Wdl::Loss,
+            shakmaty_syzygy::Wdl::BlessedLoss => Wdl::BlessedLoss,
+            shakmaty_syzygy::Wdl::Draw => Wdl::Draw,
+            shakmaty_syzygy::Wdl::CursedWin => Wdl::CursedWin,
+            shakmaty_syzygy::Wdl::Win => Wdl::Win,
+        })
+    }
+
+    // The result comes from the distance to zeroing and the fifty-move
+    // count so far, since a win that takes too long is only a draw.
+    fn best_move(&self, game: &Game) -> Option<(Move, Wdl)> {
+        let position = position(game)?;
+        let (mv, _) = self.tables.best_move(&position).ok()??;
+        let Dtz(dtz) = self.tables.probe_dtz(&position).ok()?.ignore_rounding();
+        let in_time = dtz.abs() + game.halfmove_clock as i32 <= FIFTY_MOVES;
+        let wdl = match dtz {
+            0 => Wdl::Draw,
+            1.. if in_time => Wdl::Win,
+            1.. => Wdl::CursedWin,
+            _ if in_time => Wdl::Loss,
+            _ => Wdl::BlessedLoss,
+        };
+        let mv = game.parse_uci(&mv.to_uci(CastlingMode::Standard).to_string()).ok()?;
+        Some((mv, wdl))
+    }
+}
+
+fn position(game: &Game) -> Option<Chess> {
+    Fen::from_ascii(game.to_fen().as_bytes()).ok()?.into_position(CastlingMode::Standard).ok()
+}
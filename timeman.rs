@@ -0,0 +1,107 @@
+// Time management: how much of the clock to spend on one move. The search
+// gets a soft limit, past which it starts no new iteration, and a hard
+// limit at which it stops mid-iteration. The soft limit is stretched while
+// the best move keeps changing and shrunk once it has settled.
+
+use std::time::Duration;
+
+// Kept back on every move for the time it takes to get the move to the
+// clock.
+const OVERHEAD: Duration = Duration::from_millis(30);
+// Moves assumed still to come when the time control doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Below this much time the engine plays fast to stay out of trouble.
+const EMERGENCY: Duration = Duration::from_secs(1);
+
+// The clock of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub remaining: Duration,
+    pub increment: Duration,
+    // Moves until the next time control, if there is one.
+    pub moves_to_go: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl TimeBudget {
+    // The soft limit scaled for how settled the search is: `stable` is the
+    // number of iterations in a row that have returned the same best move.
+    pub fn soft_limit(&self, stable: u32) -> Duration {
+        let scale = match stable {
+            0 => 1.5,
+            1 => 1.2,
+            2 => 1.0,
+            3 => 0.8,
+            _ => 0.5,
+        };
+        self.soft.mul_f64(scale).min(self.hard)
+    }
+}
+
+pub fn allocate(clock: &TimeControl) -> TimeBudget {
+    let available = clock.remaining.saturating_sub(OVERHEAD);
+    let moves_to_go = clock.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+
+    if available < EMERGENCY {
+        // Spend little more than the increment, leaving the rest banked.
+        let soft = available / 20 + clock.increment / 2;
+        return TimeBudget { soft: soft.min(available / 4), hard: (soft * 2).min(available / 2) };
+    }
+
+    // Up to four times the share of the time left, but no more than a
+    // third of it, unless the share is more still, as it is with a move or
+    // two to go; a quarter is always kept back. The soft limit stays well
+    // below the hard one, for the search to stretch it.
+    let soft = available / moves_to_go + clock.increment * 3 / 4;
+    let hard = (soft * 4).min(available / 3).max(soft.min(available * 3 / 4));
+    TimeBudget { soft: soft.min(hard * 2 / 3), hard }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> TimeControl {
+        TimeControl { remaining: Duration::from_millis(remaining_ms), increment: Duration::from_millis(increment_ms), moves_to_go }
+    }
+
+    #[test]
+    fn last_move_before_the_control_keeps_time_back() {
+        for increment in [0, 2_000, 60_000] {
+            let clock = clock(60_000, increment, Some(1));
+            let available = clock.remaining - OVERHEAD;
+            let budget = allocate(&clock);
+            assert_eq!(budget.hard, available * 3 / 4);
+            assert!(budget.soft < budget.hard && budget.soft_limit(0) <= budget.hard, "{:?}", budget);
+        }
+    }
+
+    #[test]
+    fn emergency_plays_fast() {
+        for (remaining, increment) in [(500, 0), (500, 10_000), (30, 0), (0, 0)] {
+            let clock = clock(remaining, increment, None);
+            let available = clock.remaining.saturating_sub(OVERHEAD);
+            let budget = allocate(&clock);
+            assert!(budget.soft <= available / 4 && budget.hard <= available / 2, "{:?} {:?}", clock, budget);
+        }
+    }
+
+    #[test]
+    fn never_spends_the_whole_clock() {
+        for remaining in [1_000, 5_000, 60_000, 600_000] {
+            for increment in [0, 1_000, 30_000] {
+                for moves_to_go in [None, Some(1), Some(2), Some(10), Some(40)] {
+                    let clock = clock(remaining, increment, moves_to_go);
+                    let budget = allocate(&clock);
+                    assert!(budget.hard <= (clock.remaining - OVERHEAD) * 3 / 4, "{:?} {:?}", clock, budget);
+                    assert!(budget.soft < budget.hard, "{:?} {:?}", clock, budget);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// The transposition table: a fixed-size hash table of search results keyed
+// by the position's Zobrist hash, so that a position reached again by a
+// different move order (or in the next iteration) needn't be searched again.
+//
+// Search threads share one table without locking. Each slot is two atomic
+// words, the entry packed into one and XORed with the key in the other;
+// an entry half-overwritten by another thread then fails the key check
+// instead of being read back torn.
+
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::packed::PackedMove;
+
+// How a stored score relates to the position's true score. A search that
+// failed high only proved a lower bound, one that failed low an upper
+// bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub best_move: PackedMove,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    // The search that stored the entry, so stale entries are replaced first.
+    generation: u8,
+}
+
+impl Entry {
+    // Move in bits 0-15, score 16-31, depth 32-39, bound 40-41 (0 for an
+    // empty slot) and generation 48-55.
+    fn pack(self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.best_move.bits() as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth as u64) << 32
+            | bound << 40
+            | (self.generation as u64) << 48
+    }
+
+    fn unpack(data: u64) -> Option<Entry> {
+        let bound = match data >> 40 & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(Entry {
+            best_move: PackedMove::from_bits(data as u16),
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32) as u8,
+            bound,
+            generation: (data >> 48) as u8,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    // The full hash XOR `data`, since many positions share a slot.
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_SIZE_MB: usize = 16;
+
+    // A table taking up about `size_mb` megabytes, with at least one slot.
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let slots = (size_mb * 1024 * 1024 / mem::size_of::<Slot>()).max(1);
+        TranspositionTable { slots: (0..slots).map(|_| Slot::default()).collect(), generation: AtomicU8::new(0) }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    // Called at the start of each search, so the entries it stores can be
+    // told apart from those of earlier ones.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    fn load(&self, key: u64) -> Option<(u64, Entry)> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        Entry::unpack(data).map(|entry| (check ^ data, entry))
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.load(key).filter(|&(stored, _)| stored == key).map(|(_, entry)| entry)
+    }
+
+    // Keeps the new result unless the slot holds a deeper search of another
+    // position from this same search.
+    pub fn store(&self, key: u64, best_move: PackedMove, score: i32, depth: u8, bound: Bound) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let old = self.load(key);
+        if let Some((stored, old)) = old {
+            if stored != key && old.generation == generation && old.depth > depth {
+                return;
+            }
+        }
+        // A search that found no move keeps the one already known.
+        let best_move = match old {
+            Some((stored, old)) if stored == key && best_move.is_none() => old.best_move,
+            _ => best_move,
+        };
+        let data = Entry { best_move, score, depth, bound, generation }.pack();
+        let slot = self.slot(key);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // How full the table is in permille, as UCI's `hashfull` reports it:
+    // the share of the first thousand slots used by the current search.
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| Entry::unpack(slot.data.load(Ordering::Relaxed)).is_some_and(|entry| entry.generation == generation))
+            .count();
+        (used * 1000 / sample) as u32
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> TranspositionTable {
+        TranspositionTable::new(TranspositionTable::DEFAULT_SIZE_MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_empties_the_table() {
+        let mut table = TranspositionTable::new(1);
+        table.new_search();
+        table.store(42, PackedMove::NONE, 10, 3, Bound::Exact);
+        assert!(table.probe(42).is_some());
+        table.resize(2);
+        assert_eq!(table.slots.len(), 2 * 1024 * 1024 / mem::size_of::<Slot>());
+        assert!(table.probe(42).is_none());
+    }
+}
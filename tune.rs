@@ -0,0 +1,224 @@
+// Texel tuning: fitting the evaluation's weights to the results of real
+// games.
+//
+// The hand-crafted evaluation is a sum of weights, each counted some
+// number of times for either side and blended by the game phase, so a
+// position's score is linear in the weights. The counts are recorded once
+// per position, after which its score under any weights is a dot product.
+// A sigmoid, 1 / (1 + 10^(-K * score / 400)), turns the score into White's
+// expected result; K is fitted to the starting weights first, and then
+// gradient descent (with Adam's per-weight step sizes) lowers the mean
+// squared error between the expected results and the real ones.
+//
+// Positions come one per line: a FEN followed by the game's result, as
+// `1-0`, `0-1` or `1/2-1/2` (optionally quoted, as in an EPD `c9` opcode)
+// or as White's score in brackets, `[1.0]`, `[0.5]` or `[0.0]`. Quiet
+// positions work best, since the evaluation doesn't see exchanges coming.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::thread;
+
+use crate::eval::{self, Parameters, Score, Terms, Weight, MAX_PHASE};
+use crate::{Color, Game};
+
+// Adam's decay rates for the gradient's mean and square.
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tuning {
+    pub epochs: usize,
+    // Roughly how far in centipawns a weight moves per epoch at first.
+    pub learning_rate: f64,
+    pub threads: usize,
+}
+
+impl Default for Tuning {
+    fn default() -> Tuning {
+        Tuning { epochs: 1000, learning_rate: 1.0, threads: 1 }
+    }
+}
+
+// A position ready for tuning: the counts of each weight for White less
+// those for Black, by index into Weight::all.
+pub struct Entry {
+    counts: Vec<(u32, f32)>,
+    // From 1 for the middlegame to 0 for the endgame.
+    phase: f32,
+    // White's result: 1, 0.5 or 0.
+    result: f32,
+}
+
+// Reads the positions at `path`, counting their weights with `params`'
+// shape.
+pub fn load(path: impl AsRef<Path>, params: &Parameters) -> io::Result<Vec<Entry>> {
+    let weights = Weight::all();
+    let index: HashMap<Weight, usize> = weights.iter().enumerate().map(|(i, &weight)| (weight, i)).collect();
+    let mut entries = vec![];
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((game, result)) = parse_line(&line) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: not a FEN and result: {}", number + 1, line)));
+        };
+        let mut counts = Counts { params, index: &index, counts: vec![0.0; weights.len()] };
+        eval::collect(&game, &mut counts);
+        entries.push(Entry {
+            counts: counts.counts.iter().enumerate().filter(|(_, &count)| count != 0.0).map(|(i, &count)| (i as u32, count as f32)).collect(),
+            phase: eval::phase(&game, params) as f32 / MAX_PHASE as f32,
+            result,
+        });
+    }
+    Ok(entries)
+}
+
+// Fits the weights, starting from `start`, to `entries`' results. Calls
+// `report` with the epoch and the error now and then.
+pub fn tune(entries: &[Entry], start: &Parameters, tuning: &Tuning, mut report: impl FnMut(usize, f64)) -> Parameters {
+    let weights = Weight::all();
+    let mut values: Vec<[f64; 2]> = weights.iter().map(|&weight| [start[weight].mg as f64, start[weight].eg as f64]).collect();
+    let k = fit_k(entries, &values, tuning.threads);
+    report(0, error(entries, &values, k, tuning.threads));
+
+    let mut mean = vec![[0.0; 2]; values.len()];
+    let mut square = vec![[0.0; 2]; values.len()];
+    for epoch in 1..=tuning.epochs {
+        let gradient = gradient(entries, &values, k, tuning.threads);
+        for (i, gradient) in gradient.iter().enumerate() {
+            for phase in 0..2 {
+                mean[i][phase] = BETA1 * mean[i][phase] + (1.0 - BETA1) * gradient[phase];
+                square[i][phase] = BETA2 * square[i][phase] + (1.0 - BETA2) * gradient[phase] * gradient[phase];
+                let mean = mean[i][phase] / (1.0 - BETA1.powi(epoch as i32));
+                let square = square[i][phase] / (1.0 - BETA2.powi(epoch as i32));
+                values[i][phase] -= tuning.learning_rate * mean / (square.sqrt() + 1e-8);
+            }
+        }
+        if epoch % 50 == 0 || epoch == tuning.epochs {
+            report(epoch, error(entries, &values, k, tuning.threads));
+        }
+    }
+
+    let mut params = start.clone();
+    for (&weight, value) in weights.iter().zip(&values) {
+        params[weight] = Score::new(value[0].round() as i32, value[1].round() as i32);
+    }
+    params
+}
+
+// Records each weight's count for White less Black's.
+struct Counts<'a> {
+    params: &'a Parameters,
+    index: &'a HashMap<Weight, usize>,
+    counts: Vec<f64>,
+}
+
+impl Terms for Counts<'_> {
+    fn params(&self) -> &Parameters {
+        self.params
+    }
+
+    fn add(&mut self, color: Color, weight: Weight, count: i32) {
+        self.add_percent(color, weight, count, 100);
+    }
+
+    fn add_percent(&mut self, color: Color, weight: Weight, count: i32, percent: i32) {
+        let count = count as f64 * percent as f64 / 100.0;
+        self.counts[self.index[&weight]] += if color == Color::White { count } else { -count };
+    }
+}
+
+// White's score for `entry` with the weights `values`.
+fn score(entry: &Entry, values: &[[f64; 2]]) -> f64 {
+    let phase = entry.phase as f64;
+    entry.counts.iter().map(|&(i, count)| count as f64 * (values[i as usize][0] * phase + values[i as usize][1] * (1.0 - phase))).sum()
+}
+
+fn sigmoid(k: f64, score: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+// Runs `work` over `entries` split between `threads` threads.
+fn split<T: Send>(entries: &[Entry], threads: usize, work: impl Fn(&[Entry]) -> T + Sync) -> Vec<T> {
+    let chunk = entries.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let work = &work;
+        let handles: Vec<_> = entries.chunks(chunk).map(|chunk| scope.spawn(move || work(chunk))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+// The mean squared error of the expected results.
+fn error(entries: &[Entry], values: &[[f64; 2]], k: f64, threads: usize) -> f64 {
+    let sums = split(entries, threads, |chunk| {
+        chunk.iter().map(|entry| (entry.result as f64 - sigmoid(k, score(entry, values))).powi(2)).sum::<f64>()
+    });
+    sums.iter().sum::<f64>() / entries.len().max(1) as f64
+}
+
+// The error's gradient with respect to every weight's middlegame and
+// endgame values.
+fn gradient(entries: &[Entry], values: &[[f64; 2]], k: f64, threads: usize) -> Vec<[f64; 2]> {
+    let parts = split(entries, threads, |chunk| {
+        let mut gradient = vec![[0.0; 2]; values.len()];
+        for entry in chunk {
+            let expected = sigmoid(k, score(entry, values));
+            // The derivative of the squared error with respect to the score.
+            let slope = -2.0 * (entry.result as f64 - expected) * expected * (1.0 - expected) * k * 10f64.ln() / 400.0;
+            let phase = entry.phase as f64;
+            for &(i, count) in &entry.counts {
+                gradient[i as usize][0] += slope * count as f64 * phase;
+                gradient[i as usize][1] += slope * count as f64 * (1.0 - phase);
+            }
+        }
+        gradient
+    });
+    let mut gradient = vec![[0.0; 2]; values.len()];
+    for part in parts {
+        for (sum, value) in gradient.iter_mut().zip(part) {
+            sum[0] += value[0] / entries.len() as f64;
+            sum[1] += value[1] / entries.len() as f64;
+        }
+    }
+    gradient
+}
+
+// The K that makes the starting weights' expected results fit best, by
+// golden section search: the error has a single minimum in K.
+fn fit_k(entries: &[Entry], values: &[[f64; 2]], threads: usize) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (0.0, 10.0);
+    while high - low > 1e-4 {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if error(entries, values, left, threads) < error(entries, values, right, threads) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// A FEN and the result after it.
+fn parse_line(line: &str) -> Option<(Game, f32)> {
+    let result = if line.contains("1/2-1/2") || line.contains("[0.5]") {
+        0.5
+    } else if line.contains("1-0") || line.contains("[1.0]") {
+        1.0
+    } else if line.contains("0-1") || line.contains("[0.0]") {
+        0.0
+    } else {
+        return None;
+    };
+    // The FEN is the first four fields and the move counters, if they
+    // are there before the result.
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let counters = fields.iter().skip(4).take(2).take_while(|field| field.parse::<u32>().is_ok()).count();
+    let fen = fields[..fields.len().min(4 + counters)].join(" ");
+    Some((Game::from_fen(&fen).ok()?, result))
+}
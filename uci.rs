@@ -0,0 +1,350 @@
+// The Universal Chess Interface (UCI), the text protocol that chess GUIs
+// such as Arena and Cute Chess drive engines with. The GUI writes commands
+// to the engine's standard input, one to a line, and reads its replies
+// from standard output:
+//
+//     uci                  -> id name, id author, option ..., uciok
+//     isready              -> readyok, at once even while searching
+//     setoption name <name> value <value>
+//                             see options.rs for the options, and
+//                             SyzygyPath with the `syzygy` feature
+//     ucinewgame              forget what was learned in the last game
+//     position startpos | fen <fen> [moves <move> ...]
+//     go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>]
+//        [depth <plies>] [nodes <n>] [mate <moves>] [movetime <ms>]
+//        [infinite] [ponder]
+//     stop, ponderhit, quit
+//
+// Moves are in coordinates, "e2e4" or "e7e8q". A search reports each
+// finished iteration as an `info` line and ends with `bestmove`, followed
+// by the reply it expects for the GUI to let it ponder on. It runs on a
+// thread of its own so that commands, `stop` above all, are still read.
+//
+// With a log file every line read and written is also recorded there,
+// after the time of day (UTC) it passed and "<<" for a line from the GUI
+// or ">>" for one to it, for finding out what a GUI and the engine
+// disagree about.
+
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::eval::Parameters;
+use crate::options::{EngineOptions, OPTIONS};
+use crate::search::{SearchHandle, SearchInfo, SearchLimits, Searcher, Tablebase};
+use crate::timeman::TimeControl;
+use crate::{Color, Game};
+
+const NAME: &str = "ChessInRust";
+const AUTHOR: &str = "the ChessInRust authors";
+// Where to find Syzygy tablebases; see syzygy.rs.
+#[cfg(feature = "syzygy")]
+const SYZYGY_PATH: &str = "SyzygyPath";
+
+// Where the traffic is logged, if anywhere; set once by `run`.
+static LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+// `println!` for the GUI, logging the line too.
+macro_rules! send {
+    ($($arg:tt)*) => {
+        send_line(&format!($($arg)*))
+    };
+}
+
+// Answers commands from standard input until `quit` or the end of input,
+// starting with `options` and `tablebase` until the GUI sets others, and
+// logging the traffic to `log` if given.
+pub fn run(evaluation: Parameters, options: EngineOptions, tablebase: Option<Arc<dyn Tablebase>>, log: Option<File>) {
+    if let Some(log) = log {
+        LOG.set(Mutex::new(log)).expect("the engine runs once");
+    }
+    let mut engine = Engine::new(Arc::new(evaluation), options);
+    engine.searcher().tablebase = tablebase;
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        write_log("<<", &line);
+        if !engine.command(&line) {
+            break;
+        }
+    }
+    engine.stop();
+}
+
+struct Engine {
+    // Here between searches; a search has it while it runs.
+    searcher: Option<Searcher>,
+    // The search in progress, whose thread hands the searcher back.
+    search: Option<(SearchHandle, JoinHandle<Searcher>)>,
+    game: Game,
+    options: EngineOptions,
+}
+
+impl Engine {
+    fn new(evaluation: Arc<Parameters>, options: EngineOptions) -> Engine {
+        let mut searcher = options.searcher();
+        searcher.set_evaluation(evaluation);
+        searcher.on_info(print_info);
+        Engine { searcher: Some(searcher), search: None, game: Game::new(), options }
+    }
+
+    // Carries out one command; false for `quit`. Unknown commands are
+    // ignored, as the protocol asks.
+    fn command(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                send!("id name {}", NAME);
+                send!("id author {}", AUTHOR);
+                for option in &OPTIONS {
+                    send!("{}", option.uci());
+                }
+                #[cfg(feature = "syzygy")]
+                send!("option name {} type string default <empty>", SYZYGY_PATH);
+                send!("uciok");
+            }
+            Some("isready") => send!("readyok"),
+            Some("setoption") => self.set_option(&words[1..]),
+            Some("ucinewgame") => {
+                self.searcher().new_game();
+                self.game = Game::new();
+            }
+            Some("position") => self.set_position(&words[1..]),
+            Some("go") => self.go(&words[1..]),
+            Some("stop") => self.stop(),
+            Some("ponderhit") => {
+                if let Some((handle, _)) = &self.search {
+                    handle.ponderhit();
+                }
+            }
+            Some("quit") => return false,
+            _ => {}
+        }
+        true
+    }
+
+    // The searcher, after waiting for any search to finish with it.
+    fn searcher(&mut self) -> &mut Searcher {
+        if let Some((_, thread)) = self.search.take() {
+            self.searcher = Some(thread.join().expect("the search thread panicked"));
+        }
+        self.searcher.as_mut().expect("the searcher is back once no search is running")
+    }
+
+    // Ends the search in progress, which still answers with its best move.
+    fn stop(&mut self) {
+        if let Some((handle, _)) = &self.search {
+            handle.stop();
+        }
+        self.searcher();
+    }
+
+    // `name <name> value <value>`, where the name may have spaces in it.
+    fn set_option(&mut self, args: &[&str]) {
+        let ["name", rest @ ..] = args else {
+            send!("info string expected setoption name <name> value <value>");
+            return;
+        };
+        let (name, value) = match rest.iter().position(|&word| word == "value") {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, &[][..]),
+        };
+        #[cfg(feature = "syzygy")]
+        if name.join(" ").eq_ignore_ascii_case(SYZYGY_PATH) {
+            self.set_syzygy_path(&value.join(" "));
+            return;
+        }
+        let old_hash = self.options.hash_mb();
+        if let Err(err) = self.options.set(&name.join(" "), &value.join(" ")) {
+            send!("info string {}", err);
+            return;
+        }
+        self.stop();
+        let options = self.options.clone();
+        let searcher = self.searcher();
+        if options.hash_mb() != old_hash {
+            searcher.set_hash_size(options.hash_mb());
+        }
+        options.configure(searcher);
+    }
+
+    // Probes the tables on `path` from now on, or none for an empty path,
+    // which GUIs send as "<empty>".
+    #[cfg(feature = "syzygy")]
+    fn set_syzygy_path(&mut self, path: &str) {
+        let tablebase: Option<Arc<dyn Tablebase>> = match path.trim() {
+            "" | "<empty>" => None,
+            path => match crate::syzygy::Syzygy::open(path) {
+                Ok(tables) => Some(Arc::new(tables)),
+                Err(err) => {
+                    send!("info string {}: {}", path, err);
+                    return;
+                }
+            },
+        };
+        self.stop();
+        self.searcher().tablebase = tablebase;
+    }
+
+    fn set_position(&mut self, args: &[&str]) {
+        let (setup, moves) = match args.iter().position(|&word| word == "moves") {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &[][..]),
+        };
+        let game = match setup {
+            ["startpos"] => Ok(Game::new()),
+            ["fen", fen @ ..] => Game::from_fen(&fen.join(" ")).map_err(|err| err.to_string()),
+            _ => Err("expected startpos or fen".to_string()),
+        };
+        let mut game = match game {
+            Ok(game) => game,
+            Err(err) => {
+                send!("info string bad position: {}", err);
+                return;
+            }
+        };
+        for text in moves {
+            match game.parse_uci(text) {
+                Ok(mv) => game.play_move(mv),
+                Err(err) => {
+                    send!("info string {}", err);
+                    return;
+                }
+            }
+        }
+        self.game = game;
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.stop();
+        let mut limits = SearchLimits::default();
+        let mut ponder = false;
+        // White's and Black's clocks; some GUIs send negative times once
+        // the flag has fallen.
+        let mut time = [None; 2];
+        let mut increment = [Duration::ZERO; 2];
+        let mut moves_to_go = None;
+        let mut words = args.iter();
+        while let Some(&word) = words.next() {
+            let mut number = || words.next().and_then(|n| n.parse::<i64>().ok()).map(|n| n.max(0) as u64);
+            match word {
+                "wtime" => time[0] = number().map(Duration::from_millis),
+                "btime" => time[1] = number().map(Duration::from_millis),
+                "winc" => increment[0] = number().map_or(Duration::ZERO, Duration::from_millis),
+                "binc" => increment[1] = number().map_or(Duration::ZERO, Duration::from_millis),
+                "movestogo" => moves_to_go = number().map(|n| n as u32),
+                "depth" => limits.depth = number().map(|n| n as u32),
+                "nodes" => limits.nodes = number(),
+                "mate" => limits.mate = number().map(|n| n as u32),
+                "movetime" => limits.move_time = number().map(Duration::from_millis),
+                "infinite" => limits.infinite = true,
+                "ponder" => ponder = true,
+                // `searchmoves` isn't supported: its moves are skipped over
+                // as unknown words.
+                _ => {}
+            }
+        }
+        if let Some(max_depth) = self.options.max_depth() {
+            limits.depth = Some(limits.depth.map_or(max_depth, |depth| depth.min(max_depth)));
+        }
+        let side = if self.game.turn == Color::White { 0 } else { 1 };
+        if let Some(remaining) = time[side] {
+            limits.clock = Some(TimeControl { remaining, increment: increment[side], moves_to_go });
+        }
+
+        // The GUI gives the position with the move to ponder on already
+        // played; the searcher wants the one before and the move.
+        let predicted = self.game.moves.last().copied().filter(|_| ponder).map(|mv| {
+            let mut before = Game::from_fen(&self.game.start).expect("the game was set up from this position");
+            for &earlier in &self.game.moves[..self.game.moves.len() - 1] {
+                before.play_move(earlier);
+            }
+            (before, mv)
+        });
+        let game = self.game.clone();
+        let mut searcher = self.searcher.take().expect("no search is running");
+        let handle = searcher.prepare(predicted.is_some());
+        let thread = thread::spawn(move || {
+            let result = match predicted {
+                Some((before, mv)) => searcher.ponder(&before, mv, &limits),
+                None => searcher.search(&game, &limits),
+            };
+            match (result.best_move, result.ponder_move()) {
+                (Some(best), Some(reply)) => send!("bestmove {} ponder {}", best, reply),
+                (Some(best), None) => send!("bestmove {}", best),
+                // No legal move: the game is over.
+                (None, _) => send!("bestmove 0000"),
+            }
+            searcher
+        });
+        self.search = Some((handle, thread));
+    }
+}
+
+fn send_line(line: &str) {
+    println!("{}", line);
+    write_log(">>", line);
+}
+
+fn write_log(direction: &str, line: &str) {
+    if let Some(log) = LOG.get() {
+        let mut log = log.lock().expect("no thread panics while logging");
+        // A log that can't be written isn't worth stopping the game for.
+        writeln!(log, "{} {} {}", timestamp(), direction, line).and_then(|_| log.flush()).ok();
+    }
+}
+
+// The time of day in UTC to the millisecond, "14:03:27.512".
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = now.as_secs() % 86_400;
+    format!("{:02}:{:02}:{:02}.{:03}", seconds / 3600, seconds / 60 % 60, seconds % 60, now.subsec_millis())
+}
+
+fn print_info(info: &SearchInfo) {
+    let score = match info.mate() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+    send!(
+        "info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        info.multi_pv,
+        score,
+        info.nodes,
+        info.nps,
+        info.hashfull,
+        info.time.as_millis(),
+        pv.join(" ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    // A `stop` sent right after `go infinite`, as GUIs do, used to be lost
+    // to the search thread starting up, leaving the engine searching for
+    // ever and waiting on it.
+    #[test]
+    fn stop_ends_infinite_search() {
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let mut engine = Engine::new(Arc::new(Parameters::default()), EngineOptions::default());
+            for _ in 0..20 {
+                engine.command("position startpos");
+                engine.command("go infinite");
+                engine.command("stop");
+            }
+            done.send(()).ok();
+        });
+        finished.recv_timeout(Duration::from_secs(60)).expect("every stop ends its search");
+    }
+}
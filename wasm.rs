@@ -0,0 +1,87 @@
+// Bindings for running the engine in a browser, built with the `wasm`
+// feature for `wasm32-unknown-unknown` as a `cdylib` and then run through
+// `wasm-bindgen`, which writes the JavaScript glue:
+//
+//     import init, { newGame, legalMoves, makeMove, bestMove, fen } from "./chess.js";
+//     await init();
+//     const game = newGame();          // or newGame("<fen>")
+//     legalMoves(game);                // ["a2a3", "a2a4", ...]
+//     makeMove(game, "e4");            // UCI or SAN; throws if illegal
+//     bestMove(game, 8, 1000);         // depth, milliseconds; "g1f3"
+//     fen(game);
+//
+// Moves come and go as strings in UCI coordinates. The browser's main
+// thread has no threads to spare and no `std::time`, so the search runs on
+// one thread, timed with `web_time`, and blocks the page while it thinks:
+// a page that wants to stay responsive calls it from a Web Worker.
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use crate::san;
+use crate::search::{SearchLimits, Searcher};
+use crate::{Game, Move, ParseMoveError};
+
+// Less than the usual table: browsers give pages little memory.
+const HASH_SIZE_MB: usize = 4;
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+
+// A game and the searcher that plays it, opaque to JavaScript.
+#[wasm_bindgen]
+pub struct ChessGame {
+    game: Game,
+    searcher: Searcher,
+}
+
+// A game from `fen`, or from the usual starting position.
+#[wasm_bindgen(js_name = newGame)]
+pub fn new_game(fen: Option<String>) -> Result<ChessGame, JsError> {
+    let game = match fen {
+        Some(fen) => Game::from_fen(&fen).map_err(|err| JsError::new(&err.to_string()))?,
+        None => Game::new(),
+    };
+    Ok(ChessGame { game, searcher: Searcher::with_hash_size(HASH_SIZE_MB) })
+}
+
+#[wasm_bindgen(js_name = legalMoves)]
+pub fn legal_moves(game: &ChessGame) -> Vec<String> {
+    game.game.legal_moves().iter().map(|mv| mv.to_string()).collect()
+}
+
+// Plays `mv`, in UCI or SAN, and returns it in UCI.
+#[wasm_bindgen(js_name = makeMove)]
+pub fn make_move(game: &mut ChessGame, mv: &str) -> Result<String, JsError> {
+    let mv = parse_move(&game.game, mv)?;
+    game.game.play_move(mv);
+    Ok(mv.to_string())
+}
+
+// The move the engine would play, searching to `depth` plies or for
+// `movetime` milliseconds, whichever comes first, or for a second if
+// neither is given. Undefined once the game is over.
+#[wasm_bindgen(js_name = bestMove)]
+pub fn best_move(game: &mut ChessGame, depth: Option<u32>, movetime: Option<u32>) -> Option<String> {
+    let move_time = movetime.map(|ms| Duration::from_millis(ms as u64)).or(if depth.is_none() { Some(DEFAULT_MOVE_TIME) } else { None });
+    let limits = SearchLimits { depth, move_time, ..SearchLimits::default() };
+    game.searcher.search(&game.game, &limits).best_move.map(|mv| mv.to_string())
+}
+
+#[wasm_bindgen]
+pub fn fen(game: &ChessGame) -> String {
+    game.game.to_fen()
+}
+
+// "1-0", "0-1" or "1/2-1/2" once the game is over.
+#[wasm_bindgen]
+pub fn result(game: &ChessGame) -> Option<String> {
+    game.game.outcome().map(|outcome| outcome.result.to_string())
+}
+
+fn parse_move(game: &Game, text: &str) -> Result<Move, JsError> {
+    match game.parse_uci(text) {
+        Ok(mv) => Ok(mv),
+        Err(ParseMoveError::Invalid(_)) => san::parse(game, text).map_err(|err| JsError::new(&err.to_string())),
+        Err(err) => Err(JsError::new(&err.to_string())),
+    }
+}
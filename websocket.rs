@@ -0,0 +1,264 @@
+// A WebSocket server for browser frontends, built with the `server`
+// feature, which brings in `tungstenite` for the protocol and
+// `serde_json`. A web page opens a socket and drives games over it with
+// JSON messages, each an object with a "type"; an "id" in a request comes
+// back in the replies to it, for matching them up.
+//
+//     {"type": "new_game", "fen": "<fen>"}     fen optional
+//         -> {"type": "game", "game": 1, "fen": ..., "turn": "white",
+//             "moves": [...], "legal_moves": [...], "check": false,
+//             "outcome": null}
+//     {"type": "get_game", "game": 1}          -> the same
+//     {"type": "move", "game": 1, "move": "e2e4"}
+//                                              in UCI or SAN -> the same
+//     {"type": "legal_moves", "game": 1, "from": "g1"}
+//                                              from optional
+//         -> {"type": "legal_moves", "game": 1,
+//             "moves": [{"uci": "g1f3", "san": "Nf3", "from": "g1", "to": "f3"}, ...]}
+//     {"type": "analyze", "game": 1, "depth": 12, "movetime": 2000,
+//      "multipv": 3}                           limits optional; without
+//                                              any, until "stop", and
+//                                              otherwise held to those
+//                                              of `api::search_limits`
+//         -> {"type": "info", "game": 1, "depth": ..., "seldepth": ...,
+//             "multipv": 1, "score": {"cp": 31} or {"mate": 3},
+//             "nodes": ..., "nps": ..., "time": <ms>, "pv": [...]},
+//            one for each line of each iteration, as the search goes, then
+//            {"type": "bestmove", "game": 1, "move": "e2e4", "ponder": "e7e5"}
+//     {"type": "stop"}                         ends the analysis early
+//
+// Anything wrong with a request is answered with
+// {"type": "error", "message": "..."}. Each connection has games of its
+// own, numbered from 1, and one search at a time, which runs on a thread
+// of its own while requests keep being answered.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tungstenite::{Message, WebSocket};
+
+use crate::api;
+use crate::eval::Parameters;
+use crate::options::EngineOptions;
+use crate::search::{SearchHandle, SearchInfo, SearchLimits, Searcher};
+use crate::{Game, Square};
+
+pub const DEFAULT_PORT: u16 = 8765;
+// How often a connection looks for search output between requests.
+const POLL: Duration = Duration::from_millis(20);
+
+// Accepts connections on `port` for ever, serving each on a thread with a
+// searcher set up by `options`.
+pub fn serve(port: u16, evaluation: Parameters, options: EngineOptions) -> io::Result<()> {
+    let evaluation = Arc::new(evaluation);
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Listening for WebSocket connections on port {}", port);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let (evaluation, options) = (Arc::clone(&evaluation), options.clone());
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map_or("?".to_string(), |address| address.to_string());
+            if let Err(err) = Connection::serve(stream, evaluation, &options) {
+                eprintln!("{}: {}", peer, err);
+            }
+        });
+    }
+    Ok(())
+}
+
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    games: HashMap<u64, Game>,
+    next_id: u64,
+    // Here between searches; a search has it while it runs.
+    searcher: Option<Searcher>,
+    search: Option<(SearchHandle, JoinHandle<Searcher>)>,
+    // What searches have to say, to be sent on.
+    replies: Receiver<Value>,
+    sender: Sender<Value>,
+}
+
+impl Connection {
+    fn serve(stream: TcpStream, evaluation: Arc<Parameters>, options: &EngineOptions) -> Result<(), tungstenite::Error> {
+        let socket = tungstenite::accept(stream).map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => err,
+            tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+        })?;
+        // Short reads let search output through while the page is quiet.
+        socket.get_ref().set_read_timeout(Some(POLL))?;
+        let mut searcher = options.searcher();
+        searcher.set_evaluation(evaluation);
+        let (sender, replies) = mpsc::channel();
+        let mut connection =
+            Connection { socket, games: HashMap::new(), next_id: 1, searcher: Some(searcher), search: None, replies, sender };
+        let result = connection.run();
+        connection.stop();
+        result
+    }
+
+    fn run(&mut self) -> Result<(), tungstenite::Error> {
+        loop {
+            while let Ok(reply) = self.replies.try_recv() {
+                self.socket.send(Message::Text(reply.to_string()))?;
+            }
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    let request: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+                    let reply = self.request(&request).unwrap_or_else(|message| json!({"type": "error", "message": message}));
+                    let reply = tagged(reply, &request.get("id").cloned());
+                    if !reply.is_null() {
+                        self.socket.send(Message::Text(reply.to_string()))?;
+                    }
+                }
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                // Pings are answered by tungstenite itself.
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err)) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // The reply to a request, or null if there is none yet.
+    fn request(&mut self, request: &Value) -> Result<Value, String> {
+        match request["type"].as_str() {
+            Some("new_game") => {
+                let game = match request["fen"].as_str() {
+                    Some(fen) => Game::from_fen(fen).map_err(|err| err.to_string())?,
+                    None => Game::new(),
+                };
+                let id = self.next_id;
+                self.next_id += 1;
+                let reply = game_state(id, &game);
+                self.games.insert(id, game);
+                Ok(reply)
+            }
+            Some("get_game") => {
+                let (id, game) = self.game(request)?;
+                Ok(game_state(id, game))
+            }
+            Some("move") => {
+                let text = request["move"].as_str().ok_or("no move")?.to_string();
+                let (id, game) = self.game(request)?;
+                if game.outcome().is_some() {
+                    return Err("the game is over".to_string());
+                }
+                let mv = api::parse_move(game, &text)?;
+                let game = self.games.get_mut(&id).expect("the game was just found");
+                game.play_move(mv);
+                Ok(game_state(id, game))
+            }
+            Some("legal_moves") => {
+                let (id, game) = self.game(request)?;
+                let from = match request["from"].as_str() {
+                    Some(square) => Some(square.parse::<Square>().map_err(|err| err.to_string())?),
+                    None => None,
+                };
+                let moves: Vec<Value> = game
+                    .legal_moves()
+                    .iter()
+                    .filter(|mv| from.is_none_or(|from| mv.from == from))
+                    .map(|&mv| api::legal_move(game, mv))
+                    .collect();
+                Ok(json!({"type": "legal_moves", "game": id, "moves": moves}))
+            }
+            Some("analyze") => {
+                let (id, game) = self.game(request)?;
+                let game = game.clone();
+                let number = |field: &str| request[field].as_u64();
+                let (limits, multi_pv) = api::search_limits(number("depth"), number("nodes"), number("movetime"), number("multipv"));
+                // Unlike the other servers', a search here can be stopped,
+                // so one with no limits runs until it is.
+                let unlimited = ["depth", "nodes", "movetime"].iter().all(|field| number(field).is_none());
+                let limits = if unlimited { SearchLimits { infinite: true, ..SearchLimits::default() } } else { limits };
+                self.analyze(id, game, limits, multi_pv, request.get("id").cloned());
+                Ok(Value::Null)
+            }
+            Some("stop") => {
+                self.stop();
+                Ok(Value::Null)
+            }
+            Some(other) => Err(format!("unknown request type {:?}", other)),
+            None => Err("expected a JSON object with a type".to_string()),
+        }
+    }
+
+    fn game(&self, request: &Value) -> Result<(u64, &Game), String> {
+        let id = request["game"].as_u64().ok_or("no game")?;
+        let game = self.games.get(&id).ok_or_else(|| format!("no game {}", id))?;
+        Ok((id, game))
+    }
+
+    // Starts a search of `game`, stopping any before it, whose output is
+    // sent back as it comes, tagged with `request`'s id.
+    fn analyze(&mut self, id: u64, game: Game, limits: SearchLimits, multi_pv: usize, request: Option<Value>) {
+        self.stop();
+        let mut searcher = self.searcher.take().expect("no search is running");
+        searcher.multi_pv = multi_pv;
+        let (sender, tag) = (self.sender.clone(), request.clone());
+        searcher.on_info(move |info| {
+            sender.send(tagged(info_message(id, info), &tag)).ok();
+        });
+        let handle = searcher.prepare(false);
+        let sender = self.sender.clone();
+        let thread = thread::spawn(move || {
+            let result = searcher.search(&game, &limits);
+            let bestmove = json!({
+                "type": "bestmove",
+                "game": id,
+                "move": result.best_move.map(|mv| mv.to_string()),
+                "ponder": result.ponder_move().map(|mv| mv.to_string()),
+            });
+            sender.send(tagged(bestmove, &request)).ok();
+            searcher
+        });
+        self.search = Some((handle, thread));
+    }
+
+    // Ends the search in progress, if any, and takes the searcher back.
+    fn stop(&mut self) {
+        if let Some((handle, thread)) = self.search.take() {
+            handle.stop();
+            self.searcher = Some(thread.join().expect("the search thread panicked"));
+        }
+    }
+}
+
+// `message` with the id of the request it answers, if that had one.
+fn tagged(mut message: Value, id: &Option<Value>) -> Value {
+    if let (Some(id), Some(object)) = (id, message.as_object_mut()) {
+        object.insert("id".to_string(), id.clone());
+    }
+    message
+}
+
+fn info_message(id: u64, info: &SearchInfo) -> Value {
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+    json!({
+        "type": "info",
+        "game": id,
+        "depth": info.depth,
+        "seldepth": info.seldepth,
+        "multipv": info.multi_pv,
+        "score": api::score(info.score),
+        "nodes": info.nodes,
+        "nps": info.nps,
+        "time": info.time.as_millis() as u64,
+        "pv": pv,
+    })
+}
+
+// A game's state as the reply to a request; see `api::game`.
+fn game_state(id: u64, game: &Game) -> Value {
+    let mut state = api::game(id, game);
+    if let Some(object) = state.as_object_mut() {
+        object.insert("type".to_string(), json!("game"));
+    }
+    state
+}
@@ -0,0 +1,79 @@
+// Zobrist keys: a random number for each feature of a position, so that a
+// position's hash is the XOR of the keys of its features and can be kept up
+// to date move by move.
+
+use crate::{CastlingRights, ChessPiece, Color, File, Square};
+
+struct Keys {
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    // White kingside, white queenside, black kingside, black queenside.
+    castling: [u64; 4],
+    en_passant_file: [u64; 8],
+}
+
+static KEYS: Keys = generate();
+
+pub fn piece(piece: ChessPiece, square: Square) -> u64 {
+    KEYS.pieces[piece.color.index()][piece.piece.index()][square.0 as usize]
+}
+
+pub fn side_to_move(color: Color) -> u64 {
+    match color {
+        Color::White => 0,
+        Color::Black => KEYS.black_to_move,
+    }
+}
+
+pub fn castling(rights: CastlingRights) -> u64 {
+    let held = [rights.white_kingside, rights.white_queenside, rights.black_kingside, rights.black_queenside];
+    held.iter().zip(KEYS.castling).filter(|(&held, _)| held).fold(0, |key, (_, right)| key ^ right)
+}
+
+pub fn en_passant(file: File) -> u64 {
+    KEYS.en_passant_file[file.0 as usize]
+}
+
+// splitmix64 from a fixed seed, so hashes are the same on every run.
+const fn next(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn generate() -> Keys {
+    let mut state = 0x5eed;
+    let mut keys = Keys { pieces: [[[0; 64]; 6]; 2], black_to_move: 0, castling: [0; 4], en_passant_file: [0; 8] };
+
+    let mut color = 0;
+    while color < 2 {
+        let mut piece = 0;
+        while piece < 6 {
+            let mut square = 0;
+            while square < 64 {
+                keys.pieces[color][piece][square] = next(&mut state);
+                square += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+
+    keys.black_to_move = next(&mut state);
+
+    let mut i = 0;
+    while i < 4 {
+        keys.castling[i] = next(&mut state);
+        i += 1;
+    }
+
+    let mut file = 0;
+    while file < 8 {
+        keys.en_passant_file[file] = next(&mut state);
+        file += 1;
+    }
+
+    keys
+}